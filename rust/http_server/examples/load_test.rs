@@ -0,0 +1,113 @@
+// Compare the backends with the keep-alive clients and the idle connections.
+// cargo run --release --example load_test -- [clients] [seconds] [idle connections]
+extern crate http_server;
+
+use http_server::response::Response;
+use http_server::router::{Context, Router};
+use http_server::server::{Backend, Server, ServerConfig};
+use std::env;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Send the requests on one connection until the deadline, and return the number of the successful responses.
+fn client(addr: SocketAddr, deadline: Instant) -> usize {
+    let stream = TcpStream::connect(addr).unwrap();
+    stream.set_nodelay(true).unwrap();
+    let mut w = stream.try_clone().unwrap();
+    let mut r = BufReader::new(stream);
+    let mut count = 0;
+    let mut line = String::new();
+    while Instant::now() < deadline {
+        w.write_all(b"GET /hello HTTP/1.1\r\nHost: bench\r\n\r\n")
+            .unwrap();
+
+        let mut length = 0;
+        let mut is_ok = false;
+        loop {
+            line.clear();
+            if r.read_line(&mut line).unwrap() == 0 {
+                return count;
+            }
+            if line == "\r\n" {
+                break;
+            }
+            if line.starts_with("HTTP/1.1 200 ") {
+                is_ok = true;
+            }
+            if let Some(v) = line.strip_prefix("Content-Length: ") {
+                length = v.trim().parse().unwrap();
+            }
+        }
+        r.by_ref()
+            .take(length)
+            .read_to_end(&mut Vec::new())
+            .unwrap();
+        if is_ok {
+            count += 1;
+        }
+    }
+    count
+}
+
+fn bench(backend: Backend, clients: usize, seconds: u64, idle: usize) {
+    let router = Router::new().get("/hello", |_: &Context| Response::ok().with_bytes("hello\n"));
+    let config = ServerConfig {
+        backend,
+        workers: clients,
+        queue_size: clients + idle,
+        max_connections: clients + idle + 16,
+        ..Default::default()
+    };
+    let server = Server::bind("127.0.0.1:0", router, config).unwrap();
+    let addr = server.local_addr().unwrap();
+    let stop = server.stop_handle();
+    let server = thread::spawn(move || server.run().unwrap());
+
+    // They occupy the workers of the thread backend until the idle timeout.
+    let idle: Vec<TcpStream> = (0..idle)
+        .map(|_| TcpStream::connect(addr).unwrap())
+        .collect();
+
+    let started = Instant::now();
+    let deadline = started + Duration::from_secs(seconds);
+    let total: usize = (0..clients)
+        .map(|_| thread::spawn(move || client(addr, deadline)))
+        .collect::<Vec<_>>()
+        .into_iter()
+        .map(|h| h.join().unwrap())
+        .sum();
+    let elapsed = started.elapsed();
+
+    println!(
+        "{:8} {:8} {:10} {:12.0}",
+        backend.name(),
+        idle.len(),
+        total,
+        total as f64 / (elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) * 1e-9)
+    );
+
+    drop(idle);
+    stop.store(true, Ordering::SeqCst);
+    server.join().unwrap();
+}
+
+fn main() {
+    let args: Vec<usize> = env::args()
+        .skip(1)
+        .map(|a| a.parse().expect("the arguments have to be numbers"))
+        .collect();
+    let clients = args.first().cloned().unwrap_or(8);
+    let seconds = args.get(1).cloned().unwrap_or(3) as u64;
+    let idle = args.get(2).cloned().unwrap_or(0);
+
+    println!(
+        "{:8} {:>8} {:>10} {:>12}",
+        "backend", "idle", "requests", "requests/s"
+    );
+    for &backend in &[Backend::Threads, Backend::Epoll] {
+        bench(backend, clients, seconds, idle);
+    }
+}
@@ -0,0 +1,212 @@
+use date::{format_clf_date, format_rfc3339};
+use log_file::LogFile;
+use std::io;
+use std::net::SocketAddr;
+use std::time::{Duration, SystemTime};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    Off,
+    // host ident user [time] "request" status bytes
+    Common,
+    // Common and "referer" "user-agent".
+    Combined,
+    // An object per line with the latency and the peer port too.
+    Json,
+}
+
+impl LogFormat {
+    pub fn from_name(name: &str) -> Option<LogFormat> {
+        match name {
+            "off" => Some(LogFormat::Off),
+            "common" => Some(LogFormat::Common),
+            "combined" => Some(LogFormat::Combined),
+            "json" => Some(LogFormat::Json),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            LogFormat::Off => "off",
+            LogFormat::Common => "common",
+            LogFormat::Combined => "combined",
+            LogFormat::Json => "json",
+        }
+    }
+}
+
+// An answered request.
+#[derive(Debug, Clone)]
+pub struct Entry<'a> {
+    // None for the unix socket.
+    pub peer: Option<SocketAddr>,
+    pub time: SystemTime,
+    // The request line as it is received, `-` if it is broken.
+    pub request_line: &'a str,
+    pub status: u16,
+    pub body_bytes: u64,
+    pub referer: Option<&'a str>,
+    pub user_agent: Option<&'a str>,
+    // From the first byte of the request until the last byte of the response is written.
+    pub latency: Duration,
+}
+
+// The quoted string of the log, the quotes and the control characters are escaped.
+fn quote(s: &str) -> String {
+    let mut q = String::with_capacity(s.len() + 2);
+    q.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => q.push_str("\\\""),
+            '\\' => q.push_str("\\\\"),
+            c if c.is_control() => q.push_str(&format!("\\x{:02x}", c as u32)),
+            c => q.push(c),
+        }
+    }
+    q.push('"');
+    q
+}
+
+// The JSON string, the control characters are escaped as \uXXXX.
+fn json_string(s: &str) -> String {
+    let mut q = String::with_capacity(s.len() + 2);
+    q.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => q.push_str("\\\""),
+            '\\' => q.push_str("\\\\"),
+            '\n' => q.push_str("\\n"),
+            '\r' => q.push_str("\\r"),
+            '\t' => q.push_str("\\t"),
+            c if c.is_control() => q.push_str(&format!("\\u{:04x}", c as u32)),
+            c => q.push(c),
+        }
+    }
+    q.push('"');
+    q
+}
+
+impl<'a> Entry<'a> {
+    fn to_json(&self) -> String {
+        let opt = |s: Option<&str>| s.map_or("null".to_string(), json_string);
+        let peer = self.peer.map(|p| p.to_string());
+        format!(
+            "{{\"time\":\"{}\",\"peer\":{},\"request\":{},\"status\":{},\"bytes\":{},\"latency_us\":{},\"referer\":{},\"user_agent\":{}}}",
+            format_rfc3339(self.time),
+            opt(peer.as_deref()),
+            json_string(self.request_line),
+            self.status,
+            self.body_bytes,
+            self.latency.as_micros(),
+            opt(self.referer),
+            opt(self.user_agent)
+        )
+    }
+
+    fn to_common(&self) -> String {
+        let host = self.peer.map_or("-".to_string(), |p| p.ip().to_string());
+        let bytes = if self.body_bytes == 0 {
+            "-".to_string()
+        } else {
+            self.body_bytes.to_string()
+        };
+        format!(
+            "{} - - [{}] {} {} {}",
+            host,
+            format_clf_date(self.time),
+            quote(self.request_line),
+            self.status,
+            bytes
+        )
+    }
+
+    pub fn format(&self, format: LogFormat) -> Option<String> {
+        match format {
+            LogFormat::Off => None,
+            LogFormat::Common => Some(self.to_common()),
+            LogFormat::Combined => Some(format!(
+                "{} {} {}",
+                self.to_common(),
+                quote(self.referer.unwrap_or("-")),
+                quote(self.user_agent.unwrap_or("-"))
+            )),
+            LogFormat::Json => Some(self.to_json()),
+        }
+    }
+}
+
+pub struct AccessLog {
+    format: LogFormat,
+    file: LogFile,
+}
+
+impl AccessLog {
+    pub fn new(format: LogFormat, file: LogFile) -> AccessLog {
+        AccessLog { format, file }
+    }
+
+    pub fn stdout(format: LogFormat) -> AccessLog {
+        AccessLog::new(format, LogFile::new(io::stdout()))
+    }
+
+    pub fn off() -> AccessLog {
+        AccessLog::new(LogFormat::Off, LogFile::new(io::sink()))
+    }
+
+    pub fn log(&self, e: &Entry) {
+        if let Some(line) = e.format(self.format) {
+            self.file.write_line(&line);
+        }
+    }
+
+    pub fn reopen(&self) -> io::Result<()> {
+        self.file.reopen()
+    }
+}
+
+#[test]
+fn access_log_format() {
+    use std::time::UNIX_EPOCH;
+
+    let e = Entry {
+        peer: "127.0.0.1:4000".parse().ok(),
+        time: UNIX_EPOCH + Duration::from_secs(784_111_777),
+        request_line: "GET /a?b HTTP/1.1",
+        status: 200,
+        body_bytes: 12,
+        referer: None,
+        user_agent: Some("curl/8 \"x\""),
+        latency: Duration::from_micros(1500),
+    };
+    assert_eq!(
+        e.format(LogFormat::Common).unwrap(),
+        "127.0.0.1 - - [06/Nov/1994:08:49:37 +0000] \"GET /a?b HTTP/1.1\" 200 12"
+    );
+    assert_eq!(
+        e.format(LogFormat::Combined).unwrap(),
+        "127.0.0.1 - - [06/Nov/1994:08:49:37 +0000] \"GET /a?b HTTP/1.1\" 200 12 \"-\" \"curl/8 \\\"x\\\"\""
+    );
+    assert_eq!(e.format(LogFormat::Off), None);
+    assert_eq!(
+        e.format(LogFormat::Json).unwrap(),
+        "{\"time\":\"1994-11-06T08:49:37Z\",\"peer\":\"127.0.0.1:4000\",\"request\":\"GET /a?b HTTP/1.1\",\
+         \"status\":200,\"bytes\":12,\"latency_us\":1500,\"referer\":null,\"user_agent\":\"curl/8 \\\"x\\\"\"}"
+    );
+
+    let e = Entry {
+        peer: None,
+        request_line: "\x01",
+        body_bytes: 0,
+        ..e
+    };
+    assert_eq!(
+        e.format(LogFormat::Common).unwrap(),
+        "- - - [06/Nov/1994:08:49:37 +0000] \"\\x01\" 200 -"
+    );
+    assert_eq!(
+        e.format(LogFormat::Json).unwrap(),
+        "{\"time\":\"1994-11-06T08:49:37Z\",\"peer\":null,\"request\":\"\\u0001\",\
+         \"status\":200,\"bytes\":0,\"latency_us\":1500,\"referer\":null,\"user_agent\":\"curl/8 \\\"x\\\"\"}"
+    );
+}
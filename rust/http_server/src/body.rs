@@ -0,0 +1,486 @@
+use parser::{
+    next_line, parse_header_fields, Headers, Limits, ParseError, ParseResult, Request, Version,
+};
+use std::borrow::Cow;
+use std::io;
+use std::io::Read;
+
+// The chunk-size line is short, a longer one is an attack or garbage.
+const MAX_CHUNK_SIZE_LINE: usize = 1024;
+
+// How the length of the request body is determined (RFC 7230 section 3.3.3).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BodyKind {
+    Empty,
+    Length(usize),
+    Chunked,
+}
+
+impl BodyKind {
+    pub fn of(req: &Request, limits: &Limits) -> Result<BodyKind, ParseError> {
+        let headers = &req.headers;
+
+        if headers.contains("Transfer-Encoding") {
+            // Both of them is a sign of request smuggling.
+            if headers.contains("Content-Length") || req.version != Version::Http11 {
+                return Err(ParseError::BadRequest);
+            }
+            let codings = headers
+                .get_all("Transfer-Encoding")
+                .flat_map(|v| v.split(','))
+                .map(|c| c.trim())
+                .collect::<Vec<_>>();
+            return match codings.last() {
+                Some(c) if !c.eq_ignore_ascii_case("chunked") => Err(ParseError::BadRequest),
+                // The other codings like gzip are not supported.
+                _ if codings.len() != 1 => Err(ParseError::NotImplemented),
+                _ => Ok(BodyKind::Chunked),
+            };
+        }
+
+        let mut length = None;
+        for v in headers.get_all("Content-Length").flat_map(|v| v.split(',')) {
+            let v = v.trim();
+            let n = match v.parse::<usize>() {
+                Ok(n) if v.bytes().all(|b| b.is_ascii_digit()) => n,
+                _ => return Err(ParseError::BadRequest),
+            };
+            // The repeated values have to be the same.
+            if length.is_some_and(|l| l != n) {
+                return Err(ParseError::BadRequest);
+            }
+            length = Some(n);
+        }
+
+        match length {
+            Some(n) if limits.max_body < n => Err(ParseError::PayloadTooLarge),
+            Some(0) | None => Ok(BodyKind::Empty),
+            Some(n) => Ok(BodyKind::Length(n)),
+        }
+    }
+}
+
+// Parse the chunk-size line, the chunk extensions are ignored.
+// It returns the size and the length of the line.
+pub fn parse_chunk_size(buf: &[u8]) -> ParseResult<(usize, usize)> {
+    use parser::ParseResult::*;
+
+    let (line, next) = match next_line(buf, 0) {
+        Some(l) if l.0.len() <= MAX_CHUNK_SIZE_LINE => l,
+        None if buf.len() <= MAX_CHUNK_SIZE_LINE => return Partial,
+        _ => return Error(ParseError::BadRequest),
+    };
+
+    let digits = line.iter().take_while(|b| b.is_ascii_hexdigit()).count();
+    let ext = line[digits..].iter().find(|&&b| b != b' ' && b != b'\t');
+    if digits == 0 || ext.is_some_and(|&b| b != b';') {
+        return Error(ParseError::BadRequest);
+    }
+
+    // The digits are ASCII, so from_utf8 never fails.
+    match usize::from_str_radix(::std::str::from_utf8(&line[..digits]).unwrap(), 16) {
+        Ok(size) => Complete((size, next)),
+        Err(_) => Error(ParseError::PayloadTooLarge),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Chunk<'a> {
+    Data(&'a [u8]),
+    // The last chunk has the trailer fields.
+    Last(Headers<'a>),
+}
+
+// Parse one chunk at the beginning of buf.
+// It returns the chunk and the number of the consumed bytes.
+pub fn parse_chunk<'a>(buf: &'a [u8], limits: &Limits) -> ParseResult<(Chunk<'a>, usize)> {
+    use parser::ParseResult::*;
+
+    let (size, pos) = match parse_chunk_size(buf) {
+        Complete(s) => s,
+        Partial => return Partial,
+        Error(e) => return Error(e),
+    };
+    if limits.max_body < size {
+        return Error(ParseError::PayloadTooLarge);
+    }
+
+    if size == 0 {
+        return match parse_header_fields(buf, pos, pos, limits) {
+            Complete((trailers, end)) => Complete((Chunk::Last(trailers), end)),
+            Partial => Partial,
+            Error(e) => Error(e),
+        };
+    }
+
+    let end = pos + size;
+    if buf.len() < end {
+        return Partial;
+    }
+    match end_of_data(&buf[end..]) {
+        Complete(n) => Complete((Chunk::Data(&buf[pos..end]), end + n)),
+        Partial => Partial,
+        Error(e) => Error(e),
+    }
+}
+
+// The data of a chunk is followed by CRLF.
+fn end_of_data(buf: &[u8]) -> ParseResult<usize> {
+    match buf {
+        [b'\r', b'\n', ..] => ParseResult::Complete(2),
+        [b'\n', ..] => ParseResult::Complete(1),
+        [] | [b'\r'] => ParseResult::Partial,
+        _ => ParseResult::Error(ParseError::BadRequest),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Body<'a> {
+    // It is borrowed from the buffer unless it is chunked.
+    pub data: Cow<'a, [u8]>,
+    pub trailers: Headers<'a>,
+    // The number of bytes of the body in the buffer.
+    pub length: usize,
+}
+
+// Parse the whole body at the beginning of buf, which follows the head of the request.
+// It is Partial until all of the body comes like parser::parse.
+pub fn parse_body<'a>(buf: &'a [u8], kind: BodyKind, limits: &Limits) -> ParseResult<Body<'a>> {
+    use parser::ParseResult::*;
+
+    match kind {
+        BodyKind::Empty => Complete(Body {
+            data: Cow::Borrowed(&[]),
+            trailers: Headers::new(),
+            length: 0,
+        }),
+        BodyKind::Length(n) if limits.max_body < n => Error(ParseError::PayloadTooLarge),
+        BodyKind::Length(n) if buf.len() < n => Partial,
+        BodyKind::Length(n) => Complete(Body {
+            data: Cow::Borrowed(&buf[..n]),
+            trailers: Headers::new(),
+            length: n,
+        }),
+        BodyKind::Chunked => {
+            let mut data = Vec::new();
+            let mut pos = 0;
+            loop {
+                match parse_chunk(&buf[pos..], limits) {
+                    Complete((Chunk::Data(d), n)) => {
+                        if limits.max_body < data.len() + d.len() {
+                            return Error(ParseError::PayloadTooLarge);
+                        }
+                        data.extend_from_slice(d);
+                        pos += n;
+                    }
+                    Complete((Chunk::Last(trailers), n)) => {
+                        return Complete(Body {
+                            data: Cow::Owned(data),
+                            trailers,
+                            length: pos + n,
+                        })
+                    }
+                    Partial => return Partial,
+                    Error(e) => return Error(e),
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ReaderState {
+    // The remaining bytes of the body or the current chunk.
+    Data(usize),
+    ChunkEnd,
+    ChunkSize,
+    Trailers,
+    Done,
+}
+
+// Stream the body without buffering all of it.
+// The bytes after the head which have already been received are given as the leftover.
+pub struct BodyReader<R> {
+    inner: R,
+    buf: Vec<u8>,
+    is_chunked: bool,
+    state: ReaderState,
+    total: usize,
+    limits: Limits,
+    trailers: Vec<(String, String)>,
+}
+
+fn invalid_body(e: ParseError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", e))
+}
+
+impl<R: Read> BodyReader<R> {
+    pub fn new(inner: R, leftover: Vec<u8>, kind: BodyKind, limits: Limits) -> BodyReader<R> {
+        let state = match kind {
+            BodyKind::Empty => ReaderState::Done,
+            BodyKind::Length(n) => ReaderState::Data(n),
+            BodyKind::Chunked => ReaderState::ChunkSize,
+        };
+
+        BodyReader {
+            inner,
+            buf: leftover,
+            is_chunked: kind == BodyKind::Chunked,
+            state,
+            total: 0,
+            limits,
+            trailers: Vec::new(),
+        }
+    }
+
+    // The trailers are available after the whole body has been read.
+    pub fn trailers(&self) -> &[(String, String)] {
+        &self.trailers
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.state == ReaderState::Done
+    }
+
+    // The stream and the bytes after the body, which are the beginning of the next request.
+    pub fn into_inner(self) -> (R, Vec<u8>) {
+        (self.inner, self.buf)
+    }
+
+    fn fill(&mut self) -> io::Result<()> {
+        let mut b = [0; 4096];
+        let n = self.inner.read(&mut b)?;
+        if n == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "the body is truncated",
+            ));
+        }
+        self.buf.extend_from_slice(&b[..n]);
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for BodyReader<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        use parser::ParseResult::*;
+
+        loop {
+            match self.state {
+                ReaderState::Done => return Ok(0),
+                ReaderState::Data(0) if self.is_chunked => self.state = ReaderState::ChunkEnd,
+                ReaderState::Data(0) => self.state = ReaderState::Done,
+                ReaderState::Data(n) => {
+                    if self.buf.is_empty() {
+                        self.fill()?;
+                    }
+                    let k = n.min(out.len()).min(self.buf.len());
+                    out[..k].copy_from_slice(&self.buf[..k]);
+                    self.buf.drain(..k);
+                    self.state = ReaderState::Data(n - k);
+                    return Ok(k);
+                }
+                ReaderState::ChunkEnd => match end_of_data(&self.buf) {
+                    Complete(n) => {
+                        self.buf.drain(..n);
+                        self.state = ReaderState::ChunkSize;
+                    }
+                    Partial => self.fill()?,
+                    Error(e) => return Err(invalid_body(e)),
+                },
+                ReaderState::ChunkSize => match parse_chunk_size(&self.buf) {
+                    Complete((0, n)) => {
+                        self.buf.drain(..n);
+                        self.state = ReaderState::Trailers;
+                    }
+                    Complete((size, n)) => {
+                        self.total = self.total.saturating_add(size);
+                        if self.limits.max_body < self.total {
+                            return Err(invalid_body(ParseError::PayloadTooLarge));
+                        }
+                        self.buf.drain(..n);
+                        self.state = ReaderState::Data(size);
+                    }
+                    Partial => self.fill()?,
+                    Error(e) => return Err(invalid_body(e)),
+                },
+                ReaderState::Trailers => {
+                    let end = match parse_header_fields(&self.buf, 0, 0, &self.limits) {
+                        Complete((trailers, end)) => {
+                            self.trailers = trailers
+                                .iter()
+                                .map(|(k, v)| (k.to_string(), v.to_string()))
+                                .collect();
+                            end
+                        }
+                        Partial => {
+                            self.fill()?;
+                            continue;
+                        }
+                        Error(e) => return Err(invalid_body(e)),
+                    };
+                    self.buf.drain(..end);
+                    self.state = ReaderState::Done;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+fn kind_of(buf: &[u8]) -> Result<BodyKind, ParseError> {
+    match ::parser::parse(buf) {
+        ParseResult::Complete(req) => BodyKind::of(&req, &Limits::default()),
+        r => panic!("{:?}", r),
+    }
+}
+
+#[test]
+fn body_kind() {
+    assert_eq!(
+        kind_of(b"GET / HTTP/1.1\r\nHost: a\r\n\r\n"),
+        Ok(BodyKind::Empty)
+    );
+    assert_eq!(
+        kind_of(b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 12\r\n\r\n"),
+        Ok(BodyKind::Length(12))
+    );
+    assert_eq!(
+        kind_of(b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 3, 3\r\n\r\n"),
+        Ok(BodyKind::Length(3))
+    );
+    assert_eq!(
+        kind_of(b"POST / HTTP/1.1\r\nHost: a\r\ntransfer-encoding: Chunked\r\n\r\n"),
+        Ok(BodyKind::Chunked)
+    );
+
+    for buf in &[
+        &b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 3\r\nContent-Length: 4\r\n\r\n"[..],
+        b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: +3\r\n\r\n",
+        b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 3\r\nTransfer-Encoding: chunked\r\n\r\n",
+        b"POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked, gzip\r\n\r\n",
+        b"POST / HTTP/1.0\r\nTransfer-Encoding: chunked\r\n\r\n",
+    ] {
+        assert_eq!(kind_of(buf), Err(ParseError::BadRequest));
+    }
+    assert_eq!(
+        kind_of(b"POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: gzip, chunked\r\n\r\n"),
+        Err(ParseError::NotImplemented)
+    );
+    assert_eq!(
+        kind_of(b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 999999999999\r\n\r\n"),
+        Err(ParseError::PayloadTooLarge)
+    );
+}
+
+#[test]
+fn body_content_length() {
+    let limits = Limits::default();
+    assert!(parse_body(b"abc", BodyKind::Length(4), &limits).is_partial());
+    let body = match parse_body(b"abcdGET", BodyKind::Length(4), &limits) {
+        ParseResult::Complete(b) => b,
+        r => panic!("{:?}", r),
+    };
+    assert_eq!(&body.data[..], b"abcd");
+    assert_eq!(body.length, 4);
+}
+
+#[test]
+fn body_chunked() {
+    let buf =
+        b"4;ext=1\r\nWiki\r\n5\r\npedia\r\nE\r\n in\r\n\r\nchunks.\r\n0\r\nX-Sum: 1\r\n\r\nnext";
+    let limits = Limits::default();
+    for i in 0..(buf.len() - 4) {
+        assert!(
+            parse_body(&buf[..i], BodyKind::Chunked, &limits).is_partial(),
+            "{}",
+            i
+        );
+    }
+
+    let body = match parse_body(buf, BodyKind::Chunked, &limits) {
+        ParseResult::Complete(b) => b,
+        r => panic!("{:?}", r),
+    };
+    assert_eq!(&body.data[..], &b"Wikipedia in\r\n\r\nchunks."[..]);
+    assert_eq!(body.trailers.get("x-sum"), Some("1"));
+    assert_eq!(&buf[body.length..], b"next");
+
+    let limits = Limits {
+        max_body: 8,
+        ..Default::default()
+    };
+    assert_eq!(
+        parse_body(buf, BodyKind::Chunked, &limits),
+        ParseResult::Error(ParseError::PayloadTooLarge)
+    );
+    for buf in &[&b"x\r\n"[..], b"4\r\nWikiX\r\n", b"4 x\r\n"] {
+        assert_eq!(
+            parse_body(buf, BodyKind::Chunked, &Limits::default()),
+            ParseResult::Error(ParseError::BadRequest)
+        );
+    }
+}
+
+#[test]
+fn body_reader() {
+    // The stream gives one byte at a time.
+    struct Slow<'a>(&'a [u8]);
+    impl<'a> Read for Slow<'a> {
+        fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+            if self.0.is_empty() || out.is_empty() {
+                return Ok(0);
+            }
+            out[0] = self.0[0];
+            self.0 = &self.0[1..];
+            Ok(1)
+        }
+    }
+
+    let buf = b"\r\n5\r\npedia\r\n0\r\nX-Sum: 1\r\n\r\nnext";
+    let mut r = BodyReader::new(
+        Slow(buf),
+        b"4\r\nWiki".to_vec(),
+        BodyKind::Chunked,
+        Limits::default(),
+    );
+    let mut data = Vec::new();
+    r.read_to_end(&mut data).unwrap();
+    assert_eq!(data, b"Wikipedia");
+    assert!(r.is_done());
+    assert_eq!(r.trailers(), &[("X-Sum".to_string(), "1".to_string())]);
+    let (mut inner, mut leftover) = r.into_inner();
+    inner.read_to_end(&mut leftover).unwrap();
+    assert_eq!(leftover, b"next");
+
+    let mut r = BodyReader::new(
+        Slow(b"cdef"),
+        b"ab".to_vec(),
+        BodyKind::Length(4),
+        Limits::default(),
+    );
+    let mut data = Vec::new();
+    r.read_to_end(&mut data).unwrap();
+    assert_eq!(data, b"abcd");
+
+    let mut r = BodyReader::new(
+        Slow(b"c"),
+        b"ab".to_vec(),
+        BodyKind::Length(4),
+        Limits::default(),
+    );
+    assert_eq!(
+        r.read_to_end(&mut Vec::new()).unwrap_err().kind(),
+        io::ErrorKind::UnexpectedEof
+    );
+
+    let limits = Limits {
+        max_body: 4,
+        ..Default::default()
+    };
+    let mut r = BodyReader::new(Slow(b""), buf[2..].to_vec(), BodyKind::Chunked, limits);
+    assert_eq!(
+        r.read_to_end(&mut Vec::new()).unwrap_err().kind(),
+        io::ErrorKind::InvalidData
+    );
+}
@@ -0,0 +1,347 @@
+use access_log::LogFormat;
+use listener::ListenAddr;
+use server::{Backend, ServerConfig};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+pub const DEFAULT_LISTEN: &str = "127.0.0.1:8080";
+
+pub const USAGE: &str = "usage: http_server [--config file] [--option value]...
+
+The options are the ones of the config file with - for _, they override the config file.
+  --listen addr              127.0.0.1:8080, [::1]:8080 or unix:/path, repeatable
+  --backend name             threads or epoll
+  --workers n                the worker threads of the threads backend
+  --queue-size n             the connections waiting for a worker
+  --max-connections n        the connections of the epoll backend
+  --idle-timeout secs        0 disables it
+  --write-timeout secs       0 disables it
+  --max-request-line bytes
+  --max-head bytes
+  --max-headers n
+  --max-body bytes
+  --root dir                 serve the files under it at /files/
+  --access-log file          - for stdout, reopened on SIGHUP
+  --access-log-format fmt    off, common, combined or json
+  --error-log file           - for stderr, reopened on SIGHUP
+
+The bound addresses are printed to stderr, port 0 binds a free port.
+";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Config {
+    // The default is used if nothing is given.
+    pub listen: Vec<ListenAddr>,
+    pub server: ServerConfig,
+    pub root: Option<PathBuf>,
+    // None for stdout.
+    pub access_log: Option<PathBuf>,
+    pub access_log_format: LogFormat,
+    // None for stderr.
+    pub error_log: Option<PathBuf>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            listen: Vec::new(),
+            server: ServerConfig::default(),
+            root: None,
+            access_log: None,
+            access_log_format: LogFormat::Common,
+            error_log: None,
+        }
+    }
+}
+
+// `-` is the standard output or the standard error.
+fn parse_log_path(values: &[&str]) -> Option<Option<PathBuf>> {
+    match *values {
+        ["-"] => Some(None),
+        [v] => Some(Some(PathBuf::from(v))),
+        _ => None,
+    }
+}
+
+fn parse_number(values: &[&str]) -> Option<usize> {
+    match *values {
+        [v] => v.parse().ok(),
+        _ => None,
+    }
+}
+
+// 0 is no timeout.
+fn parse_timeout(values: &[&str]) -> Option<Option<Duration>> {
+    match *values {
+        [v] => v
+            .parse::<f64>()
+            .ok()
+            .filter(|&s| 0.0 <= s && s.is_finite())
+            .map(|s| {
+                if s == 0.0 {
+                    None
+                } else {
+                    Some(Duration::from_millis((s * 1000.0) as u64))
+                }
+            }),
+        _ => None,
+    }
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut is_after_space = true;
+    for (i, c) in line.char_indices() {
+        if c == '#' && is_after_space {
+            return &line[..i];
+        }
+        is_after_space = c.is_whitespace();
+    }
+    line
+}
+
+impl Config {
+    // Set the option of the name, `listen` adds the addresses and the others replace the value.
+    pub fn set(&mut self, name: &str, values: &[&str]) -> Result<(), String> {
+        let positive = |values: &[&str]| parse_number(values).filter(|&n| 0 < n);
+        let is_ok = match name {
+            "listen" => {
+                if values.is_empty() {
+                    false
+                } else {
+                    for v in values {
+                        self.listen.push(v.parse()?);
+                    }
+                    true
+                }
+            }
+            "backend" => match *values {
+                [v] => Backend::from_name(v)
+                    .map(|b| self.server.backend = b)
+                    .is_some(),
+                _ => false,
+            },
+            "workers" => positive(values).map(|n| self.server.workers = n).is_some(),
+            "queue_size" => parse_number(values)
+                .map(|n| self.server.queue_size = n)
+                .is_some(),
+            "max_connections" => positive(values)
+                .map(|n| self.server.max_connections = n)
+                .is_some(),
+            "idle_timeout" => parse_timeout(values)
+                .map(|t| self.server.connection.idle_timeout = t)
+                .is_some(),
+            "write_timeout" => parse_timeout(values)
+                .map(|t| self.server.connection.write_timeout = t)
+                .is_some(),
+            "max_request_line" => positive(values)
+                .map(|n| self.server.connection.limits.max_request_line = n)
+                .is_some(),
+            "max_head" => positive(values)
+                .map(|n| self.server.connection.limits.max_head = n)
+                .is_some(),
+            "max_headers" => positive(values)
+                .map(|n| self.server.connection.limits.max_headers = n)
+                .is_some(),
+            "max_body" => parse_number(values)
+                .map(|n| self.server.connection.limits.max_body = n)
+                .is_some(),
+            "root" => match *values {
+                [v] => {
+                    self.root = Some(PathBuf::from(v));
+                    true
+                }
+                _ => false,
+            },
+            "access_log" => parse_log_path(values)
+                .map(|p| self.access_log = p)
+                .is_some(),
+            "error_log" => parse_log_path(values).map(|p| self.error_log = p).is_some(),
+            "access_log_format" => match *values {
+                [v] => LogFormat::from_name(v)
+                    .map(|f| self.access_log_format = f)
+                    .is_some(),
+                _ => false,
+            },
+            _ => return Err(format!("unknown option {}", name)),
+        };
+
+        if is_ok {
+            Ok(())
+        } else {
+            Err(format!("invalid values for {}: {}", name, values.join(" ")))
+        }
+    }
+
+    // The config file consists of the lines of an option name and its values like below.
+    // `#` starts a comment at the start of a line or after a space, it can be in a path.
+    //   # comment
+    //   listen 127.0.0.1:8080 [::1]:8080
+    //   listen unix:/run/http_server.sock
+    //   workers 16
+    //   root /srv/artifacts # the files
+    pub fn parse(text: &str) -> Result<Config, String> {
+        let mut config = Config::default();
+        for (i, line) in text.lines().enumerate() {
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let words = line.split_whitespace().collect::<Vec<_>>();
+            config
+                .set(words[0], &words[1..])
+                .map_err(|e| format!("line {}: {}", i + 1, e))?;
+        }
+        Ok(config)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Config, String> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+        Config::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    // The arguments without the program name.
+    // `--config file` is read first wherever it is, and the other options override it in order.
+    pub fn from_args<S: AsRef<str>>(args: &[S]) -> Result<Config, String> {
+        let args: Vec<&str> = args.iter().map(|a| a.as_ref()).collect();
+        let mut options = Vec::new();
+        let mut config = Config::default();
+
+        let mut it = args.iter();
+        while let Some(&arg) = it.next() {
+            let name = match arg.strip_prefix("--") {
+                Some(n) if !n.is_empty() => n.replace('-', "_"),
+                _ => return Err(format!("unexpected argument {}", arg)),
+            };
+            let value = *it
+                .next()
+                .ok_or_else(|| format!("missing value for {}", arg))?;
+            if name == "config" {
+                config = Config::load(value)?;
+            } else {
+                options.push((name, value));
+            }
+        }
+
+        // The command line addresses replace the ones of the config file.
+        if options.iter().any(|(n, _)| n == "listen") {
+            config.listen.clear();
+        }
+        for (name, value) in options {
+            config.set(&name, &[value])?;
+        }
+        Ok(config)
+    }
+
+    pub fn listen_addrs(&self) -> Vec<ListenAddr> {
+        if self.listen.is_empty() {
+            vec![DEFAULT_LISTEN.parse().unwrap()]
+        } else {
+            self.listen.clone()
+        }
+    }
+}
+
+#[test]
+fn config_parse() {
+    let config = Config::parse(
+        "# comment\n\
+         listen 127.0.0.1:0 [::1]:0\n\
+         listen unix:/tmp/a.sock\n\
+         backend epoll # inline\n\
+         workers 3\n\
+         idle_timeout 0.5\n\
+         write_timeout 0\n\
+         max_body 1024\n\
+         root /srv\n\
+         access_log /var/log/access.log\n\
+         access_log_format json\n\
+         error_log -\n",
+    )
+    .unwrap();
+    assert_eq!(
+        config.listen,
+        vec![
+            "127.0.0.1:0".parse().unwrap(),
+            "[::1]:0".parse().unwrap(),
+            "unix:/tmp/a.sock".parse().unwrap(),
+        ]
+    );
+    assert_eq!(config.server.backend, Backend::Epoll);
+    assert_eq!(config.server.workers, 3);
+    assert_eq!(
+        config.server.connection.idle_timeout,
+        Some(Duration::from_millis(500))
+    );
+    assert_eq!(config.server.connection.write_timeout, None);
+    assert_eq!(config.server.connection.limits.max_body, 1024);
+    assert_eq!(config.root, Some(PathBuf::from("/srv")));
+    assert_eq!(
+        config.access_log,
+        Some(PathBuf::from("/var/log/access.log"))
+    );
+    assert_eq!(config.access_log_format, LogFormat::Json);
+    assert_eq!(config.error_log, None);
+
+    assert_eq!(
+        Config::parse("workers 1\nworker 2\n"),
+        Err("line 2: unknown option worker".to_string())
+    );
+    assert_eq!(
+        Config::parse("workers 0\n"),
+        Err("line 1: invalid values for workers: 0".to_string())
+    );
+    let config = Config::parse("root /srv/a#b # files\nlisten unix:/tmp/#a.sock#\n").unwrap();
+    assert_eq!(config.root, Some(PathBuf::from("/srv/a#b")));
+    assert_eq!(config.listen, vec!["unix:/tmp/#a.sock#".parse().unwrap()]);
+    assert!(Config::parse("listen localhost\n").is_err());
+    assert!(Config::parse("backend fibers\n").is_err());
+
+    assert_eq!(
+        Config::default().listen_addrs(),
+        vec![DEFAULT_LISTEN.parse().unwrap()]
+    );
+}
+
+#[test]
+fn config_from_args() {
+    let path = ::std::env::temp_dir().join(format!("http_server_{}.conf", ::std::process::id()));
+    fs::write(&path, "listen 127.0.0.1:1\nworkers 2\nmax_headers 10\n").unwrap();
+    let path = path.to_str().unwrap();
+
+    let config = Config::from_args(&["--workers", "4", "--config", path]).unwrap();
+    assert_eq!(config.server.workers, 4);
+    assert_eq!(config.server.connection.limits.max_headers, 10);
+    assert_eq!(config.listen, vec!["127.0.0.1:1".parse().unwrap()]);
+
+    let config = Config::from_args(&[
+        "--config",
+        path,
+        "--listen",
+        "127.0.0.1:0",
+        "--listen",
+        "unix:/tmp/b.sock",
+        "--access-log-format",
+        "off",
+        "--error-log",
+        "/tmp/error.log",
+    ])
+    .unwrap();
+    assert_eq!(
+        config.listen,
+        vec![
+            "127.0.0.1:0".parse().unwrap(),
+            "unix:/tmp/b.sock".parse().unwrap(),
+        ]
+    );
+    assert_eq!(config.access_log_format, LogFormat::Off);
+    assert_eq!(config.error_log, Some(PathBuf::from("/tmp/error.log")));
+    fs::remove_file(path).unwrap();
+
+    assert!(Config::from_args(&["epoll"]).is_err());
+    assert!(Config::from_args(&["--workers"]).is_err());
+    assert!(Config::from_args(&["--config", "/nonexistent"]).is_err());
+}
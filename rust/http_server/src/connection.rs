@@ -0,0 +1,534 @@
+use access_log::{AccessLog, Entry};
+use body::{parse_body, BodyKind};
+use listener::Stream;
+use parser::{next_line, parse_with_limits, Limits, Method, ParseError, Request, Version};
+use response::{BodyWriter, Response};
+use router::Router;
+use std::io;
+use std::io::{BufWriter, Read, Write};
+use std::net::{Shutdown, SocketAddr};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant, SystemTime};
+
+// How often the blocking connection checks the idle time and the shutdown.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConnectionConfig {
+    pub limits: Limits,
+    // The connection is closed if no byte comes in this time.
+    pub idle_timeout: Option<Duration>,
+    // The connection is closed if the client doesn't take the response in this time.
+    pub write_timeout: Option<Duration>,
+}
+
+impl Default for ConnectionConfig {
+    fn default() -> ConnectionConfig {
+        ConnectionConfig {
+            limits: Limits::default(),
+            idle_timeout: Some(Duration::from_secs(5)),
+            write_timeout: Some(Duration::from_secs(30)),
+        }
+    }
+}
+
+// The state of a connection, it doesn't know how the bytes are received.
+// The received bytes are pushed and the complete requests in them are answered in order.
+#[derive(Debug, Default)]
+pub struct Connection {
+    buf: Vec<u8>,
+    is_continue_sent: bool,
+    is_closing: bool,
+    // For the access log.
+    peer: Option<SocketAddr>,
+    // When the first byte of the pending request came.
+    started: Option<Instant>,
+}
+
+// The first line of the request as it is received.
+fn request_line(buf: &[u8]) -> String {
+    let mut pos = 0;
+    while let Some((line, next)) = next_line(buf, pos) {
+        if !line.is_empty() {
+            return String::from_utf8_lossy(line).into_owned();
+        }
+        pos = next;
+    }
+    "-".to_string()
+}
+
+impl Connection {
+    pub fn new() -> Connection {
+        Connection::with_peer(None)
+    }
+
+    pub fn with_peer(peer: Option<SocketAddr>) -> Connection {
+        Connection {
+            buf: Vec::new(),
+            is_continue_sent: false,
+            is_closing: false,
+            peer,
+            started: None,
+        }
+    }
+
+    // The request is drained from the buffer when the response is written,
+    // what the access log needs is taken before.
+    fn answer(&self, req: Option<&Request>, body: BodyWriter) -> Answer {
+        let header = |name| req.and_then(|r| r.headers.get(name)).map(str::to_string);
+        Answer {
+            body,
+            peer: self.peer,
+            request_line: request_line(&self.buf),
+            referer: header("Referer"),
+            user_agent: header("User-Agent"),
+            started: self.started,
+        }
+    }
+
+    // The idle connection is closed silently, the slow request is answered.
+    pub fn time_out<W: Write>(&self, w: &mut W, log: &AccessLog) -> io::Result<()> {
+        if self.has_pending() {
+            let mut res = self.reject(w, Response::error(408))?;
+            res.write_part(w, usize::MAX)?;
+            w.flush()?;
+            res.log(log);
+        }
+        Ok(())
+    }
+
+    // Answer the broken request, the connection is closed after it.
+    fn reject<W: Write>(&self, w: &mut W, res: Response) -> io::Result<Answer> {
+        let body = res.write_head(w, Version::Http11, Method::Get, false)?;
+        Ok(self.answer(None, body))
+    }
+
+    pub fn peer(&self) -> Option<SocketAddr> {
+        self.peer
+    }
+
+    // The connection is closed after the next response.
+    pub fn set_closing(&mut self) {
+        self.is_closing = true;
+    }
+
+    pub fn push(&mut self, data: &[u8]) {
+        if self.started.is_none() && !data.is_empty() {
+            self.started = Some(Instant::now());
+        }
+        self.buf.extend_from_slice(data);
+    }
+
+    // Whether a request has been received partially.
+    pub fn has_pending(&self) -> bool {
+        !self.buf.is_empty()
+    }
+
+    // Answer the first complete request in the buffer, None if there is none yet.
+    // The head of the response is written, the caller writes the rest of the body
+    // and logs it before the next request.
+    pub fn next<W: Write>(
+        &mut self,
+        w: &mut W,
+        router: &Router,
+        limits: &Limits,
+    ) -> io::Result<Option<Answer>> {
+        use parser::ParseResult::*;
+
+        let (length, res) = match parse_with_limits(&self.buf, limits) {
+            // The empty lines before the request line are not limited by the parser.
+            Partial if limits.max_head < self.buf.len() => {
+                let e = ParseError::HeaderTooLarge;
+                return self.reject(w, Response::from_parse_error(e)).map(Some);
+            }
+            Partial => return Ok(None),
+            Error(e) => return self.reject(w, Response::from_parse_error(e)).map(Some),
+            Complete(req) => {
+                let kind = match BodyKind::of(&req, limits) {
+                    Ok(k) => k,
+                    Err(e) => return self.reject(w, Response::from_parse_error(e)).map(Some),
+                };
+                let body = match parse_body(&self.buf[req.head_length..], kind, limits) {
+                    Complete(body) => body,
+                    Error(e) => return self.reject(w, Response::from_parse_error(e)).map(Some),
+                    // The chunk sizes and the trailers take a head at most besides the data.
+                    Partial
+                        if limits.max_body + limits.max_head < self.buf.len() - req.head_length =>
+                    {
+                        let e = ParseError::PayloadTooLarge;
+                        return self.reject(w, Response::from_parse_error(e)).map(Some);
+                    }
+                    Partial => {
+                        // The client waits for this before sending the body.
+                        if !self.is_continue_sent
+                            && req.version == Version::Http11
+                            && req.headers.contains_token("Expect", "100-continue")
+                        {
+                            w.write_all(b"HTTP/1.1 100 Continue\r\n\r\n")?;
+                            w.flush()?;
+                            self.is_continue_sent = true;
+                        }
+                        return Ok(None);
+                    }
+                };
+
+                let res = router.handle(&req, &body.data);
+                let body_writer = res.write_head(
+                    w,
+                    req.version,
+                    req.method,
+                    req.wants_keep_alive() && !self.is_closing,
+                )?;
+                (
+                    req.head_length + body.length,
+                    self.answer(Some(&req), body_writer),
+                )
+            }
+        };
+
+        self.buf.drain(..length);
+        self.is_continue_sent = false;
+        // The pipelined request has been waiting since now.
+        self.started = if self.buf.is_empty() {
+            None
+        } else {
+            Some(Instant::now())
+        };
+        Ok(Some(res))
+    }
+
+    // Answer all the complete requests in the buffer, the rest is kept for the next push.
+    // It returns false if the connection has to be closed.
+    pub fn process<W: Write>(
+        &mut self,
+        w: &mut W,
+        router: &Router,
+        limits: &Limits,
+        log: &AccessLog,
+    ) -> io::Result<bool> {
+        loop {
+            let mut w = BufWriter::new(&mut *w);
+            let mut res = match self.next(&mut w, router, limits)? {
+                Some(res) => res,
+                None => return Ok(true),
+            };
+            res.write_part(&mut w, usize::MAX)?;
+            w.flush()?;
+            res.log(log);
+            if !res.keep_alive() {
+                return Ok(false);
+            }
+        }
+    }
+}
+
+// The response to a request, the body may be left to write.
+#[derive(Debug)]
+pub struct Answer {
+    body: BodyWriter,
+    peer: Option<SocketAddr>,
+    request_line: String,
+    referer: Option<String>,
+    user_agent: Option<String>,
+    // When the first byte of the request came.
+    started: Option<Instant>,
+}
+
+impl Answer {
+    // Write the body by about max bytes at most, true once the whole body is written.
+    pub fn write_part<W: Write>(&mut self, w: &mut W, max: usize) -> io::Result<bool> {
+        self.body.write_part(w, max)
+    }
+
+    // The connection can be used for the next request.
+    pub fn keep_alive(&self) -> bool {
+        self.body.sent().keep_alive
+    }
+
+    // The latency ends now, so it is logged after the response is written.
+    pub fn log(&self, log: &AccessLog) {
+        let sent = self.body.sent();
+        log.log(&Entry {
+            peer: self.peer,
+            time: SystemTime::now(),
+            request_line: &self.request_line,
+            status: sent.status,
+            body_bytes: sent.body_bytes,
+            referer: self.referer.as_deref(),
+            user_agent: self.user_agent.as_deref(),
+            latency: self.started.map_or(Duration::from_secs(0), |t| t.elapsed()),
+        });
+    }
+}
+
+fn is_timeout(e: &io::Error) -> bool {
+    e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut
+}
+
+// Serve the requests on the blocking stream until the connection is closed.
+// After `stop` is set, the connection is closed once the request in flight is answered.
+pub fn serve<S: Read + Write>(
+    stream: &mut S,
+    peer: Option<SocketAddr>,
+    router: &Router,
+    log: &AccessLog,
+    config: &ConnectionConfig,
+    stop: &AtomicBool,
+) -> io::Result<()> {
+    let mut conn = Connection::with_peer(peer);
+    let mut b = [0; 4096];
+    let mut last_active = Instant::now();
+    loop {
+        let n = match stream.read(&mut b) {
+            Ok(0) => return Ok(()),
+            Ok(n) => n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(ref e) if is_timeout(e) => {
+                // The connection without the request in flight is closed at once after the stop.
+                let is_stopped = stop.load(Ordering::SeqCst) && !conn.has_pending();
+                let is_expired = config
+                    .idle_timeout
+                    .is_some_and(|t| t <= last_active.elapsed());
+                if !is_stopped && !is_expired {
+                    continue;
+                }
+
+                return conn.time_out(stream, log);
+            }
+            Err(e) => return Err(e),
+        };
+        last_active = Instant::now();
+
+        if stop.load(Ordering::SeqCst) {
+            conn.set_closing();
+        }
+        conn.push(&b[..n]);
+        if !conn.process(stream, router, &config.limits, log)? {
+            return Ok(());
+        }
+    }
+}
+
+pub fn serve_stream(
+    mut stream: Stream,
+    router: &Router,
+    log: &AccessLog,
+    config: &ConnectionConfig,
+    stop: &AtomicBool,
+) -> io::Result<()> {
+    let tick = config
+        .idle_timeout
+        .map_or(POLL_INTERVAL, |t| t.min(POLL_INTERVAL));
+    stream.set_read_timeout(Some(tick))?;
+    stream.set_write_timeout(config.write_timeout)?;
+    stream.set_nodelay(true)?;
+    let peer = stream.peer_addr();
+    let r = serve(&mut stream, peer, router, log, config, stop);
+    let _ = stream.shutdown(Shutdown::Both);
+    r
+}
+
+#[cfg(test)]
+struct MockStream {
+    input: io::Cursor<Vec<u8>>,
+    output: Vec<u8>,
+}
+
+#[cfg(test)]
+impl Read for MockStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.input.read(buf)
+    }
+}
+
+#[cfg(test)]
+impl Write for MockStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.output.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+fn serve_mock(input: &[u8]) -> String {
+    use router::Context;
+
+    let router = Router::new()
+        .get("/:n", |ctx: &Context| {
+            Response::ok()
+                .with_header("Date", "-")
+                .with_bytes(ctx.params.get("n").unwrap().to_string())
+        })
+        .post("/echo", |ctx: &Context| {
+            Response::ok()
+                .with_header("Date", "-")
+                .with_bytes(ctx.body.to_vec())
+        });
+    let mut s = MockStream {
+        input: io::Cursor::new(input.to_vec()),
+        output: Vec::new(),
+    };
+    serve(
+        &mut s,
+        None,
+        &router,
+        &AccessLog::off(),
+        &ConnectionConfig::default(),
+        &AtomicBool::new(false),
+    )
+    .unwrap();
+    String::from_utf8(s.output).unwrap()
+}
+
+#[test]
+fn connection_pipelining() {
+    let out = serve_mock(
+        b"GET /1 HTTP/1.1\r\nHost: a\r\n\r\nPOST /echo HTTP/1.1\r\nHost: a\r\nContent-Length: 2\r\n\r\nxyGET /3 HTTP/1.1\r\nHost: a\r\n\r\n",
+    );
+    assert_eq!(
+        out,
+        "HTTP/1.1 200 OK\r\nDate: -\r\nContent-Length: 1\r\n\r\n1\
+         HTTP/1.1 200 OK\r\nDate: -\r\nContent-Length: 2\r\n\r\nxy\
+         HTTP/1.1 200 OK\r\nDate: -\r\nContent-Length: 1\r\n\r\n3"
+    );
+}
+
+#[test]
+fn connection_close() {
+    let out = serve_mock(
+        b"GET /1 HTTP/1.1\r\nHost: a\r\nConnection: close\r\n\r\nGET /2 HTTP/1.1\r\nHost: a\r\n\r\n",
+    );
+    assert!(out.ends_with("Connection: close\r\n\r\n1"));
+
+    // HTTP/1.0 is closed by default.
+    let out = serve_mock(b"GET /1 HTTP/1.0\r\n\r\nGET /2 HTTP/1.0\r\n\r\n");
+    assert!(out.ends_with("Connection: close\r\n\r\n1"));
+    let out =
+        serve_mock(b"GET /1 HTTP/1.0\r\nConnection: keep-alive\r\n\r\nGET /2 HTTP/1.0\r\n\r\n");
+    assert!(out.contains("Connection: keep-alive\r\n\r\n1"));
+    assert!(out.ends_with("Connection: close\r\n\r\n2"));
+
+    // The error closes the connection.
+    let out = serve_mock(b"GET /1 HTTP/1.1\r\n\r\nGET /2 HTTP/1.1\r\nHost: a\r\n\r\n");
+    assert!(out.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+    assert_eq!(out.matches("HTTP/1.1").count(), 1);
+}
+
+#[test]
+fn connection_closing() {
+    let router = Router::new();
+    let mut conn = Connection::new();
+    let mut out = Vec::new();
+    conn.push(b"GET / HTTP/1.1\r\nHost: a\r\n\r\nGET / HTTP/1.1\r\nHost: a\r\n\r\n");
+    conn.set_closing();
+    assert!(!conn
+        .process(&mut out, &router, &Limits::default(), &AccessLog::off())
+        .unwrap());
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.contains("Connection: close\r\n"));
+    assert_eq!(out.matches("HTTP/1.1").count(), 1);
+}
+
+#[test]
+fn connection_expect_continue() {
+    let router = Router::new();
+    let mut conn = Connection::new();
+    let mut out = Vec::new();
+    conn.push(
+        b"POST /echo HTTP/1.1\r\nHost: a\r\nExpect: 100-continue\r\nContent-Length: 2\r\n\r\n",
+    );
+    assert!(conn
+        .process(&mut out, &router, &Limits::default(), &AccessLog::off())
+        .unwrap());
+    conn.push(b"x");
+    assert!(conn
+        .process(&mut out, &router, &Limits::default(), &AccessLog::off())
+        .unwrap());
+    assert_eq!(out, b"HTTP/1.1 100 Continue\r\n\r\n");
+    assert!(conn.has_pending());
+}
+
+#[test]
+fn connection_idle_timeout() {
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+
+    let lis = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = lis.local_addr().unwrap();
+    let server = thread::spawn(move || {
+        let (stream, _) = lis.accept().unwrap();
+        let stream = Stream::Tcp(stream);
+        let config = ConnectionConfig {
+            idle_timeout: Some(Duration::from_millis(100)),
+            ..Default::default()
+        };
+        serve_stream(
+            stream,
+            &Router::new(),
+            &AccessLog::off(),
+            &config,
+            &AtomicBool::new(false),
+        )
+        .unwrap();
+    });
+
+    let mut client = TcpStream::connect(addr).unwrap();
+    client.write_all(b"GET / HTTP/1.1\r\n").unwrap();
+    let mut res = String::new();
+    client.read_to_string(&mut res).unwrap();
+    assert!(res.starts_with("HTTP/1.1 408 Request Timeout\r\n"));
+    server.join().unwrap();
+}
+
+#[test]
+fn connection_access_log() {
+    use access_log::LogFormat;
+    use log_file::{LogFile, SharedBuf};
+
+    let buf = SharedBuf::default();
+    let log = AccessLog::new(LogFormat::Combined, LogFile::new(buf.clone()));
+    let mut conn = Connection::with_peer("10.0.0.1:5000".parse().ok());
+    conn.push(b"\r\nGET /a HTTP/1.1\r\nHost: a\r\nUser-Agent: t\r\n\r\nBAD\r\n\r\n");
+    assert!(!conn
+        .process(&mut Vec::new(), &Router::new(), &Limits::default(), &log)
+        .unwrap());
+
+    let lines = buf.to_string();
+    let lines: Vec<&str> = lines.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].starts_with("10.0.0.1 - - ["));
+    assert!(lines[0].ends_with("] \"GET /a HTTP/1.1\" 404 14 \"-\" \"t\""));
+    assert!(lines[1].ends_with("] \"BAD\" 400 16 \"-\" \"-\""));
+}
+
+#[test]
+fn connection_buffer_limits() {
+    let limits = Limits {
+        max_head: 64,
+        max_body: 16,
+        ..Default::default()
+    };
+    let answer = |input: &[u8]| {
+        let mut conn = Connection::new();
+        let mut out = Vec::new();
+        conn.push(input);
+        let r = conn.process(&mut out, &Router::new(), &limits, &AccessLog::off());
+        (r.unwrap(), String::from_utf8(out).unwrap())
+    };
+
+    // The empty lines are limited too.
+    let (is_open, out) = answer(&b"\r\n".repeat(40));
+    assert!(!is_open);
+    assert!(out.starts_with("HTTP/1.1 431 "));
+    assert!(answer(&b"\r\n".repeat(20)).0);
+
+    // The chunk sizes without data don't grow the buffer forever.
+    let mut input = b"POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\n\r\n".to_vec();
+    input.extend_from_slice(&[b'0'; 100]);
+    let (is_open, out) = answer(&input);
+    assert!(!is_open);
+    assert!(out.starts_with("HTTP/1.1 413 "));
+}
@@ -0,0 +1,183 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const DAY_NAMES: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+const MONTH_NAMES: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+// The year, the month and the day of the days since 1970-01-01.
+// http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil_from_days(days: i64) -> (i64, usize, usize) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y = yoe + era * 400 + if m <= 2 { 1 } else { 0 };
+    (y, m as usize, d as usize)
+}
+
+// The days since 1970-01-01 of the date, the inverse of civil_from_days.
+// http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+fn days_from_civil(y: i64, m: usize, d: usize) -> i64 {
+    let (m, d) = (m as i64, d as i64);
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = if m > 2 { m - 3 } else { m + 9 };
+    let doy = (153 * mp + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+// IMF-fixdate like `Sun, 06 Nov 1994 08:49:37 GMT`.
+pub fn format_http_date(t: SystemTime) -> String {
+    let secs = t
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64);
+    let (days, secs) = (secs / 86400, secs % 86400);
+    let (y, m, d) = civil_from_days(days);
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+        DAY_NAMES[(days % 7) as usize],
+        d,
+        MONTH_NAMES[m - 1],
+        y,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+// The time of Common Log Format like `10/Oct/2000:13:55:36 +0000`.
+pub fn format_clf_date(t: SystemTime) -> String {
+    let secs = t
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64);
+    let (days, secs) = (secs / 86400, secs % 86400);
+    let (y, m, d) = civil_from_days(days);
+    format!(
+        "{:02}/{}/{}:{:02}:{:02}:{:02} +0000",
+        d,
+        MONTH_NAMES[m - 1],
+        y,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+// RFC 3339 in UTC like `2000-10-10T13:55:36Z`.
+pub fn format_rfc3339(t: SystemTime) -> String {
+    let secs = t
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64);
+    let (days, secs) = (secs / 86400, secs % 86400);
+    let (y, m, d) = civil_from_days(days);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        y,
+        m,
+        d,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+fn parse_time(s: &str) -> Option<u64> {
+    let mut it = s.split(':').map(|n| {
+        if n.len() == 2 {
+            n.parse::<u64>().ok()
+        } else {
+            None
+        }
+    });
+    let (h, m, s) = (it.next()??, it.next()??, it.next()??);
+    if it.next().is_some() || 23 < h || 59 < m || 60 < s {
+        return None;
+    }
+    Some(h * 3600 + m * 60 + s)
+}
+
+// The three formats are accepted, the obsolete RFC 850 and asctime too.
+//   Sun, 06 Nov 1994 08:49:37 GMT
+//   Sunday, 06-Nov-94 08:49:37 GMT
+//   Sun Nov  6 08:49:37 1994
+pub fn parse_http_date(s: &str) -> Option<SystemTime> {
+    let month = |m: &str| MONTH_NAMES.iter().position(|&n| n == m).map(|i| i + 1);
+    let words: Vec<&str> = s.split_whitespace().collect();
+
+    let (y, m, d, time) = match *words.as_slice() {
+        [_, d, m, y, time, "GMT"] if d.len() == 2 && y.len() == 4 => {
+            (y.parse().ok()?, month(m)?, d.parse().ok()?, time)
+        }
+        [_, date, time, "GMT"] => {
+            let mut it = date.split('-');
+            let (d, m, y) = (it.next()?, it.next()?, it.next()?);
+            if it.next().is_some() || d.len() != 2 || y.len() != 2 {
+                return None;
+            }
+            // The two digit years are 1970 to 2069.
+            let y: i64 = y.parse().ok()?;
+            let y = if y < 70 { 2000 + y } else { 1900 + y };
+            (y, month(m)?, d.parse().ok()?, time)
+        }
+        [_, m, d, time, y] if y.len() == 4 => (y.parse().ok()?, month(m)?, d.parse().ok()?, time),
+        _ => return None,
+    };
+    if !(1..=31).contains(&d) || y < 1970 {
+        return None;
+    }
+
+    let secs = days_from_civil(y, m, d) as u64 * 86400 + parse_time(time)?;
+    Some(UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+#[test]
+fn http_date_format() {
+    assert_eq!(
+        format_http_date(UNIX_EPOCH + Duration::from_secs(784_111_777)),
+        "Sun, 06 Nov 1994 08:49:37 GMT"
+    );
+    assert_eq!(
+        format_http_date(UNIX_EPOCH + Duration::from_secs(951_782_400)),
+        "Tue, 29 Feb 2000 00:00:00 GMT"
+    );
+    assert_eq!(
+        format_http_date(UNIX_EPOCH),
+        "Thu, 01 Jan 1970 00:00:00 GMT"
+    );
+    assert_eq!(
+        format_clf_date(UNIX_EPOCH + Duration::from_secs(784_111_777)),
+        "06/Nov/1994:08:49:37 +0000"
+    );
+    assert_eq!(
+        format_rfc3339(UNIX_EPOCH + Duration::from_secs(784_111_777)),
+        "1994-11-06T08:49:37Z"
+    );
+}
+
+#[test]
+fn http_date_parse() {
+    let t = Some(UNIX_EPOCH + Duration::from_secs(784_111_777));
+    assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"), t);
+    assert_eq!(parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"), t);
+    assert_eq!(parse_http_date("Sun Nov  6 08:49:37 1994"), t);
+    assert_eq!(
+        parse_http_date("Tue, 29 Feb 2000 00:00:00 GMT"),
+        Some(UNIX_EPOCH + Duration::from_secs(951_782_400))
+    );
+
+    let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    assert_eq!(parse_http_date(&format_http_date(now)), Some(now));
+
+    assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 UTC"), None);
+    assert_eq!(parse_http_date("Sun, 06 Foo 1994 08:49:37 GMT"), None);
+    assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49 GMT"), None);
+    assert_eq!(parse_http_date("Sun, 06 Nov 1994 25:49:37 GMT"), None);
+    assert_eq!(parse_http_date(""), None);
+}
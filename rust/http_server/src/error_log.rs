@@ -0,0 +1,59 @@
+use date::format_clf_date;
+use log_file::LogFile;
+use std::fmt;
+use std::io;
+use std::net::SocketAddr;
+use std::time::SystemTime;
+
+// The failures which are not of a request, like the accept and the connection errors.
+pub struct ErrorLog {
+    file: LogFile,
+}
+
+// The peer in the logs, the unix socket has no address.
+pub fn format_peer(peer: Option<SocketAddr>) -> String {
+    peer.map_or("unix".to_string(), |p| p.to_string())
+}
+
+impl ErrorLog {
+    pub fn new(file: LogFile) -> ErrorLog {
+        ErrorLog { file }
+    }
+
+    pub fn stderr() -> ErrorLog {
+        ErrorLog::new(LogFile::new(io::stderr()))
+    }
+
+    // The line like `[10/Oct/2000:13:55:36 +0000] error: the message`.
+    pub fn log(&self, message: fmt::Arguments) {
+        self.file.write_line(&format!(
+            "[{}] error: {}",
+            format_clf_date(SystemTime::now()),
+            message
+        ));
+    }
+
+    pub fn reopen(&self) -> io::Result<()> {
+        self.file.reopen()
+    }
+}
+
+#[test]
+fn error_log_format() {
+    use log_file::SharedBuf;
+
+    let buf = SharedBuf::default();
+    let log = ErrorLog::new(LogFile::new(buf.clone()));
+    log.log(format_args!(
+        "{}: {}",
+        format_peer("[::1]:80".parse().ok()),
+        "reset"
+    ));
+    log.log(format_args!("{}: {}", format_peer(None), "broken pipe"));
+
+    let text = buf.to_string();
+    let lines: Vec<&str> = text.lines().collect();
+    assert!(lines[0].starts_with('['));
+    assert!(lines[0].ends_with(" +0000] error: [::1]:80: reset"));
+    assert!(lines[1].ends_with("] error: unix: broken pipe"));
+}
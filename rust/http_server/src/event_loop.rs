@@ -0,0 +1,570 @@
+use access_log::AccessLog;
+use connection::{Answer, Connection};
+use error_log::{format_peer, ErrorLog};
+use listener::{Listener, Stream};
+use parser::Limits;
+use router::Router;
+use server::{reject_nonblocking, ServerConfig};
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::io::{Read, Write};
+use std::os::unix::io::AsRawFd;
+use std::time::{Duration, Instant};
+use sys::{Epoll, EPOLLIN, EPOLLOUT, EPOLLRDHUP};
+
+const MAX_EVENTS: usize = 1024;
+// How often the loop checks the shutdown.
+const POLL_INTERVAL_MS: i32 = 100;
+// How often the idle connections are looked for.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+// The bytes queued for a connection at most, the body of a large file or a stream
+// is read into the queue part by part as the client takes it.
+const OUT_CAPACITY: usize = 64 * 1024;
+// The bytes read from a connection at most before the others are served.
+const MAX_READ_PER_EVENT: usize = 64 * 1024;
+
+struct Client {
+    stream: Stream,
+    conn: Connection,
+    // The responses not written yet.
+    out: Vec<u8>,
+    written: usize,
+    // The bytes written before out.
+    count_flushed: u64,
+    // The response whose body is left to queue, the next requests wait for it.
+    in_flight: Option<Answer>,
+    // The queued responses by the position of their last bytes, each is logged once it is written.
+    queued: VecDeque<(u64, Answer)>,
+    last_active: Instant,
+    // No more request is answered.
+    is_closing: bool,
+    // The client has closed its side.
+    is_eof: bool,
+    interest: u32,
+}
+
+impl Client {
+    fn new(stream: Stream) -> Client {
+        Client {
+            conn: Connection::with_peer(stream.peer_addr()),
+            stream,
+            out: Vec::new(),
+            written: 0,
+            count_flushed: 0,
+            in_flight: None,
+            queued: VecDeque::new(),
+            last_active: Instant::now(),
+            is_closing: false,
+            is_eof: false,
+            interest: EPOLLIN | EPOLLRDHUP,
+        }
+    }
+
+    // Whether the responses have been written.
+    fn is_sent(&self) -> bool {
+        self.out.is_empty() && self.in_flight.is_none()
+    }
+
+    fn is_idle(&self) -> bool {
+        !self.conn.has_pending() && self.is_sent()
+    }
+
+    // The responses are written before the next requests are read.
+    fn wanted_interest(&self) -> u32 {
+        if self.is_sent() {
+            EPOLLIN | EPOLLRDHUP
+        } else {
+            EPOLLOUT
+        }
+    }
+
+    // Read and answer the requests as far as it doesn't block, false if the connection has to be closed.
+    fn on_ready(
+        &mut self,
+        buf: &mut [u8],
+        router: &Router,
+        limits: &Limits,
+        log: &AccessLog,
+    ) -> io::Result<bool> {
+        if self.is_sent() && !self.is_eof {
+            // The rest is read on the next event, the socket stays readable.
+            let mut count_read = 0;
+            while count_read < MAX_READ_PER_EVENT {
+                match self.stream.read(buf) {
+                    Ok(0) => {
+                        self.is_eof = true;
+                        break;
+                    }
+                    Ok(n) => {
+                        self.conn.push(&buf[..n]);
+                        count_read += n;
+                    }
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                    Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(e),
+                }
+            }
+            self.last_active = Instant::now();
+        }
+
+        // The responses to the requests before the half-close are still written.
+        let is_waiting = self.queue(router, limits)?;
+        self.flush(log)?;
+        Ok(!(self.is_sent() && (self.is_closing || (self.is_eof && is_waiting))))
+    }
+
+    // Queue the responses until out is full, true if the next request isn't complete.
+    fn queue(&mut self, router: &Router, limits: &Limits) -> io::Result<bool> {
+        // The written bytes are dropped, out doesn't grow while the client reads slowly.
+        self.out.drain(..self.written);
+        self.count_flushed += self.written as u64;
+        self.written = 0;
+        while self.out.len() < OUT_CAPACITY {
+            let mut res = match self.in_flight.take() {
+                Some(res) => res,
+                None if self.is_closing => return Ok(false),
+                None => match self.conn.next(&mut self.out, router, limits)? {
+                    Some(res) => res,
+                    None => return Ok(true),
+                },
+            };
+
+            let room = OUT_CAPACITY.saturating_sub(self.out.len());
+            if !res.write_part(&mut self.out, room)? {
+                self.in_flight = Some(res);
+                continue;
+            }
+            if !res.keep_alive() {
+                self.is_closing = true;
+            }
+            let end = self.count_flushed + self.out.len() as u64;
+            self.queued.push_back((end, res));
+        }
+        Ok(false)
+    }
+
+    fn flush(&mut self, log: &AccessLog) -> io::Result<()> {
+        let r = self.write_out();
+        // The latency of the access log runs to the last byte of the response.
+        let count_written = self.count_flushed + self.written as u64;
+        while self
+            .queued
+            .front()
+            .is_some_and(|&(end, _)| end <= count_written)
+        {
+            self.queued.pop_front().unwrap().1.log(log);
+        }
+        if self.written == self.out.len() {
+            self.count_flushed += self.out.len() as u64;
+            self.out.clear();
+            self.written = 0;
+        }
+        r
+    }
+
+    fn write_out(&mut self) -> io::Result<()> {
+        while self.written < self.out.len() {
+            match self.stream.write(&self.out[self.written..]) {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(n) => {
+                    self.written += n;
+                    self.last_active = Instant::now();
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+}
+
+struct EventLoop<'a> {
+    epoll: Epoll,
+    router: &'a Router,
+    log: &'a AccessLog,
+    error_log: &'a ErrorLog,
+    config: &'a ServerConfig,
+    clients: HashMap<u64, Client>,
+    next_token: u64,
+}
+
+impl<'a> EventLoop<'a> {
+    fn accept(&mut self, listener: &Listener) {
+        loop {
+            let stream = match listener.accept() {
+                Ok(stream) => stream,
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    self.error_log.log(format_args!(
+                        "An error occured while accepting a connection: {}",
+                        e
+                    ));
+                    return;
+                }
+            };
+
+            if self.config.max_connections <= self.clients.len() {
+                reject_nonblocking(stream);
+                continue;
+            }
+
+            let token = self.next_token;
+            self.next_token += 1;
+            let client = Client::new(stream);
+            let r = client
+                .stream
+                .set_nonblocking(true)
+                .and_then(|_| client.stream.set_nodelay(true))
+                .and_then(|_| {
+                    self.epoll
+                        .add(client.stream.as_raw_fd(), client.interest, token)
+                });
+            match r {
+                Ok(_) => {
+                    self.clients.insert(token, client);
+                }
+                Err(e) => self.error_log.log(format_args!(
+                    "An error occured while registering {}: {}",
+                    format_peer(client.conn.peer()),
+                    e
+                )),
+            }
+        }
+    }
+
+    fn on_ready(&mut self, token: u64, buf: &mut [u8]) {
+        let is_open = match self.clients.get_mut(&token) {
+            None => return,
+            Some(c) => match c.on_ready(buf, self.router, &self.config.connection.limits, self.log)
+            {
+                Ok(true) if c.wanted_interest() == c.interest => true,
+                Ok(true) => {
+                    c.interest = c.wanted_interest();
+                    self.epoll
+                        .modify(c.stream.as_raw_fd(), c.interest, token)
+                        .is_ok()
+                }
+                Ok(false) => false,
+                Err(e) => {
+                    self.error_log.log(format_args!(
+                        "An error occured while serving {}: {}",
+                        format_peer(c.conn.peer()),
+                        e
+                    ));
+                    false
+                }
+            },
+        };
+
+        if !is_open {
+            self.close(token);
+        }
+    }
+
+    fn close(&mut self, token: u64) {
+        if let Some(c) = self.clients.remove(&token) {
+            let _ = self.epoll.delete(c.stream.as_raw_fd());
+        }
+    }
+
+    // Close the connections which are quiet too long, the slow request is answered.
+    fn sweep(&mut self) {
+        let c = &self.config.connection;
+        let (idle_timeout, write_timeout) = (c.idle_timeout, c.write_timeout);
+        let expired: Vec<u64> = self
+            .clients
+            .iter()
+            .filter(|&(_, c)| {
+                let timeout = if c.is_sent() {
+                    idle_timeout
+                } else {
+                    write_timeout
+                };
+                timeout.is_some_and(|t| t <= c.last_active.elapsed())
+            })
+            .map(|(&t, _)| t)
+            .collect();
+        for token in expired {
+            if let Some(c) = self.clients.get_mut(&token) {
+                if c.is_sent() {
+                    let _ = c.conn.time_out(&mut c.stream, self.log);
+                }
+            }
+            self.close(token);
+        }
+    }
+
+    fn close_idle(&mut self) {
+        let idle: Vec<u64> = self
+            .clients
+            .iter()
+            .filter(|&(_, c)| c.is_idle())
+            .map(|(&t, _)| t)
+            .collect();
+        for token in idle {
+            self.close(token);
+        }
+    }
+}
+
+// Serve all the connections on one thread until `is_stopped` returns true.
+// The handlers are called on the thread, a handler or a stream body which blocks stalls all the connections.
+// After the stop, the connections are closed once the requests in flight are answered.
+pub fn run<F: Fn() -> bool>(
+    listeners: &[Listener],
+    router: &Router,
+    log: &AccessLog,
+    error_log: &ErrorLog,
+    config: &ServerConfig,
+    is_stopped: F,
+) -> io::Result<()> {
+    // The tokens of the listeners are their indices.
+    let mut el = EventLoop {
+        epoll: Epoll::new()?,
+        router,
+        log,
+        error_log,
+        config,
+        clients: HashMap::new(),
+        next_token: listeners.len() as u64,
+    };
+    for (i, l) in listeners.iter().enumerate() {
+        l.set_nonblocking(true)?;
+        el.epoll.add(l.as_raw_fd(), EPOLLIN, i as u64)?;
+    }
+
+    let mut events = Epoll::events(MAX_EVENTS);
+    let mut buf = vec![0; 16 * 1024];
+    let mut is_accepting = true;
+    let mut last_sweep = Instant::now();
+    loop {
+        if is_accepting && is_stopped() {
+            is_accepting = false;
+            for l in listeners {
+                el.epoll.delete(l.as_raw_fd())?;
+            }
+            for c in el.clients.values_mut() {
+                c.conn.set_closing();
+            }
+        }
+        if !is_accepting {
+            el.close_idle();
+            if el.clients.is_empty() {
+                return Ok(());
+            }
+        }
+
+        let n = el.epoll.wait(&mut events, POLL_INTERVAL_MS)?;
+        for e in &events[..n] {
+            match listeners.get(e.token() as usize) {
+                Some(l) => el.accept(l),
+                None => el.on_ready(e.token(), &mut buf),
+            }
+        }
+
+        if SWEEP_INTERVAL <= last_sweep.elapsed() {
+            el.sweep();
+            last_sweep = Instant::now();
+        }
+    }
+}
+
+#[cfg(test)]
+fn epoll_config() -> ServerConfig {
+    use server::Backend;
+
+    ServerConfig {
+        backend: Backend::Epoll,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn event_loop_keep_alive() {
+    use response::Response;
+    use router::Context;
+    use server::spawn_test_server;
+    use std::net::TcpStream;
+    use std::sync::atomic::Ordering;
+
+    let router = Router::new().get("/:n", |ctx: &Context| {
+        Response::ok().with_bytes(ctx.params.get("n").unwrap().to_string())
+    });
+    let (addr, stop, server) = spawn_test_server(router, epoll_config());
+
+    // The pipelined requests are answered in order on the same connection.
+    let mut s = TcpStream::connect(addr).unwrap();
+    s.write_all(b"GET /1 HTTP/1.1\r\nHost: a\r\n\r\nGET /2 HTTP/1.1\r\nHost: a\r\n\r\n")
+        .unwrap();
+    s.write_all(b"GET /3 HTTP/1.1\r\nHost: a\r\nConnection: close\r\n\r\n")
+        .unwrap();
+    let mut res = String::new();
+    s.read_to_string(&mut res).unwrap();
+    let bodies: Vec<&str> = res
+        .split("HTTP/1.1 200 OK\r\n")
+        .skip(1)
+        .map(|r| r.rsplit("\r\n\r\n").next().unwrap())
+        .collect();
+    assert_eq!(bodies, vec!["1", "2", "3"]);
+
+    stop.store(true, Ordering::SeqCst);
+    server.join().unwrap();
+}
+
+#[test]
+fn event_loop_many_idle_connections() {
+    use server::{get, spawn_test_server};
+    use std::net::TcpStream;
+    use std::sync::atomic::Ordering;
+    use std::thread;
+
+    let (addr, stop, server) = spawn_test_server(Router::new(), epoll_config());
+    let idle: Vec<TcpStream> = (0..200)
+        .map(|_| TcpStream::connect(addr).unwrap())
+        .collect();
+    assert!(get(addr, "/").starts_with("HTTP/1.1 404 Not Found\r\n"));
+
+    // The idle connections are closed by the stop.
+    let mut half = TcpStream::connect(addr).unwrap();
+    half.write_all(b"GET / HTTP/1.1\r\n").unwrap();
+    thread::sleep(Duration::from_millis(50));
+    stop.store(true, Ordering::SeqCst);
+    thread::sleep(Duration::from_millis(300));
+    for mut s in idle {
+        assert_eq!(s.read(&mut [0; 16]).unwrap(), 0);
+    }
+
+    // The request in flight is answered.
+    half.write_all(b"Host: a\r\n\r\n").unwrap();
+    let mut res = String::new();
+    half.read_to_string(&mut res).unwrap();
+    assert!(res.starts_with("HTTP/1.1 404 Not Found\r\n"));
+    assert!(res.contains("Connection: close\r\n"));
+    server.join().unwrap();
+}
+
+#[test]
+fn event_loop_large_body() {
+    use access_log::{AccessLog, LogFormat};
+    use log_file::{LogFile, SharedBuf};
+    use response::Response;
+    use router::Context;
+    use server::{get, Server};
+    use std::fs;
+    use std::net::TcpStream;
+    use std::sync::atomic::Ordering;
+
+    let path =
+        ::std::env::temp_dir().join(format!("http_server_large_body_{}", ::std::process::id()));
+    // Larger than the socket buffers too.
+    let data: Vec<u8> = (0..16 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+    fs::write(&path, &data).unwrap();
+    let file_path = path.clone();
+    let router = Router::new()
+        .get("/large", move |_: &Context| {
+            Response::ok()
+                .with_file(fs::File::open(&file_path).unwrap())
+                .unwrap()
+        })
+        .get("/endless", |_: &Context| {
+            Response::ok().with_stream(io::repeat(b'x'))
+        });
+    let buf = SharedBuf::default();
+    let server = Server::bind("127.0.0.1:0", router, epoll_config())
+        .unwrap()
+        .with_access_log(AccessLog::new(LogFormat::Common, LogFile::new(buf.clone())));
+    let (addr, stop) = (server.local_addr().unwrap(), server.stop_handle());
+    let server = ::std::thread::spawn(move || server.run().unwrap());
+
+    // The bodies larger than the buffer don't stall the other connections while the clients don't read.
+    let mut large = TcpStream::connect(addr).unwrap();
+    large
+        .write_all(b"GET /large HTTP/1.1\r\nHost: a\r\nConnection: close\r\n\r\n")
+        .unwrap();
+    let mut endless = TcpStream::connect(addr).unwrap();
+    endless
+        .write_all(b"GET /endless HTTP/1.1\r\nHost: a\r\n\r\n")
+        .unwrap();
+    let mut b = vec![0; 256 * 1024];
+    endless.read_exact(&mut b).unwrap();
+    assert!(get(addr, "/").starts_with("HTTP/1.1 404 Not Found\r\n"));
+    // The response is logged after its last byte is written.
+    let lines = buf.to_string();
+    assert!(lines.contains("\"GET / HTTP/1.1\" 404 "));
+    assert!(!lines.contains("/large"));
+
+    let mut res = Vec::new();
+    large.read_to_end(&mut res).unwrap();
+    let head_length = res.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+    assert!(res.starts_with(b"HTTP/1.1 200 OK\r\n"));
+    assert!(res[..head_length].ends_with(b"Content-Length: 16777216\r\nConnection: close\r\n\r\n"));
+    assert!(res[head_length..] == data[..]);
+    assert!(buf
+        .to_string()
+        .contains("\"GET /large HTTP/1.1\" 200 16777216"));
+
+    drop(endless);
+    stop.store(true, Ordering::SeqCst);
+    server.join().unwrap();
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn event_loop_reject() {
+    use server::{get, spawn_test_server};
+    use std::net::TcpStream;
+    use std::sync::atomic::Ordering;
+    use std::thread;
+
+    let config = ServerConfig {
+        max_connections: 2,
+        ..epoll_config()
+    };
+    let (addr, stop, server) = spawn_test_server(Router::new(), config);
+
+    // The connection over the limit is told to come back later.
+    let idle: Vec<TcpStream> = (0..2).map(|_| TcpStream::connect(addr).unwrap()).collect();
+    thread::sleep(Duration::from_millis(100));
+    let mut busy = TcpStream::connect(addr).unwrap();
+    let _ = busy.write_all(b"GET / HTTP/1.1\r\nHost: a\r\n\r\n");
+    let mut res = String::new();
+    let _ = busy.read_to_string(&mut res);
+    assert!(res.starts_with("HTTP/1.1 503 Service Unavailable\r\n"));
+    drop(idle);
+    thread::sleep(Duration::from_millis(100));
+    assert!(get(addr, "/").starts_with("HTTP/1.1 404 Not Found\r\n"));
+
+    stop.store(true, Ordering::SeqCst);
+    server.join().unwrap();
+}
+
+#[test]
+fn event_loop_flood() {
+    use server::{get, spawn_test_server};
+    use std::net::TcpStream;
+    use std::sync::atomic::Ordering;
+    use std::thread;
+
+    let (addr, stop, server) = spawn_test_server(Router::new(), epoll_config());
+
+    // The client which sends the empty lines forever is closed at the head limit.
+    let mut flood = TcpStream::connect(addr).unwrap();
+    let writer = {
+        let mut flood = flood.try_clone().unwrap();
+        thread::spawn(move || {
+            let lines = b"\r\n".repeat(32 * 1024);
+            while flood.write_all(&lines).is_ok() {}
+        })
+    };
+    let mut res = Vec::new();
+    let _ = flood.read_to_end(&mut res);
+    assert!(res.starts_with(b"HTTP/1.1 431 "));
+    writer.join().unwrap();
+    assert!(get(addr, "/").starts_with("HTTP/1.1 404 Not Found\r\n"));
+
+    stop.store(true, Ordering::SeqCst);
+    server.join().unwrap();
+}
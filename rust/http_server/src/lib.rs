@@ -0,0 +1,16 @@
+pub mod access_log;
+pub mod body;
+pub mod config;
+pub mod connection;
+pub mod date;
+pub mod error_log;
+mod event_loop;
+pub mod listener;
+pub mod log_file;
+pub mod parser;
+pub mod pool;
+pub mod response;
+pub mod router;
+pub mod server;
+pub mod static_files;
+mod sys;
@@ -0,0 +1,251 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::io::{Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+// The address like `127.0.0.1:8080`, `[::1]:8080` or `unix:/run/http.sock`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListenAddr {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+}
+
+impl FromStr for ListenAddr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ListenAddr, String> {
+        if let Some(path) = s.strip_prefix("unix:") {
+            if path.is_empty() {
+                return Err("empty unix socket path".to_string());
+            }
+            return Ok(ListenAddr::Unix(PathBuf::from(path)));
+        }
+        s.parse()
+            .map(ListenAddr::Tcp)
+            .map_err(|_| format!("invalid listen address: {}", s))
+    }
+}
+
+impl fmt::Display for ListenAddr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ListenAddr::Tcp(ref a) => write!(f, "{}", a),
+            ListenAddr::Unix(ref p) => write!(f, "unix:{}", p.display()),
+        }
+    }
+}
+
+pub enum Listener {
+    Tcp(TcpListener),
+    // The socket file is removed on drop.
+    Unix(UnixListener, PathBuf),
+}
+
+impl Listener {
+    pub fn bind(addr: &ListenAddr) -> io::Result<Listener> {
+        match *addr {
+            ListenAddr::Tcp(ref a) => TcpListener::bind(a).map(Listener::Tcp),
+            ListenAddr::Unix(ref path) => {
+                // The socket left by the previous process is replaced, the other files are not.
+                // The socket which accepts a connection belongs to a running process.
+                if fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_socket()) {
+                    match UnixStream::connect(path) {
+                        Err(ref e) if e.kind() == io::ErrorKind::ConnectionRefused => {
+                            fs::remove_file(path)?
+                        }
+                        _ => {
+                            return Err(io::Error::new(
+                                io::ErrorKind::AddrInUse,
+                                format!("{} is in use", path.display()),
+                            ))
+                        }
+                    }
+                }
+                UnixListener::bind(path).map(|l| Listener::Unix(l, path.clone()))
+            }
+        }
+    }
+
+    // The bound address, the TCP port is decided here if 0 is given.
+    pub fn local_addr(&self) -> io::Result<ListenAddr> {
+        match *self {
+            Listener::Tcp(ref l) => l.local_addr().map(ListenAddr::Tcp),
+            Listener::Unix(_, ref path) => Ok(ListenAddr::Unix(path.clone())),
+        }
+    }
+
+    pub fn accept(&self) -> io::Result<Stream> {
+        match *self {
+            Listener::Tcp(ref l) => l.accept().map(|(s, _)| Stream::Tcp(s)),
+            Listener::Unix(ref l, _) => l.accept().map(|(s, _)| Stream::Unix(s)),
+        }
+    }
+
+    pub fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        match *self {
+            Listener::Tcp(ref l) => l.set_nonblocking(nonblocking),
+            Listener::Unix(ref l, _) => l.set_nonblocking(nonblocking),
+        }
+    }
+}
+
+impl AsRawFd for Listener {
+    fn as_raw_fd(&self) -> RawFd {
+        match *self {
+            Listener::Tcp(ref l) => l.as_raw_fd(),
+            Listener::Unix(ref l, _) => l.as_raw_fd(),
+        }
+    }
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        if let Listener::Unix(_, ref path) = *self {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+pub enum Stream {
+    Tcp(TcpStream),
+    Unix(UnixStream),
+}
+
+impl Stream {
+    // None for the unix socket.
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        match *self {
+            Stream::Tcp(ref s) => s.peer_addr().ok(),
+            Stream::Unix(_) => None,
+        }
+    }
+
+    pub fn set_read_timeout(&self, t: Option<Duration>) -> io::Result<()> {
+        match *self {
+            Stream::Tcp(ref s) => s.set_read_timeout(t),
+            Stream::Unix(ref s) => s.set_read_timeout(t),
+        }
+    }
+
+    pub fn set_write_timeout(&self, t: Option<Duration>) -> io::Result<()> {
+        match *self {
+            Stream::Tcp(ref s) => s.set_write_timeout(t),
+            Stream::Unix(ref s) => s.set_write_timeout(t),
+        }
+    }
+
+    pub fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        match *self {
+            Stream::Tcp(ref s) => s.set_nonblocking(nonblocking),
+            Stream::Unix(ref s) => s.set_nonblocking(nonblocking),
+        }
+    }
+
+    // The unix socket has no delay anyway.
+    pub fn set_nodelay(&self, nodelay: bool) -> io::Result<()> {
+        match *self {
+            Stream::Tcp(ref s) => s.set_nodelay(nodelay),
+            Stream::Unix(_) => Ok(()),
+        }
+    }
+
+    pub fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        match *self {
+            Stream::Tcp(ref s) => s.shutdown(how),
+            Stream::Unix(ref s) => s.shutdown(how),
+        }
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match *self {
+            Stream::Tcp(ref mut s) => s.read(buf),
+            Stream::Unix(ref mut s) => s.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match *self {
+            Stream::Tcp(ref mut s) => s.write(buf),
+            Stream::Unix(ref mut s) => s.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match *self {
+            Stream::Tcp(ref mut s) => s.flush(),
+            Stream::Unix(ref mut s) => s.flush(),
+        }
+    }
+}
+
+impl AsRawFd for Stream {
+    fn as_raw_fd(&self) -> RawFd {
+        match *self {
+            Stream::Tcp(ref s) => s.as_raw_fd(),
+            Stream::Unix(ref s) => s.as_raw_fd(),
+        }
+    }
+}
+
+#[test]
+fn listen_addr_parse() {
+    assert_eq!(
+        "127.0.0.1:8080".parse(),
+        Ok(ListenAddr::Tcp(SocketAddr::from(([127, 0, 0, 1], 8080))))
+    );
+    assert_eq!(
+        "[::1]:0".parse(),
+        Ok(ListenAddr::Tcp("[::1]:0".parse().unwrap()))
+    );
+    assert_eq!(
+        "unix:/tmp/a.sock".parse(),
+        Ok(ListenAddr::Unix(PathBuf::from("/tmp/a.sock")))
+    );
+    assert!("localhost:80".parse::<ListenAddr>().is_err());
+    assert!("unix:".parse::<ListenAddr>().is_err());
+    assert_eq!(
+        "unix:/tmp/a.sock"
+            .parse::<ListenAddr>()
+            .unwrap()
+            .to_string(),
+        "unix:/tmp/a.sock"
+    );
+}
+
+#[test]
+fn listener_unix() {
+    let path = ::std::env::temp_dir().join(format!("http_server_{}.sock", ::std::process::id()));
+    let l = Listener::bind(&ListenAddr::Unix(path.clone())).unwrap();
+    let mut c = UnixStream::connect(&path).unwrap();
+    let mut s = l.accept().unwrap();
+    assert_eq!(s.peer_addr(), None);
+    c.write_all(b"x").unwrap();
+    let mut b = [0; 1];
+    s.read_exact(&mut b).unwrap();
+    assert_eq!(&b, b"x");
+
+    // The socket of the running listener is kept.
+    drop(s);
+    let e = Listener::bind(&ListenAddr::Unix(path.clone())).err();
+    assert_eq!(e.map(|e| e.kind()), Some(io::ErrorKind::AddrInUse));
+    assert!(path.exists());
+    drop(l);
+    assert!(!path.exists());
+
+    // The stale socket is replaced.
+    drop(UnixListener::bind(&path).unwrap());
+    let l = Listener::bind(&ListenAddr::Unix(path.clone())).unwrap();
+    drop(l);
+    assert!(!path.exists());
+}
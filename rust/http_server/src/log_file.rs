@@ -0,0 +1,97 @@
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+fn open_append(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+// The line oriented output of a log, the file can be reopened after it is rotated.
+pub struct LogFile {
+    // None if it is not a file.
+    path: Option<PathBuf>,
+    out: Mutex<Box<dyn Write + Send>>,
+}
+
+impl LogFile {
+    pub fn new<W: Write + Send + 'static>(out: W) -> LogFile {
+        LogFile {
+            path: None,
+            out: Mutex::new(Box::new(out)),
+        }
+    }
+
+    // The lines are appended to the file.
+    pub fn open<P: Into<PathBuf>>(path: P) -> io::Result<LogFile> {
+        let path = path.into();
+        let file = open_append(&path)?;
+        Ok(LogFile {
+            path: Some(path),
+            out: Mutex::new(Box::new(file)),
+        })
+    }
+
+    // The failure of logging doesn't stop serving.
+    pub fn write_line(&self, line: &str) {
+        let mut out = self.out.lock().unwrap();
+        let _ = writeln!(out, "{}", line).and_then(|_| out.flush());
+    }
+
+    // Open the path again, the old file is kept if it fails.
+    pub fn reopen(&self) -> io::Result<()> {
+        if let Some(ref path) = self.path {
+            let file = open_append(path)?;
+            *self.out.lock().unwrap() = Box::new(file);
+        }
+        Ok(())
+    }
+}
+
+// The writer for the tests, the written lines are read by the clone.
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub(crate) struct SharedBuf(::std::sync::Arc<Mutex<Vec<u8>>>);
+
+#[cfg(test)]
+impl Write for SharedBuf {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+impl ::std::fmt::Display for SharedBuf {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        write!(f, "{}", String::from_utf8_lossy(&self.0.lock().unwrap()))
+    }
+}
+
+#[test]
+fn log_file_reopen() {
+    use std::fs;
+
+    let dir = ::std::env::temp_dir().join(format!("http_server_log_{}", ::std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("access.log");
+
+    let log = LogFile::open(&path).unwrap();
+    log.write_line("a");
+    fs::rename(&path, dir.join("access.log.1")).unwrap();
+    log.write_line("b");
+    log.reopen().unwrap();
+    log.write_line("c");
+
+    assert_eq!(
+        fs::read_to_string(dir.join("access.log.1")).unwrap(),
+        "a\nb\n"
+    );
+    assert_eq!(fs::read_to_string(&path).unwrap(), "c\n");
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -1,57 +1,70 @@
-mod parser;
-
-use std::net::TcpListener;
-use std::thread;
-use std::io::{Read, Write};
-use std::io;
-
-
-fn server_start() -> io::Result<()> {
-    let lis = TcpListener::bind("127.0.0.1:8080")?;
-
-    for stream in lis.incoming() {
-        let mut stream = match stream {
-            Ok(stream) => stream,
-            Err(e) => {
-                println!("An error occured while accepting a connection: {}", e);
-                continue;
-            }
-        };
-
-        let _ = thread::spawn(
-            move || -> io::Result<()> {
-                use parser::ParseResult::*;
-                let mut buf = Vec::new();
-                loop {
-                    let mut b = [0; 1024];
-                    let n = stream.read(&mut b)?;
-
-                    if n == 0 {
-                        return Ok(());
-                    }
-
-                    buf.extend_from_slice(&b[0..n]);
-                    match parser::parse(buf.as_slice()) {
-                        Partial => continue,
-                        Error => {
-                            return Ok(());
-                        },
-                        Complete(req) => {
-                            write!(stream, "OK {}\r\n", req.0)?;
-                            return Ok(());
-                        },
-                    };
-                }
-            }
-        );
+extern crate http_server;
+
+use std::env;
+use std::path::PathBuf;
+use std::process;
+use http_server::access_log::AccessLog;
+use http_server::config::{Config, USAGE};
+use http_server::error_log::ErrorLog;
+use http_server::log_file::LogFile;
+use http_server::response::Response;
+use http_server::router::{Context, Router};
+use http_server::server::{self, Server};
+use http_server::static_files::StaticFiles;
+
+
+fn router(root: Option<&PathBuf>) -> Router {
+    let router = Router::new()
+        .get("/", |_: &Context| Response::ok().with_bytes("http_server\n"))
+        .get("/hello/:name", |ctx: &Context| {
+            Response::ok().with_bytes(format!("Hello, {}!\n", ctx.params.get("name").unwrap()))
+        })
+        .post("/echo", |ctx: &Context| Response::ok().with_bytes(ctx.body.to_vec()));
+
+    match root {
+        Some(root) => router.get("/files/*path", StaticFiles::new(root.clone())),
+        None => router,
+    }
+}
+
+fn open_log(path: Option<&PathBuf>) -> Result<Option<LogFile>, String> {
+    match path {
+        Some(path) => LogFile::open(path).map(Some).map_err(|e| format!("failed to open {}: {}", path.display(), e)),
+        None => Ok(None),
+    }
+}
+
+fn server_start() -> Result<(), String> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.iter().any(|a| a == "-h" || a == "--help") {
+        print!("{}", USAGE);
+        return Ok(());
+    }
+
+    let config = Config::from_args(&args)?;
+    let access_log = match open_log(config.access_log.as_ref())? {
+        Some(file) => AccessLog::new(config.access_log_format, file),
+        None => AccessLog::stdout(config.access_log_format),
+    };
+    let error_log = open_log(config.error_log.as_ref())?.map_or_else(ErrorLog::stderr, ErrorLog::new);
+    let server = Server::bind_all(&config.listen_addrs(), router(config.root.as_ref()), config.server)
+        .map_err(|e| e.to_string())?
+        .with_access_log(access_log)
+        .with_error_log(error_log);
+    for addr in server.local_addrs().map_err(|e| e.to_string())? {
+        eprintln!("listening on {}", addr);
     }
 
-    Ok(())
+    server::handle_signals();
+    server.run().map_err(|e| e.to_string())
 }
 
 fn main() {
     match server_start() {
         Ok(_) => (),
-        Err(e) => println!("{:?}", e),
+        Err(e) => {
+            eprintln!("http_server: {}", e);
+            process::exit(1);
+        },
     }
 }
@@ -1,67 +1,475 @@
 use std::str::from_utf8;
 
+#[derive(Debug, PartialEq, Eq)]
 pub enum ParseResult<T> {
     Complete(T),
     Partial,
-    Error,
+    Error(ParseError),
 }
 
 impl<T> ParseResult<T> {
-    fn is_complete(&self) -> bool {
+    pub fn is_complete(&self) -> bool {
+        matches!(*self, ParseResult::Complete(_))
+    }
+
+    pub fn is_partial(&self) -> bool {
+        matches!(*self, ParseResult::Partial)
+    }
+}
+
+impl<T> From<Result<T, ParseError>> for ParseResult<T> {
+    fn from(r: Result<T, ParseError>) -> Self {
         use self::ParseResult::*;
+        match r {
+            Ok(t) => Complete(t),
+            Err(e) => Error(e),
+        }
+    }
+}
+
+// Why the request was rejected, each of them corresponds to a status code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseError {
+    BadRequest,
+    UriTooLong,
+    HeaderTooLarge,
+    PayloadTooLarge,
+    NotImplemented,
+    VersionNotSupported,
+}
+
+impl ParseError {
+    pub fn status_code(&self) -> u16 {
         match *self {
-            Complete(_) => true,
-            _ => false
+            ParseError::BadRequest => 400,
+            ParseError::UriTooLong => 414,
+            ParseError::HeaderTooLarge => 431,
+            ParseError::PayloadTooLarge => 413,
+            ParseError::NotImplemented => 501,
+            ParseError::VersionNotSupported => 505,
         }
     }
+}
 
-    fn is_partial(&self) -> bool {
-        use self::ParseResult::*;
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Method {
+    Get,
+    Head,
+    Post,
+    Put,
+    Delete,
+    Connect,
+    Options,
+    Trace,
+    Patch,
+}
+
+impl Method {
+    // The method names are case-sensitive.
+    pub fn from_bytes(s: &[u8]) -> Option<Method> {
+        use self::Method::*;
+        Some(match s {
+            b"GET" => Get,
+            b"HEAD" => Head,
+            b"POST" => Post,
+            b"PUT" => Put,
+            b"DELETE" => Delete,
+            b"CONNECT" => Connect,
+            b"OPTIONS" => Options,
+            b"TRACE" => Trace,
+            b"PATCH" => Patch,
+            _ => return None,
+        })
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        use self::Method::*;
         match *self {
-            Partial => true,
-            _ => false
+            Get => "GET",
+            Head => "HEAD",
+            Post => "POST",
+            Put => "PUT",
+            Delete => "DELETE",
+            Connect => "CONNECT",
+            Options => "OPTIONS",
+            Trace => "TRACE",
+            Patch => "PATCH",
         }
     }
 }
 
-impl<T, E> From<Result<T, E>> for ParseResult<T> {
-    fn from(r: Result<T, E>) -> Self {
-        use self::ParseResult::*;
-        match r {
-            Ok(t) => Complete(t),
-            Err(_) => Error,
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Version {
+    Http09,
+    Http10,
+    Http11,
+}
+
+impl Version {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Version::Http09 => "HTTP/0.9",
+            Version::Http10 => "HTTP/1.0",
+            Version::Http11 => "HTTP/1.1",
         }
     }
 }
 
-pub struct Request<'a>(pub &'a str);
+// The four forms of request-target in RFC 7230 section 5.3.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target<'a> {
+    // /where?q=now
+    Origin {
+        path: &'a str,
+        query: Option<&'a str>,
+    },
+    // http://www.example.org/pub/WWW/TheProject.html
+    Absolute {
+        scheme: &'a str,
+        authority: &'a str,
+        path: &'a str,
+        query: Option<&'a str>,
+    },
+    // www.example.com:80, only for CONNECT.
+    Authority(&'a str),
+    // *, only for OPTIONS.
+    Asterisk,
+}
 
-pub fn parse(mut buf: &[u8]) -> ParseResult<Request> {
-    use self::ParseResult::*;
+fn split_query(s: &str) -> (&str, Option<&str>) {
+    match s.find('?') {
+        Some(i) => (&s[..i], Some(&s[i + 1..])),
+        None => (s, None),
+    }
+}
+
+impl<'a> Target<'a> {
+    fn parse(s: &'a str, method: Method) -> Option<Target<'a>> {
+        if s.is_empty() || s.contains('#') || !s.bytes().all(|b| (b'!'..=b'~').contains(&b)) {
+            return None;
+        }
+
+        let target = if method == Method::Connect {
+            if s.contains('/') || !s.contains(':') {
+                return None;
+            }
+            Target::Authority(s)
+        } else if s == "*" {
+            Target::Asterisk
+        } else if s.starts_with('/') {
+            let (path, query) = split_query(s);
+            Target::Origin { path, query }
+        } else {
+            let i = s.find("://")?;
+            let scheme = &s[..i];
+            if scheme.is_empty() || !scheme.bytes().all(|b| b.is_ascii_alphabetic()) {
+                return None;
+            }
+            let rest = &s[i + 3..];
+            let end = rest.find(['/', '?']).unwrap_or(rest.len());
+            let (authority, rest) = rest.split_at(end);
+            if authority.is_empty() {
+                return None;
+            }
+            let (path, query) = split_query(rest);
+            Target::Absolute {
+                scheme,
+                authority,
+                path: if path.is_empty() { "/" } else { path },
+                query,
+            }
+        };
+
+        if target == Target::Asterisk && method != Method::Options {
+            return None;
+        }
+        Some(target)
+    }
+}
 
-    let get = b"GET ";
-    let end = b"\r\n";
+// The header fields in the received order.
+// The names are compared case-insensitively.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Headers<'a> {
+    fields: Vec<(&'a str, &'a str)>,
+}
+
+impl<'a> Headers<'a> {
+    pub fn new() -> Headers<'a> {
+        Headers { fields: Vec::new() }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&'a str> {
+        self.get_all(name).next()
+    }
+
+    pub fn get_all<'b>(&'b self, name: &'b str) -> impl Iterator<Item = &'a str> + 'b {
+        self.fields
+            .iter()
+            .filter(move |&&(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|&(_, v)| v)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    // Whether the comma separated values of the fields contain the token like `Connection: close`.
+    pub fn contains_token(&self, name: &str, token: &str) -> bool {
+        self.get_all(name)
+            .flat_map(|v| v.split(','))
+            .any(|t| t.trim().eq_ignore_ascii_case(token))
+    }
+
+    pub fn iter<'b>(&'b self) -> impl Iterator<Item = (&'a str, &'a str)> + 'b {
+        self.fields.iter().cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+}
 
-    if !buf.starts_with(get) {
-        return Error;
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request<'a> {
+    pub method: Method,
+    pub target: Target<'a>,
+    pub version: Version,
+    pub headers: Headers<'a>,
+    // The number of bytes of the request line and the headers including the empty line.
+    // The body or the next request follows them.
+    pub head_length: usize,
+}
+
+impl<'a> Request<'a> {
+    // "*" for the asterisk-form and "" for the authority-form.
+    pub fn path(&self) -> &'a str {
+        match self.target {
+            Target::Origin { path, .. } | Target::Absolute { path, .. } => path,
+            Target::Authority(_) => "",
+            Target::Asterisk => "*",
+        }
+    }
+
+    pub fn query(&self) -> Option<&'a str> {
+        match self.target {
+            Target::Origin { query, .. } | Target::Absolute { query, .. } => query,
+            _ => None,
+        }
+    }
+
+    // HTTP/1.1 is persistent unless `Connection: close`, HTTP/1.0 is not unless `keep-alive`.
+    pub fn wants_keep_alive(&self) -> bool {
+        match self.version {
+            Version::Http09 => false,
+            Version::Http10 => self.headers.contains_token("Connection", "keep-alive"),
+            Version::Http11 => !self.headers.contains_token("Connection", "close"),
+        }
     }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    pub max_request_line: usize,
+    // The request line and all the headers.
+    pub max_head: usize,
+    pub max_headers: usize,
+    // The decoded body.
+    pub max_body: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits {
+            max_request_line: 8 * 1024,
+            max_head: 64 * 1024,
+            max_headers: 100,
+            max_body: 8 * 1024 * 1024,
+        }
+    }
+}
+
+pub(crate) fn is_token(s: &[u8]) -> bool {
+    !s.is_empty()
+        && s.iter()
+            .all(|&b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
 
-    buf = &buf[get.len()..];
-    if buf.ends_with(end) {
-        buf = &buf[0..buf.len() - end.len()]
+// The line ends with LF, the preceding CR is removed.
+pub(crate) fn next_line(buf: &[u8], start: usize) -> Option<(&[u8], usize)> {
+    let i = start + buf[start..].iter().position(|&b| b == b'\n')?;
+    let line = &buf[start..i];
+    let line = if line.ends_with(b"\r") {
+        &line[..line.len() - 1]
     } else {
-        return Partial;
+        line
+    };
+    Some((line, i + 1))
+}
+
+fn parse_version(s: &[u8]) -> Result<Version, ParseError> {
+    match s {
+        b"HTTP/1.1" => Ok(Version::Http11),
+        b"HTTP/1.0" => Ok(Version::Http10),
+        [b'H', b'T', b'T', b'P', b'/', major, b'.', minor]
+            if major.is_ascii_digit() && minor.is_ascii_digit() =>
+        {
+            Err(ParseError::VersionNotSupported)
+        }
+        _ => Err(ParseError::BadRequest),
+    }
+}
+
+fn parse_header_field(line: &[u8]) -> Option<(&str, &str)> {
+    let i = line.iter().position(|&b| b == b':')?;
+    // No whitespace is allowed between the name and the colon.
+    if !is_token(&line[..i]) {
+        return None;
+    }
+
+    let is_ows = |b: &u8| *b == b' ' || *b == b'\t';
+    let value = &line[i + 1..];
+    let start = value.iter().position(|b| !is_ows(b)).unwrap_or(value.len());
+    let end = value
+        .iter()
+        .rposition(|b| !is_ows(b))
+        .map_or(start, |e| e + 1);
+    let value = &value[start..end];
+    if value.iter().any(|&b| (b < b' ' && b != b'\t') || b == 0x7F) {
+        return None;
+    }
+
+    // The values are required to be UTF-8 so that they can be handled as str.
+    Some((from_utf8(&line[..i]).ok()?, from_utf8(value).ok()?))
+}
+
+// Parse the header fields from pos until the empty line.
+// It returns the fields and the position after the empty line.
+// The size from start is limited by max_head, the trailers of a chunked body are parsed by this too.
+pub fn parse_header_fields<'a>(
+    buf: &'a [u8],
+    mut pos: usize,
+    start: usize,
+    limits: &Limits,
+) -> ParseResult<(Headers<'a>, usize)> {
+    use self::ParseResult::*;
+
+    let mut headers = Headers::new();
+    loop {
+        let (line, next) = match next_line(buf, pos) {
+            Some(l) => l,
+            None if limits.max_head < buf.len() - start => {
+                return Error(ParseError::HeaderTooLarge)
+            }
+            None => return Partial,
+        };
+        if limits.max_head < next - start {
+            return Error(ParseError::HeaderTooLarge);
+        }
+        pos = next;
+
+        if line.is_empty() {
+            return Complete((headers, pos));
+        }
+        // obs-fold is rejected.
+        if line[0] == b' ' || line[0] == b'\t' {
+            return Error(ParseError::BadRequest);
+        }
+        if headers.len() == limits.max_headers {
+            return Error(ParseError::HeaderTooLarge);
+        }
+        match parse_header_field(line) {
+            Some(f) => headers.fields.push(f),
+            None => return Error(ParseError::BadRequest),
+        }
     }
+}
 
-    // from_utf8で、&[u8]から&strが作れる。データのコピーはしない
-    // ただし失敗するかもしれないので、返り値はResultに包まれる
-    from_utf8(buf)
-        // タプル構造体は関数としても扱える
-        // Result<&str, Utf8Error> -> Result<Request, Utf8Error>
-        .map(Request)
-        // Fromを実装したのでIntoのintoメソッドが自動で実装されている
-        // Result<Request, Utf8Error> -> ParseResult<Request>
-        .into()
+pub fn parse(buf: &[u8]) -> ParseResult<Request<'_>> {
+    parse_with_limits(buf, &Limits::default())
+}
+
+// Parse the request line and the headers.
+// It is Partial until the empty line after the headers comes, so the caller appends the received
+// bytes to the buffer and calls this again.
+pub fn parse_with_limits<'a>(buf: &'a [u8], limits: &Limits) -> ParseResult<Request<'a>> {
+    use self::ParseResult::*;
+
+    // The empty lines before the request line are ignored.
+    let mut start = 0;
+    while let Some((b"", next)) = next_line(buf, start) {
+        start = next;
+    }
+
+    let (line, pos) = match next_line(buf, start) {
+        Some(l) => l,
+        None if limits.max_request_line < buf.len() - start => {
+            return Error(ParseError::UriTooLong)
+        }
+        None => return Partial,
+    };
+    if limits.max_request_line < line.len() {
+        return Error(ParseError::UriTooLong);
+    }
+
+    let parts = line.split(|&b| b == b' ').collect::<Vec<_>>();
+    if parts.len() < 2 || 3 < parts.len() || !is_token(parts[0]) {
+        return Error(ParseError::BadRequest);
+    }
+    let method = match Method::from_bytes(parts[0]) {
+        Some(m) => m,
+        None => return Error(ParseError::NotImplemented),
+    };
+    let target = match from_utf8(parts[1])
+        .ok()
+        .and_then(|s| Target::parse(s, method))
+    {
+        Some(t) => t,
+        None => return Error(ParseError::BadRequest),
+    };
+
+    // HTTP/0.9 has no version and no headers.
+    if parts.len() == 2 {
+        return if method == Method::Get {
+            Complete(Request {
+                method,
+                target,
+                version: Version::Http09,
+                headers: Headers::new(),
+                head_length: pos,
+            })
+        } else {
+            Error(ParseError::BadRequest)
+        };
+    }
+    let version = match parse_version(parts[2]) {
+        Ok(v) => v,
+        Err(e) => return Error(e),
+    };
+
+    let (headers, pos) = match parse_header_fields(buf, pos, start, limits) {
+        Complete(h) => h,
+        Partial => return Partial,
+        Error(e) => return Error(e),
+    };
+
+    // HTTP/1.1 requires exactly one Host.
+    let count_hosts = headers.get_all("Host").count();
+    if 1 < count_hosts || (version == Version::Http11 && count_hosts == 0) {
+        return Error(ParseError::BadRequest);
+    }
+
+    Complete(Request {
+        method,
+        target,
+        version,
+        headers,
+        head_length: pos,
+    })
 }
 
 #[test]
@@ -92,3 +500,160 @@ fn http09_post_failure() {
     let res = parse(req);
     assert!(res.is_complete());
 }
+
+#[cfg(test)]
+fn complete(buf: &[u8]) -> Request<'_> {
+    match parse(buf) {
+        ParseResult::Complete(req) => req,
+        r => panic!("{:?}", r),
+    }
+}
+
+#[test]
+fn http11_get_with_headers() {
+    let buf = b"GET /foo?a=1&b=2 HTTP/1.1\r\nHost: example.com\r\nAccept:  text/html \r\nX-A: 1\r\nx-a: 2\r\n\r\nrest";
+    let req = complete(buf);
+    assert_eq!(req.method, Method::Get);
+    assert_eq!(req.version, Version::Http11);
+    assert_eq!(req.path(), "/foo");
+    assert_eq!(req.query(), Some("a=1&b=2"));
+    assert_eq!(req.headers.len(), 4);
+    assert_eq!(req.headers.get("host"), Some("example.com"));
+    assert_eq!(req.headers.get("ACCEPT"), Some("text/html"));
+    assert_eq!(
+        req.headers.get_all("x-a").collect::<Vec<_>>(),
+        vec!["1", "2"]
+    );
+    assert_eq!(&buf[req.head_length..], b"rest");
+}
+
+#[test]
+fn http11_partial_until_empty_line() {
+    let buf = b"GET / HTTP/1.1\r\nHost: example.com\r\n\r\n";
+    for i in 0..buf.len() {
+        assert!(parse(&buf[..i]).is_partial(), "{}", i);
+    }
+    assert!(parse(buf).is_complete());
+}
+
+#[test]
+fn http11_all_methods() {
+    for m in &[
+        "GET", "HEAD", "POST", "PUT", "DELETE", "OPTIONS", "TRACE", "PATCH",
+    ] {
+        let buf = format!("{} / HTTP/1.1\r\nHost: a\r\n\r\n", m);
+        assert_eq!(complete(buf.as_bytes()).method.as_str(), *m);
+    }
+    assert_eq!(
+        parse(b"BREW / HTTP/1.1\r\nHost: a\r\n\r\n"),
+        ParseResult::Error(ParseError::NotImplemented)
+    );
+    assert_eq!(
+        parse(b"get / HTTP/1.1\r\nHost: a\r\n\r\n"),
+        ParseResult::Error(ParseError::NotImplemented)
+    );
+}
+
+#[test]
+fn http11_target_forms() {
+    let req = complete(b"GET http://example.com:8080/a/b?c HTTP/1.1\r\nHost: example.com\r\n\r\n");
+    assert_eq!(
+        req.target,
+        Target::Absolute {
+            scheme: "http",
+            authority: "example.com:8080",
+            path: "/a/b",
+            query: Some("c"),
+        }
+    );
+    let req = complete(b"GET http://example.com HTTP/1.1\r\nHost: example.com\r\n\r\n");
+    assert_eq!(req.path(), "/");
+
+    let req = complete(b"CONNECT example.com:443 HTTP/1.1\r\nHost: example.com:443\r\n\r\n");
+    assert_eq!(req.target, Target::Authority("example.com:443"));
+    let req = complete(b"OPTIONS * HTTP/1.1\r\nHost: a\r\n\r\n");
+    assert_eq!(req.target, Target::Asterisk);
+
+    for buf in &[
+        &b"GET * HTTP/1.1\r\nHost: a\r\n\r\n"[..],
+        b"CONNECT / HTTP/1.1\r\nHost: a\r\n\r\n",
+        b"GET foo HTTP/1.1\r\nHost: a\r\n\r\n",
+        b"GET /a#b HTTP/1.1\r\nHost: a\r\n\r\n",
+        b"GET  / HTTP/1.1\r\nHost: a\r\n\r\n",
+    ] {
+        assert_eq!(parse(buf), ParseResult::Error(ParseError::BadRequest));
+    }
+}
+
+#[test]
+fn http11_versions() {
+    assert_eq!(complete(b"GET / HTTP/1.0\r\n\r\n").version, Version::Http10);
+    assert_eq!(
+        parse(b"GET / HTTP/2.0\r\n\r\n"),
+        ParseResult::Error(ParseError::VersionNotSupported)
+    );
+    assert_eq!(
+        parse(b"GET / HTTP/1\r\n\r\n"),
+        ParseResult::Error(ParseError::BadRequest)
+    );
+    // Host is required only by HTTP/1.1.
+    assert_eq!(
+        parse(b"GET / HTTP/1.1\r\n\r\n"),
+        ParseResult::Error(ParseError::BadRequest)
+    );
+    assert_eq!(
+        parse(b"GET / HTTP/1.0\r\nHost: a\r\nHost: b\r\n\r\n"),
+        ParseResult::Error(ParseError::BadRequest)
+    );
+}
+
+#[test]
+fn http11_invalid_headers() {
+    for buf in &[
+        &b"GET / HTTP/1.1\r\nHost: a\r\nX-Folded: a\r\n b\r\n\r\n"[..],
+        b"GET / HTTP/1.1\r\nHost: a\r\nX-Space : a\r\n\r\n",
+        b"GET / HTTP/1.1\r\nHost: a\r\nNo colon\r\n\r\n",
+        b"GET / HTTP/1.1\r\nHost: a\r\nX-Ctl: a\x01b\r\n\r\n",
+    ] {
+        assert_eq!(parse(buf), ParseResult::Error(ParseError::BadRequest));
+    }
+}
+
+#[test]
+fn http11_limits() {
+    let limits = Limits {
+        max_request_line: 16,
+        max_head: 48,
+        max_headers: 2,
+        ..Default::default()
+    };
+    assert_eq!(
+        parse_with_limits(b"GET /0123456789abcdef", &limits),
+        ParseResult::Error(ParseError::UriTooLong)
+    );
+    assert!(parse_with_limits(b"GET / HTTP/1.1\r\nHost: a\r\n", &limits).is_partial());
+    assert_eq!(
+        parse_with_limits(
+            b"GET / HTTP/1.1\r\nHost: a\r\nX-Long: 0123456789abcdef",
+            &limits
+        ),
+        ParseResult::Error(ParseError::HeaderTooLarge)
+    );
+    assert_eq!(
+        parse_with_limits(
+            b"GET / HTTP/1.1\r\nHost: a\r\nA: 1\r\nB: 2\r\n\r\n",
+            &limits
+        ),
+        ParseResult::Error(ParseError::HeaderTooLarge)
+    );
+}
+
+#[test]
+fn http11_leading_empty_lines_and_bare_lf() {
+    let buf = b"\r\n\r\nGET / HTTP/1.1\nHost: a\nConnection: keep-alive, Close\n\n";
+    let req = complete(buf);
+    assert_eq!(req.headers.get("Host"), Some("a"));
+    assert_eq!(req.head_length, buf.len());
+    assert!(req.headers.contains_token("connection", "close"));
+    assert!(!req.headers.contains_token("connection", "upgrade"));
+}
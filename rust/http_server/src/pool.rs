@@ -0,0 +1,140 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::thread::JoinHandle;
+
+struct State<T> {
+    items: VecDeque<T>,
+    is_closed: bool,
+}
+
+struct Queue<T> {
+    state: Mutex<State<T>>,
+    cond: Condvar,
+    capacity: usize,
+}
+
+impl<T> Queue<T> {
+    // None after the queue is closed and emptied.
+    fn pop(&self) -> Option<T> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(item) = state.items.pop_front() {
+                return Some(item);
+            }
+            if state.is_closed {
+                return None;
+            }
+            state = self.cond.wait(state).unwrap();
+        }
+    }
+}
+
+// The fixed number of the threads which handle the items in the bounded queue.
+pub struct WorkerPool<T> {
+    queue: Arc<Queue<T>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl<T: Send + 'static> WorkerPool<T> {
+    pub fn new<F>(size: usize, capacity: usize, f: F) -> WorkerPool<T>
+    where
+        F: Fn(T) + Send + Sync + 'static,
+    {
+        let queue = Arc::new(Queue {
+            state: Mutex::new(State {
+                items: VecDeque::with_capacity(capacity),
+                is_closed: false,
+            }),
+            cond: Condvar::new(),
+            capacity,
+        });
+        let f = Arc::new(f);
+
+        let workers = (0..size.max(1))
+            .map(|i| {
+                let queue = queue.clone();
+                let f = f.clone();
+                thread::Builder::new()
+                    .name(format!("worker-{}", i))
+                    .spawn(move || {
+                        while let Some(item) = queue.pop() {
+                            f(item);
+                        }
+                    })
+                    .expect("failed to spawn a worker")
+            })
+            .collect();
+
+        WorkerPool { queue, workers }
+    }
+
+    // The item is given back if the queue is full.
+    pub fn try_push(&self, item: T) -> Result<(), T> {
+        let mut state = self.queue.state.lock().unwrap();
+        if state.is_closed || self.queue.capacity <= state.items.len() {
+            return Err(item);
+        }
+        state.items.push_back(item);
+        self.queue.cond.notify_one();
+        Ok(())
+    }
+
+    pub fn size(&self) -> usize {
+        self.workers.len()
+    }
+
+    // Wait for the workers after they handle all the queued items.
+    pub fn join(self) {
+        self.queue.state.lock().unwrap().is_closed = true;
+        self.queue.cond.notify_all();
+        for w in self.workers {
+            let _ = w.join();
+        }
+    }
+}
+
+#[test]
+fn pool_handles_all_items() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let sum = Arc::new(AtomicUsize::new(0));
+    let s = sum.clone();
+    let pool = WorkerPool::new(4, 100, move |n: usize| {
+        s.fetch_add(n, Ordering::SeqCst);
+    });
+    assert_eq!(pool.size(), 4);
+    for i in 1..=100 {
+        while pool.try_push(i).is_err() {
+            thread::yield_now();
+        }
+    }
+    pool.join();
+    assert_eq!(sum.load(Ordering::SeqCst), 5050);
+}
+
+#[test]
+fn pool_rejects_when_full() {
+    use std::sync::mpsc;
+
+    let (tx, rx) = mpsc::channel::<()>();
+    let rx = Mutex::new(rx);
+    let (started_tx, started_rx) = mpsc::channel();
+    let started_tx = Mutex::new(started_tx);
+    let pool = WorkerPool::new(1, 2, move |_: usize| {
+        started_tx.lock().unwrap().send(()).unwrap();
+        rx.lock().unwrap().recv().unwrap();
+    });
+
+    // One is running and two are waiting.
+    assert!(pool.try_push(0).is_ok());
+    started_rx.recv().unwrap();
+    assert!(pool.try_push(1).is_ok());
+    assert!(pool.try_push(2).is_ok());
+    assert_eq!(pool.try_push(3), Err(3));
+
+    for _ in 0..3 {
+        tx.send(()).unwrap();
+    }
+    pool.join();
+}
@@ -0,0 +1,525 @@
+use date::format_http_date;
+use parser::{Method, ParseError, Version};
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::{Read, Write};
+use std::time::SystemTime;
+
+pub fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        100 => "Continue",
+        101 => "Switching Protocols",
+        200 => "OK",
+        201 => "Created",
+        202 => "Accepted",
+        204 => "No Content",
+        206 => "Partial Content",
+        301 => "Moved Permanently",
+        302 => "Found",
+        303 => "See Other",
+        304 => "Not Modified",
+        307 => "Temporary Redirect",
+        308 => "Permanent Redirect",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        411 => "Length Required",
+        412 => "Precondition Failed",
+        413 => "Payload Too Large",
+        414 => "URI Too Long",
+        416 => "Range Not Satisfiable",
+        431 => "Request Header Fields Too Large",
+        500 => "Internal Server Error",
+        501 => "Not Implemented",
+        503 => "Service Unavailable",
+        505 => "HTTP Version Not Supported",
+        _ => "",
+    }
+}
+
+pub enum ResponseBody {
+    Empty,
+    Bytes(Vec<u8>),
+    // The file is sent from the current position by the given length.
+    File(File, u64),
+    // The length is unknown, it is sent by the chunked encoding or until the connection is closed.
+    Stream(Box<dyn Read + Send>),
+    // The stream of the given length.
+    SizedStream(Box<dyn Read + Send>, u64),
+}
+
+impl fmt::Debug for ResponseBody {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ResponseBody::Empty => write!(f, "Empty"),
+            ResponseBody::Bytes(ref b) => write!(f, "Bytes({})", b.len()),
+            ResponseBody::File(_, len) => write!(f, "File({})", len),
+            ResponseBody::Stream(_) => write!(f, "Stream"),
+            ResponseBody::SizedStream(_, len) => write!(f, "SizedStream({})", len),
+        }
+    }
+}
+
+impl ResponseBody {
+    pub fn len(&self) -> Option<u64> {
+        match *self {
+            ResponseBody::Empty => Some(0),
+            ResponseBody::Bytes(ref b) => Some(b.len() as u64),
+            ResponseBody::File(_, len) | ResponseBody::SizedStream(_, len) => Some(len),
+            ResponseBody::Stream(_) => None,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == Some(0)
+    }
+}
+
+// What happened by writing a response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sent {
+    pub status: u16,
+    pub body_bytes: u64,
+    // The connection can be used for the next request.
+    pub keep_alive: bool,
+}
+
+#[derive(Debug)]
+pub struct Response {
+    pub status: u16,
+    pub reason: String,
+    headers: Vec<(String, String)>,
+    pub body: ResponseBody,
+}
+
+impl Response {
+    pub fn new(status: u16) -> Response {
+        Response {
+            status,
+            reason: reason_phrase(status).to_string(),
+            headers: Vec::new(),
+            body: ResponseBody::Empty,
+        }
+    }
+
+    pub fn ok() -> Response {
+        Response::new(200)
+    }
+
+    // The error response with the reason as the text body.
+    pub fn error(status: u16) -> Response {
+        let text = format!("{} {}\n", status, reason_phrase(status));
+        Response::new(status)
+            .with_header("Content-Type", "text/plain; charset=utf-8")
+            .with_bytes(text)
+    }
+
+    pub fn from_parse_error(e: ParseError) -> Response {
+        Response::error(e.status_code()).with_header("Connection", "close")
+    }
+
+    pub fn with_reason(mut self, reason: &str) -> Response {
+        self.reason = reason.to_string();
+        self
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Response {
+        self.set_header(name, value);
+        self
+    }
+
+    pub fn with_bytes<B: Into<Vec<u8>>>(mut self, bytes: B) -> Response {
+        self.body = ResponseBody::Bytes(bytes.into());
+        self
+    }
+
+    pub fn with_file(mut self, file: File) -> io::Result<Response> {
+        let len = file.metadata()?.len();
+        self.body = ResponseBody::File(file, len);
+        Ok(self)
+    }
+
+    pub fn with_stream<R: Read + Send + 'static>(mut self, r: R) -> Response {
+        self.body = ResponseBody::Stream(Box::new(r));
+        self
+    }
+
+    pub fn with_sized_stream<R: Read + Send + 'static>(mut self, r: R, len: u64) -> Response {
+        self.body = ResponseBody::SizedStream(Box::new(r), len);
+        self
+    }
+
+    // The names are compared case-insensitively.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn headers(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.headers.iter().map(|(n, v)| (n.as_str(), v.as_str()))
+    }
+
+    // Replace the field of the name.
+    pub fn set_header(&mut self, name: &str, value: &str) {
+        self.remove_header(name);
+        self.add_header(name, value);
+    }
+
+    // Add the field even if the name exists like Set-Cookie.
+    pub fn add_header(&mut self, name: &str, value: &str) {
+        self.headers.push((name.to_string(), value.to_string()));
+    }
+
+    pub fn remove_header(&mut self, name: &str) {
+        self.headers.retain(|(n, _)| !n.eq_ignore_ascii_case(name));
+    }
+
+    // 1xx, 204 and 304 never have a body.
+    fn allows_body(&self) -> bool {
+        !(self.status < 200 || self.status == 204 || self.status == 304)
+    }
+
+    // Write the response to the request of the given version and method.
+    // Date, Content-Length, Transfer-Encoding and Connection are added unless they are set.
+    // keep_alive is whether the client wants to keep the connection.
+    pub fn write_to<W: Write>(
+        self,
+        w: &mut W,
+        version: Version,
+        method: Method,
+        keep_alive: bool,
+    ) -> io::Result<Sent> {
+        let mut body = self.write_head(w, version, method, keep_alive)?;
+        body.write_part(w, usize::MAX)?;
+        w.flush()?;
+        Ok(body.sent())
+    }
+
+    // Write the head and the body in memory like write_to,
+    // the body read from a file or a stream is left to the returned writer.
+    pub fn write_head<W: Write>(
+        self,
+        w: &mut W,
+        version: Version,
+        method: Method,
+        keep_alive: bool,
+    ) -> io::Result<BodyWriter> {
+        let allows_body = self.allows_body();
+        let has_body = allows_body && method != Method::Head;
+        let Response {
+            status,
+            reason,
+            mut headers,
+            body,
+        } = self;
+        let has = |headers: &[(String, String)], name: &str| {
+            headers.iter().any(|(n, _)| n.eq_ignore_ascii_case(name))
+        };
+
+        // HTTP/0.9 has only the body.
+        if version == Version::Http09 {
+            return BodyWriter::new(body, false, status, false).write_bytes(w);
+        }
+
+        // HTTP/1.0 clients don't know the chunked encoding, the end of the body is the close.
+        let is_chunked = has_body && body.len().is_none() && version == Version::Http11;
+        let is_close_delimited = has_body && body.len().is_none() && !is_chunked;
+        let keep_alive = keep_alive
+            && !is_close_delimited
+            && !headers.iter().any(|(n, v)| {
+                n.eq_ignore_ascii_case("Connection") && v.eq_ignore_ascii_case("close")
+            });
+
+        if !has(&headers, "Date") {
+            headers.push(("Date".to_string(), format_http_date(SystemTime::now())));
+        }
+        if let Some(len) = body.len() {
+            if allows_body && !has(&headers, "Content-Length") {
+                headers.push(("Content-Length".to_string(), len.to_string()));
+            }
+        } else if is_chunked {
+            headers.push(("Transfer-Encoding".to_string(), "chunked".to_string()));
+        }
+        if !has(&headers, "Connection") {
+            match (keep_alive, version) {
+                (false, _) => headers.push(("Connection".to_string(), "close".to_string())),
+                // HTTP/1.0 closes the connection by default.
+                (true, Version::Http10) => {
+                    headers.push(("Connection".to_string(), "keep-alive".to_string()))
+                }
+                _ => {}
+            }
+        }
+
+        let mut head = format!("HTTP/1.1 {} {}\r\n", status, reason);
+        for (n, v) in headers.iter() {
+            head.push_str(&format!("{}: {}\r\n", n, v));
+        }
+        head.push_str("\r\n");
+        w.write_all(head.as_bytes())?;
+
+        let body = if has_body { body } else { ResponseBody::Empty };
+        BodyWriter::new(body, is_chunked, status, keep_alive).write_bytes(w)
+    }
+}
+
+// The body left after the head, it is written part by part
+// so that a large file doesn't have to be in memory at once.
+#[derive(Debug)]
+pub struct BodyWriter {
+    // Empty after the whole body is written.
+    body: ResponseBody,
+    is_chunked: bool,
+    sent: Sent,
+}
+
+// The bytes read from the file or the stream at once.
+const PART_SIZE: usize = 8192;
+
+fn read_some<R: Read + ?Sized>(r: &mut R, b: &mut [u8]) -> io::Result<usize> {
+    loop {
+        match r.read(b) {
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            r => return r,
+        }
+    }
+}
+
+// Read the next part of the body whose remaining length is len.
+fn read_exactly<R: Read + ?Sized>(r: &mut R, len: &mut u64, b: &mut [u8]) -> io::Result<usize> {
+    let max = (*len).min(b.len() as u64) as usize;
+    let n = read_some(r, &mut b[..max])?;
+    if n == 0 && max != 0 {
+        // The file was truncated or the stream ended after Content-Length was sent.
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "body is truncated",
+        ));
+    }
+    *len -= n as u64;
+    Ok(n)
+}
+
+impl BodyWriter {
+    fn new(body: ResponseBody, is_chunked: bool, status: u16, keep_alive: bool) -> BodyWriter {
+        BodyWriter {
+            body,
+            is_chunked,
+            sent: Sent {
+                status,
+                body_bytes: 0,
+                keep_alive,
+            },
+        }
+    }
+
+    // The bytes are already in memory, they are written with the head.
+    fn write_bytes<W: Write>(mut self, w: &mut W) -> io::Result<BodyWriter> {
+        if let ResponseBody::Bytes(_) = self.body {
+            self.write_part(w, usize::MAX)?;
+        }
+        Ok(self)
+    }
+
+    // body_bytes is the bytes written so far.
+    pub fn sent(&self) -> Sent {
+        self.sent
+    }
+
+    pub fn is_finished(&self) -> bool {
+        matches!(self.body, ResponseBody::Empty)
+    }
+
+    // Write the body by about max bytes at most, it returns true once the whole body is written.
+    // The bytes in memory are written at once.
+    pub fn write_part<W: Write>(&mut self, w: &mut W, max: usize) -> io::Result<bool> {
+        let mut b = [0; PART_SIZE];
+        let mut count = 0;
+        while count < max && !self.is_finished() {
+            let len = (max - count).min(b.len());
+            count += self.write_chunk(w, &mut b[..len])?;
+        }
+        Ok(self.is_finished())
+    }
+
+    fn write_chunk<W: Write>(&mut self, w: &mut W, b: &mut [u8]) -> io::Result<usize> {
+        let (n, is_end) = match self.body {
+            ResponseBody::Empty => return Ok(0),
+            ResponseBody::Bytes(ref bytes) => {
+                w.write_all(bytes)?;
+                (bytes.len(), true)
+            }
+            ResponseBody::File(ref mut f, ref mut len) => {
+                let n = read_exactly(f, len, b)?;
+                w.write_all(&b[..n])?;
+                (n, *len == 0)
+            }
+            ResponseBody::SizedStream(ref mut r, ref mut len) => {
+                let n = read_exactly(&mut **r, len, b)?;
+                w.write_all(&b[..n])?;
+                (n, *len == 0)
+            }
+            ResponseBody::Stream(ref mut r) => {
+                let n = read_some(&mut **r, b)?;
+                if !self.is_chunked {
+                    w.write_all(&b[..n])?;
+                } else if n == 0 {
+                    w.write_all(b"0\r\n\r\n")?;
+                } else {
+                    write!(w, "{:x}\r\n", n)?;
+                    w.write_all(&b[..n])?;
+                    w.write_all(b"\r\n")?;
+                }
+                (n, n == 0)
+            }
+        };
+        self.sent.body_bytes += n as u64;
+        if is_end {
+            self.body = ResponseBody::Empty;
+        }
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+fn written(res: Response, version: Version, method: Method, keep_alive: bool) -> (String, Sent) {
+    let mut buf = Vec::new();
+    let sent = res
+        .with_header("Date", "Thu, 01 Jan 1970 00:00:00 GMT")
+        .write_to(&mut buf, version, method, keep_alive)
+        .unwrap();
+    (String::from_utf8(buf).unwrap(), sent)
+}
+
+#[test]
+fn response_http11() {
+    let res = Response::ok()
+        .with_header("Content-Type", "text/plain")
+        .with_bytes("hello");
+    let (s, sent) = written(res, Version::Http11, Method::Get, true);
+    assert_eq!(
+        s,
+        "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nDate: Thu, 01 Jan 1970 00:00:00 GMT\r\nContent-Length: 5\r\n\r\nhello"
+    );
+    assert_eq!(
+        sent,
+        Sent {
+            status: 200,
+            body_bytes: 5,
+            keep_alive: true
+        }
+    );
+
+    let (s, sent) = written(
+        Response::new(404).with_bytes("x"),
+        Version::Http11,
+        Method::Head,
+        false,
+    );
+    assert_eq!(
+        s,
+        "HTTP/1.1 404 Not Found\r\nDate: Thu, 01 Jan 1970 00:00:00 GMT\r\nContent-Length: 1\r\nConnection: close\r\n\r\n"
+    );
+    assert!(!sent.keep_alive);
+
+    let (s, _) = written(Response::new(304), Version::Http11, Method::Get, true);
+    assert!(!s.contains("Content-Length"));
+}
+
+#[test]
+fn response_http10_and_http09() {
+    let (s, sent) = written(
+        Response::ok().with_bytes("a"),
+        Version::Http10,
+        Method::Get,
+        true,
+    );
+    assert!(s.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(s.contains("\r\nConnection: keep-alive\r\n"));
+    assert!(sent.keep_alive);
+
+    // The stream is delimited by the close.
+    let res = Response::ok().with_stream(io::Cursor::new(b"abc".to_vec()));
+    let (s, sent) = written(res, Version::Http10, Method::Get, true);
+    assert!(s.contains("\r\nConnection: close\r\n\r\nabc"));
+    assert!(!s.contains("Transfer-Encoding"));
+    assert!(!sent.keep_alive);
+
+    let (s, sent) = written(Response::error(404), Version::Http09, Method::Get, true);
+    assert_eq!(s, "404 Not Found\n");
+    assert!(!sent.keep_alive);
+}
+
+#[test]
+fn response_chunked_stream() {
+    let res = Response::ok().with_stream(io::Cursor::new(b"abc".to_vec()));
+    let (s, sent) = written(res, Version::Http11, Method::Get, true);
+    assert!(s.ends_with("\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n0\r\n\r\n"));
+    assert_eq!(sent.body_bytes, 3);
+    assert!(sent.keep_alive);
+
+    // The stream of the known length is not chunked.
+    let res = Response::ok().with_sized_stream(io::Cursor::new(b"abcdef".to_vec()), 4);
+    let (s, sent) = written(res, Version::Http11, Method::Get, true);
+    assert!(s.ends_with("\r\nContent-Length: 4\r\n\r\nabcd"));
+    assert_eq!(sent.body_bytes, 4);
+}
+
+#[test]
+fn response_write_part() {
+    // The endless stream is written part by part after the head.
+    let res = Response::ok().with_stream(io::repeat(b'x'));
+    let mut out = Vec::new();
+    let mut body = res
+        .write_head(&mut out, Version::Http11, Method::Get, true)
+        .unwrap();
+    assert!(out.ends_with(b"Transfer-Encoding: chunked\r\n\r\n"));
+    out.clear();
+    assert!(!body.write_part(&mut out, 5).unwrap());
+    assert_eq!(out, b"5\r\nxxxxx\r\n");
+    assert_eq!(body.sent().body_bytes, 5);
+
+    // The bytes in memory are written with the head.
+    let mut out = Vec::new();
+    let body = Response::ok()
+        .with_bytes("abc")
+        .write_head(&mut out, Version::Http11, Method::Get, true)
+        .unwrap();
+    assert!(body.is_finished());
+    assert!(out.ends_with(b"\r\n\r\nabc"));
+
+    // The stream which ends before the length is an error.
+    let mut body = Response::ok()
+        .with_sized_stream(io::Cursor::new(b"ab".to_vec()), 4)
+        .write_head(&mut Vec::new(), Version::Http11, Method::Get, true)
+        .unwrap();
+    assert!(!body.write_part(&mut Vec::new(), 2).unwrap());
+    assert_eq!(
+        body.write_part(&mut Vec::new(), 3).unwrap_err().kind(),
+        io::ErrorKind::UnexpectedEof
+    );
+}
+
+#[test]
+fn response_headers() {
+    let mut res = Response::new(201)
+        .with_header("X-A", "1")
+        .with_header("x-a", "2");
+    res.add_header("Set-Cookie", "a=1");
+    res.add_header("Set-Cookie", "b=2");
+    assert_eq!(res.header("X-A"), Some("2"));
+    assert_eq!(res.headers().count(), 3);
+    res.remove_header("set-cookie");
+    assert_eq!(res.headers().count(), 1);
+    assert_eq!(res.reason, "Created");
+    assert_eq!(
+        Response::from_parse_error(ParseError::UriTooLong).status,
+        414
+    );
+}
@@ -0,0 +1,400 @@
+use parser::{Method, Request};
+use response::Response;
+use std::str::FromStr;
+
+// Decode %XX, the result has to be UTF-8.
+// '+' is a space in the query.
+pub fn percent_decode(s: &str, is_query: bool) -> Option<String> {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' => {
+                let hex = bytes.get(i + 1..i + 3)?;
+                let hex = ::std::str::from_utf8(hex).ok()?;
+                out.push(u8::from_str_radix(hex, 16).ok()?);
+                i += 3;
+                continue;
+            }
+            b'+' if is_query => out.push(b' '),
+            b => out.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8(out).ok()
+}
+
+// The decoded pairs of names and values.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Params {
+    pairs: Vec<(String, String)>,
+}
+
+impl Params {
+    pub fn new() -> Params {
+        Params { pairs: Vec::new() }
+    }
+
+    // `a=1&b=x+y`, the pairs which cannot be decoded are ignored.
+    pub fn from_query(query: &str) -> Params {
+        let pairs = query
+            .split('&')
+            .filter(|p| !p.is_empty())
+            .filter_map(|p| {
+                let mut kv = p.splitn(2, '=');
+                let k = percent_decode(kv.next().unwrap(), true)?;
+                let v = percent_decode(kv.next().unwrap_or(""), true)?;
+                Some((k, v))
+            })
+            .collect();
+        Params { pairs }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.pairs
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    // None if it is missing or cannot be parsed.
+    pub fn parse<T: FromStr>(&self, name: &str) -> Option<T> {
+        self.get(name).and_then(|v| v.parse().ok())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.pairs.iter().map(|(n, v)| (n.as_str(), v.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+}
+
+// What a handler receives.
+pub struct Context<'a> {
+    pub request: &'a Request<'a>,
+    pub body: &'a [u8],
+    // The parameters of the path pattern like :id and *rest.
+    pub params: Params,
+    pub query: Params,
+}
+
+impl<'a> Context<'a> {
+    pub fn param<T: FromStr>(&self, name: &str) -> Option<T> {
+        self.params.parse(name)
+    }
+
+    pub fn query<T: FromStr>(&self, name: &str) -> Option<T> {
+        self.query.parse(name)
+    }
+}
+
+pub trait Handler: Send + Sync {
+    fn handle(&self, ctx: &Context) -> Response;
+}
+
+impl<F> Handler for F
+where
+    F: Fn(&Context) -> Response + Send + Sync,
+{
+    fn handle(&self, ctx: &Context) -> Response {
+        self(ctx)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    // :name matches one segment.
+    Param(String),
+    // *name matches the rest of the path, it has to be the last.
+    Rest(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Pattern {
+    segments: Vec<Segment>,
+}
+
+impl Pattern {
+    fn parse(pattern: &str) -> Option<Pattern> {
+        if !pattern.starts_with('/') {
+            return None;
+        }
+
+        let segments = pattern[1..]
+            .split('/')
+            .map(|s| {
+                if let Some(name) = s.strip_prefix(':') {
+                    Segment::Param(name.to_string())
+                } else if let Some(name) = s.strip_prefix('*') {
+                    Segment::Rest(name.to_string())
+                } else {
+                    Segment::Literal(s.to_string())
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let is_valid = segments.iter().enumerate().all(|(i, s)| match *s {
+            Segment::Param(ref n) => !n.is_empty(),
+            Segment::Rest(ref n) => !n.is_empty() && i == segments.len() - 1,
+            Segment::Literal(_) => true,
+        });
+        if is_valid {
+            Some(Pattern { segments })
+        } else {
+            None
+        }
+    }
+
+    // The path is not decoded yet, the parameters are decoded.
+    fn matches(&self, path: &str) -> Option<Params> {
+        let path = path.strip_prefix('/')?;
+        let mut params = Params::new();
+        let mut rest = Some(path);
+
+        for s in self.segments.iter() {
+            let p = rest?;
+            if let Segment::Rest(ref name) = *s {
+                params.pairs.push((name.clone(), percent_decode(p, false)?));
+                return Some(params);
+            }
+
+            let (seg, next) = match p.find('/') {
+                Some(i) => (&p[..i], Some(&p[i + 1..])),
+                None => (p, None),
+            };
+            match *s {
+                Segment::Literal(ref l) if percent_decode(seg, false)? == *l => {}
+                Segment::Param(ref name) if !seg.is_empty() => {
+                    params
+                        .pairs
+                        .push((name.clone(), percent_decode(seg, false)?));
+                }
+                _ => return None,
+            }
+            rest = next;
+        }
+
+        if rest.is_none() {
+            Some(params)
+        } else {
+            None
+        }
+    }
+}
+
+struct Route {
+    method: Method,
+    pattern: Pattern,
+    handler: Box<dyn Handler>,
+}
+
+// Dispatch the requests by the method and the path.
+// The routes are tried in the registered order.
+#[derive(Default)]
+pub struct Router {
+    routes: Vec<Route>,
+}
+
+impl Router {
+    pub fn new() -> Router {
+        Router { routes: Vec::new() }
+    }
+
+    // The pattern is like `/users/:id` or `/static/*rest`, it panics if the pattern is invalid.
+    pub fn route<H: Handler + 'static>(
+        mut self,
+        method: Method,
+        pattern: &str,
+        handler: H,
+    ) -> Router {
+        let p = Pattern::parse(pattern).unwrap_or_else(|| panic!("invalid pattern {}", pattern));
+        self.routes.push(Route {
+            method,
+            pattern: p,
+            handler: Box::new(handler),
+        });
+        self
+    }
+
+    pub fn get<H: Handler + 'static>(self, pattern: &str, handler: H) -> Router {
+        self.route(Method::Get, pattern, handler)
+    }
+
+    pub fn post<H: Handler + 'static>(self, pattern: &str, handler: H) -> Router {
+        self.route(Method::Post, pattern, handler)
+    }
+
+    pub fn put<H: Handler + 'static>(self, pattern: &str, handler: H) -> Router {
+        self.route(Method::Put, pattern, handler)
+    }
+
+    pub fn delete<H: Handler + 'static>(self, pattern: &str, handler: H) -> Router {
+        self.route(Method::Delete, pattern, handler)
+    }
+
+    // HEAD is handled by GET if it is not routed, the body is dropped when the response is written.
+    // 405 has Allow of the methods for the path, and OPTIONS is answered by them too.
+    pub fn handle(&self, req: &Request, body: &[u8]) -> Response {
+        let path = req.path();
+        let mut allowed = Vec::new();
+        let mut get = None;
+
+        for r in self.routes.iter() {
+            let params = match r.pattern.matches(path) {
+                Some(p) => p,
+                None => continue,
+            };
+            if r.method == req.method {
+                return self.call(r, req, body, params);
+            }
+            if r.method == Method::Get && req.method == Method::Head && get.is_none() {
+                get = Some((r, params));
+            }
+            if !allowed.contains(&r.method) {
+                allowed.push(r.method);
+            }
+        }
+
+        if let Some((r, params)) = get {
+            return self.call(r, req, body, params);
+        }
+        if allowed.is_empty() {
+            return Response::error(404);
+        }
+
+        if allowed.contains(&Method::Get) && !allowed.contains(&Method::Head) {
+            allowed.push(Method::Head);
+        }
+        allowed.push(Method::Options);
+        let allow = allowed
+            .iter()
+            .map(|m| m.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        let res = if req.method == Method::Options {
+            Response::new(204)
+        } else {
+            Response::error(405)
+        };
+        res.with_header("Allow", &allow)
+    }
+
+    fn call(&self, r: &Route, req: &Request, body: &[u8], params: Params) -> Response {
+        let ctx = Context {
+            request: req,
+            body,
+            params,
+            query: req.query().map_or_else(Params::new, Params::from_query),
+        };
+        r.handler.handle(&ctx)
+    }
+}
+
+#[cfg(test)]
+fn request(buf: &[u8]) -> Request<'_> {
+    match ::parser::parse(buf) {
+        ::parser::ParseResult::Complete(req) => req,
+        r => panic!("{:?}", r),
+    }
+}
+
+#[cfg(test)]
+fn body_of(res: Response) -> String {
+    match res.body {
+        ::response::ResponseBody::Bytes(b) => String::from_utf8(b).unwrap(),
+        b => panic!("{:?}", b),
+    }
+}
+
+#[test]
+fn router_dispatch() {
+    struct Hello;
+    impl Handler for Hello {
+        fn handle(&self, ctx: &Context) -> Response {
+            Response::ok().with_bytes(format!("hello {}", ctx.params.get("name").unwrap()))
+        }
+    }
+
+    let router = Router::new()
+        .get("/", |_: &Context| Response::ok().with_bytes("root"))
+        .get("/users/me", |_: &Context| Response::ok().with_bytes("me"))
+        .get("/users/:id", |ctx: &Context| match ctx.param::<u32>("id") {
+            Some(id) => {
+                Response::ok().with_bytes(format!("user {} {:?}", id, ctx.query::<u8>("v")))
+            }
+            None => Response::error(400),
+        })
+        .post("/users/:id", |ctx: &Context| {
+            Response::new(201).with_bytes(ctx.body.to_vec())
+        })
+        .get("/static/*rest", |ctx: &Context| {
+            Response::ok().with_bytes(ctx.params.get("rest").unwrap().to_string())
+        })
+        .get("/hello/:name", Hello);
+
+    let get = |path: &str| {
+        let buf = format!("GET {} HTTP/1.1\r\nHost: a\r\n\r\n", path);
+        let res = router.handle(&request(buf.as_bytes()), b"");
+        (res.status, body_of(res))
+    };
+    assert_eq!(get("/"), (200, "root".to_string()));
+    assert_eq!(get("/users/me"), (200, "me".to_string()));
+    assert_eq!(get("/users/12?v=3"), (200, "user 12 Some(3)".to_string()));
+    assert_eq!(get("/users/x").0, 400);
+    assert_eq!(get("/users/12/x").0, 404);
+    assert_eq!(get("/users/").0, 404);
+    assert_eq!(get("/static/a/b%20c.txt"), (200, "a/b c.txt".to_string()));
+    assert_eq!(get("/static/"), (200, "".to_string()));
+    assert_eq!(get("/hello/%E3%81%82"), (200, "hello \u{3042}".to_string()));
+    assert_eq!(get("/nothing").0, 404);
+
+    let req = request(b"POST /users/1 HTTP/1.1\r\nHost: a\r\n\r\n");
+    let res = router.handle(&req, b"data");
+    assert_eq!((res.status, body_of(res)), (201, "data".to_string()));
+
+    let req = request(b"HEAD /users/me HTTP/1.1\r\nHost: a\r\n\r\n");
+    assert_eq!(router.handle(&req, b"").status, 200);
+}
+
+#[test]
+fn router_method_not_allowed() {
+    let router = Router::new()
+        .get("/users/:id", |_: &Context| Response::ok())
+        .delete("/users/:id", |_: &Context| Response::new(204));
+
+    let req = request(b"PUT /users/1 HTTP/1.1\r\nHost: a\r\n\r\n");
+    let res = router.handle(&req, b"");
+    assert_eq!(res.status, 405);
+    assert_eq!(res.header("Allow"), Some("GET, DELETE, HEAD, OPTIONS"));
+
+    let req = request(b"OPTIONS /users/1 HTTP/1.1\r\nHost: a\r\n\r\n");
+    let res = router.handle(&req, b"");
+    assert_eq!(res.status, 204);
+    assert_eq!(res.header("Allow"), Some("GET, DELETE, HEAD, OPTIONS"));
+}
+
+#[test]
+fn router_params() {
+    let q = Params::from_query("a=1&b=x+y%21&c&=&d=%ZZ");
+    assert_eq!(q.get("a"), Some("1"));
+    assert_eq!(q.get("b"), Some("x y!"));
+    assert_eq!(q.get("c"), Some(""));
+    assert_eq!(q.get("d"), None);
+    assert_eq!(q.parse::<u8>("a"), Some(1));
+    assert_eq!(q.parse::<u8>("b"), None);
+
+    assert_eq!(percent_decode("a%2Fb+c", false), Some("a/b+c".to_string()));
+    assert_eq!(percent_decode("%ff", false), None);
+    assert_eq!(percent_decode("%4", false), None);
+    assert!(Pattern::parse("users").is_none());
+    assert!(Pattern::parse("/*rest/x").is_none());
+    assert!(Pattern::parse("/:").is_none());
+}
@@ -0,0 +1,377 @@
+use access_log::AccessLog;
+use connection::{serve_stream, ConnectionConfig};
+use error_log::{format_peer, ErrorLog};
+use event_loop;
+use listener::{ListenAddr, Listener, Stream};
+use parser::{Method, Version};
+use pool::WorkerPool;
+use response::Response;
+use router::Router;
+use std::io;
+use std::io::Write;
+use std::net::{Shutdown, SocketAddr, TcpListener, ToSocketAddrs};
+use std::os::raw::c_int;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use sys;
+
+// How often the accept loop checks the shutdown.
+const POLL_INTERVAL_MS: i32 = 100;
+
+static IS_TERMINATED: AtomicBool = AtomicBool::new(false);
+static IS_HUNG_UP: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_terminate(_: c_int) {
+    IS_TERMINATED.store(true, Ordering::SeqCst);
+}
+
+extern "C" fn on_hangup(_: c_int) {
+    IS_HUNG_UP.store(true, Ordering::SeqCst);
+}
+
+// Stop all the servers gracefully on SIGTERM and SIGINT, and reopen the log files on SIGHUP.
+pub fn handle_signals() {
+    sys::set_signal_handler(sys::SIGTERM, on_terminate);
+    sys::set_signal_handler(sys::SIGINT, on_terminate);
+    sys::set_signal_handler(sys::SIGHUP, on_hangup);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    // The blocking connections on the worker pool.
+    Threads,
+    // All the non-blocking connections on one thread.
+    // The handlers run on the thread too, so they and their stream bodies must not block.
+    Epoll,
+}
+
+impl Backend {
+    pub fn from_name(name: &str) -> Option<Backend> {
+        match name {
+            "threads" => Some(Backend::Threads),
+            "epoll" => Some(Backend::Epoll),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Backend::Threads => "threads",
+            Backend::Epoll => "epoll",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ServerConfig {
+    pub backend: Backend,
+    pub workers: usize,
+    // The accepted connections waiting for a worker, the others are answered 503.
+    pub queue_size: usize,
+    // The connections the epoll backend holds at once, the others are answered 503.
+    pub max_connections: usize,
+    pub connection: ConnectionConfig,
+}
+
+impl Default for ServerConfig {
+    fn default() -> ServerConfig {
+        ServerConfig {
+            backend: Backend::Threads,
+            workers: 8,
+            queue_size: 64,
+            max_connections: 10_000,
+            connection: ConnectionConfig::default(),
+        }
+    }
+}
+
+pub struct Server {
+    listeners: Vec<Listener>,
+    router: Arc<Router>,
+    access_log: Arc<AccessLog>,
+    error_log: Arc<ErrorLog>,
+    config: ServerConfig,
+    stop: Arc<AtomicBool>,
+}
+
+fn busy() -> Response {
+    Response::error(503).with_header("Retry-After", "1")
+}
+
+// Tell the client to come back later without reading its request.
+pub(crate) fn reject(mut stream: Stream) {
+    let _ = stream.set_nonblocking(false);
+    let _ = stream.set_write_timeout(Some(Duration::from_secs(1)));
+    let _ = busy().write_to(&mut stream, Version::Http11, Method::Get, false);
+    let _ = stream.shutdown(Shutdown::Write);
+}
+
+// Like reject but the event loop doesn't wait for the client,
+// the response is written once as far as the socket buffer takes it.
+pub(crate) fn reject_nonblocking(mut stream: Stream) {
+    let mut buf = Vec::new();
+    let _ = busy().write_to(&mut buf, Version::Http11, Method::Get, false);
+    if stream.set_nonblocking(true).is_ok() {
+        let _ = stream.write(&buf);
+    }
+    let _ = stream.shutdown(Shutdown::Write);
+}
+
+impl Server {
+    pub fn bind<A: ToSocketAddrs>(
+        addr: A,
+        router: Router,
+        config: ServerConfig,
+    ) -> io::Result<Server> {
+        let listener = Listener::Tcp(TcpListener::bind(addr)?);
+        Ok(Server::new(vec![listener], router, config))
+    }
+
+    // All the addresses are bound or nothing.
+    pub fn bind_all(
+        addrs: &[ListenAddr],
+        router: Router,
+        config: ServerConfig,
+    ) -> io::Result<Server> {
+        let listeners = addrs
+            .iter()
+            .map(|a| {
+                Listener::bind(a)
+                    .map_err(|e| io::Error::new(e.kind(), format!("failed to bind {}: {}", a, e)))
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        Ok(Server::new(listeners, router, config))
+    }
+
+    fn new(listeners: Vec<Listener>, router: Router, config: ServerConfig) -> Server {
+        Server {
+            listeners,
+            router: Arc::new(router),
+            access_log: Arc::new(AccessLog::off()),
+            error_log: Arc::new(ErrorLog::stderr()),
+            config,
+            stop: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn with_access_log(mut self, log: AccessLog) -> Server {
+        self.access_log = Arc::new(log);
+        self
+    }
+
+    pub fn with_error_log(mut self, log: ErrorLog) -> Server {
+        self.error_log = Arc::new(log);
+        self
+    }
+
+    // The first TCP address.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        for l in self.listeners.iter() {
+            if let ListenAddr::Tcp(a) = l.local_addr()? {
+                return Ok(a);
+            }
+        }
+        Err(io::Error::new(io::ErrorKind::NotFound, "no TCP listener"))
+    }
+
+    pub fn local_addrs(&self) -> io::Result<Vec<ListenAddr>> {
+        self.listeners.iter().map(|l| l.local_addr()).collect()
+    }
+
+    // Setting it true stops the server like SIGTERM.
+    pub fn stop_handle(&self) -> Arc<AtomicBool> {
+        self.stop.clone()
+    }
+
+    fn is_stopped(&self) -> bool {
+        if IS_TERMINATED.load(Ordering::SeqCst) {
+            self.stop.store(true, Ordering::SeqCst);
+        }
+        self.stop.load(Ordering::SeqCst)
+    }
+
+    // The rotated log files are opened again after SIGHUP.
+    fn reopen_logs_if_hung_up(&self) {
+        if !IS_HUNG_UP.swap(false, Ordering::SeqCst) {
+            return;
+        }
+        if let Err(e) = self.access_log.reopen() {
+            self.error_log
+                .log(format_args!("failed to reopen the access log: {}", e));
+        }
+        if let Err(e) = self.error_log.reopen() {
+            self.error_log
+                .log(format_args!("failed to reopen the error log: {}", e));
+        }
+    }
+
+    // Accept the connections until the server is stopped, and then wait for the requests in flight.
+    pub fn run(self) -> io::Result<()> {
+        match self.config.backend {
+            Backend::Threads => self.run_threads(),
+            Backend::Epoll => event_loop::run(
+                &self.listeners,
+                &self.router,
+                &self.access_log,
+                &self.error_log,
+                &self.config,
+                || {
+                    self.reopen_logs_if_hung_up();
+                    self.is_stopped()
+                },
+            ),
+        }
+    }
+
+    fn run_threads(self) -> io::Result<()> {
+        let (router, log, error_log, stop) = (
+            self.router.clone(),
+            self.access_log.clone(),
+            self.error_log.clone(),
+            self.stop.clone(),
+        );
+        let config = self.config;
+        let pool = WorkerPool::new(config.workers, config.queue_size, move |s: Stream| {
+            let peer = s.peer_addr();
+            if let Err(e) = serve_stream(s, &router, &log, &config.connection, &stop) {
+                error_log.log(format_args!(
+                    "An error occured while serving {}: {}",
+                    format_peer(peer),
+                    e
+                ));
+            }
+        });
+
+        let fds: Vec<RawFd> = self.listeners.iter().map(|l| l.as_raw_fd()).collect();
+        while !self.is_stopped() {
+            self.reopen_logs_if_hung_up();
+            for i in sys::wait_readable(&fds, POLL_INTERVAL_MS)? {
+                match self.listeners[i].accept() {
+                    Ok(stream) => {
+                        if let Err(stream) = pool.try_push(stream) {
+                            reject(stream);
+                        }
+                    }
+                    Err(e) => self.error_log.log(format_args!(
+                        "An error occured while accepting a connection: {}",
+                        e
+                    )),
+                }
+            }
+        }
+
+        pool.join();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(crate) fn spawn_test_server(
+    router: Router,
+    config: ServerConfig,
+) -> (SocketAddr, Arc<AtomicBool>, ::std::thread::JoinHandle<()>) {
+    let server = Server::bind("127.0.0.1:0", router, config).unwrap();
+    let addr = server.local_addr().unwrap();
+    let stop = server.stop_handle();
+    (
+        addr,
+        stop,
+        ::std::thread::spawn(move || server.run().unwrap()),
+    )
+}
+
+#[cfg(test)]
+pub(crate) fn get(addr: SocketAddr, path: &str) -> String {
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+
+    let mut s = TcpStream::connect(addr).unwrap();
+    write!(
+        s,
+        "GET {} HTTP/1.1\r\nHost: a\r\nConnection: close\r\n\r\n",
+        path
+    )
+    .unwrap();
+    let mut res = String::new();
+    s.read_to_string(&mut res).unwrap();
+    res
+}
+
+#[test]
+fn server_saturated() {
+    use router::Context;
+    use std::sync::mpsc;
+    use std::sync::Mutex;
+    use std::thread;
+
+    // The handler blocks until it is released.
+    let (tx, rx) = mpsc::channel::<()>();
+    let rx = Mutex::new(rx);
+    let router = Router::new().get("/block", move |_: &Context| {
+        rx.lock().unwrap().recv().unwrap();
+        Response::ok()
+    });
+    let config = ServerConfig {
+        workers: 1,
+        queue_size: 1,
+        ..Default::default()
+    };
+    let (addr, stop, server) = spawn_test_server(router, config);
+
+    let first = thread::spawn(move || get(addr, "/block"));
+    thread::sleep(Duration::from_millis(200));
+    let second = thread::spawn(move || get(addr, "/block"));
+    thread::sleep(Duration::from_millis(200));
+    assert!(get(addr, "/block").starts_with("HTTP/1.1 503 Service Unavailable\r\n"));
+
+    // The requests in flight are answered after the stop.
+    stop.store(true, Ordering::SeqCst);
+    tx.send(()).unwrap();
+    tx.send(()).unwrap();
+    assert!(first.join().unwrap().starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(second.join().unwrap().starts_with("HTTP/1.1 200 OK\r\n"));
+    server.join().unwrap();
+}
+
+#[test]
+fn server_listen_all() {
+    use std::io::{Read, Write};
+    use std::os::unix::net::UnixStream;
+
+    for &backend in &[Backend::Threads, Backend::Epoll] {
+        let path = ::std::env::temp_dir().join(format!(
+            "http_server_{}_{}.sock",
+            backend.name(),
+            ::std::process::id()
+        ));
+        let addrs = vec![
+            "127.0.0.1:0".parse().unwrap(),
+            ListenAddr::Unix(path.clone()),
+        ];
+        let config = ServerConfig {
+            backend,
+            ..Default::default()
+        };
+        let server = Server::bind_all(&addrs, Router::new(), config).unwrap();
+        let bound = server.local_addrs().unwrap();
+        assert_eq!(bound[1], ListenAddr::Unix(path.clone()));
+        let addr = server.local_addr().unwrap();
+        assert_ne!(addr.port(), 0);
+        let stop = server.stop_handle();
+        let handle = ::std::thread::spawn(move || server.run().unwrap());
+
+        assert!(get(addr, "/").starts_with("HTTP/1.1 404 Not Found\r\n"));
+        let mut s = UnixStream::connect(&path).unwrap();
+        s.write_all(b"GET / HTTP/1.1\r\nHost: a\r\nConnection: close\r\n\r\n")
+            .unwrap();
+        let mut res = String::new();
+        s.read_to_string(&mut res).unwrap();
+        assert!(res.starts_with("HTTP/1.1 404 Not Found\r\n"));
+
+        stop.store(true, Ordering::SeqCst);
+        handle.join().unwrap();
+        assert!(!path.exists());
+    }
+}
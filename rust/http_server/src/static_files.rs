@@ -0,0 +1,631 @@
+use date::{format_http_date, parse_http_date};
+use response::{Response, ResponseBody};
+use router::{percent_decode, Context, Handler};
+use std::collections::VecDeque;
+use std::fs;
+use std::fs::{File, Metadata};
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// More ranges than this are ignored and the whole file is sent.
+const MAX_RANGES: usize = 16;
+
+const MIME_TYPES: &[(&str, &str)] = &[
+    ("html", "text/html; charset=utf-8"),
+    ("htm", "text/html; charset=utf-8"),
+    ("css", "text/css; charset=utf-8"),
+    ("js", "text/javascript; charset=utf-8"),
+    ("mjs", "text/javascript; charset=utf-8"),
+    ("json", "application/json"),
+    ("map", "application/json"),
+    ("xml", "application/xml"),
+    ("txt", "text/plain; charset=utf-8"),
+    ("log", "text/plain; charset=utf-8"),
+    ("md", "text/markdown; charset=utf-8"),
+    ("csv", "text/csv; charset=utf-8"),
+    ("svg", "image/svg+xml"),
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("gif", "image/gif"),
+    ("webp", "image/webp"),
+    ("ico", "image/x-icon"),
+    ("woff", "font/woff"),
+    ("woff2", "font/woff2"),
+    ("wasm", "application/wasm"),
+    ("pdf", "application/pdf"),
+    ("zip", "application/zip"),
+    ("gz", "application/gzip"),
+    ("tgz", "application/gzip"),
+    ("tar", "application/x-tar"),
+    ("bz2", "application/x-bzip2"),
+    ("xz", "application/x-xz"),
+    ("zst", "application/zstd"),
+    ("jar", "application/java-archive"),
+    ("deb", "application/vnd.debian.binary-package"),
+    ("rpm", "application/x-rpm"),
+];
+
+// Unknown extensions are the binary.
+pub fn mime_type(path: &Path) -> &'static str {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .and_then(|e| {
+            MIME_TYPES
+                .iter()
+                .find(|&&(x, _)| x.eq_ignore_ascii_case(e))
+                .map(|&(_, m)| m)
+        })
+        .unwrap_or("application/octet-stream")
+}
+
+// The relative path in the root, None if it tries to go out of the root.
+fn sanitize(path: &str) -> Option<PathBuf> {
+    let mut p = PathBuf::new();
+    for seg in path.split('/') {
+        match seg {
+            "" | "." => {}
+            ".." => return None,
+            s if s.contains('\\') || s.contains('\0') => return None,
+            s => p.push(s),
+        }
+    }
+    Some(p)
+}
+
+// The byte ranges like `bytes=0-99,200-,-50` as the inclusive bounds in the file.
+// None if the header is invalid, it is ignored then. The empty list is unsatisfiable.
+pub fn parse_range(value: &str, len: u64) -> Option<Vec<(u64, u64)>> {
+    let specs = value.trim().strip_prefix("bytes=")?;
+    let mut ranges = Vec::new();
+    for spec in specs.split(',').map(|s| s.trim()).filter(|s| !s.is_empty()) {
+        let (first, last) = spec.split_at(spec.find('-')?);
+        let last = &last[1..];
+        let range = match (first.is_empty(), last.is_empty()) {
+            // The last bytes.
+            (true, false) => {
+                let n: u64 = last.parse().ok()?;
+                if n == 0 {
+                    None
+                } else {
+                    Some((len.saturating_sub(n), len.checked_sub(1)?))
+                }
+            }
+            (false, _) => {
+                let first: u64 = first.parse().ok()?;
+                let last: u64 = if last.is_empty() {
+                    u64::MAX
+                } else {
+                    last.parse().ok()?
+                };
+                if last < first {
+                    return None;
+                }
+                if len <= first {
+                    None
+                } else {
+                    Some((first, last.min(len - 1)))
+                }
+            }
+            (true, true) => return None,
+        };
+        ranges.extend(range);
+    }
+
+    if MAX_RANGES < ranges.len() {
+        None
+    } else {
+        Some(ranges)
+    }
+}
+
+fn etag_of(meta: &Metadata) -> Option<String> {
+    let d = meta.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some(format!(
+        "\"{:x}-{:x}.{:x}\"",
+        meta.len(),
+        d.as_secs(),
+        d.subsec_nanos()
+    ))
+}
+
+// The weak comparison, it is for If-None-Match.
+fn etag_matches(header: &str, etag: &str) -> bool {
+    header.split(',').map(|t| t.trim()).any(|t| {
+        t == "*" || t.strip_prefix("W/").unwrap_or(t) == etag.strip_prefix("W/").unwrap_or(etag)
+    })
+}
+
+// The time in the seconds, HTTP-date doesn't have the less.
+fn truncate_secs(t: SystemTime) -> SystemTime {
+    let secs = t.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    UNIX_EPOCH + Duration::from_secs(secs)
+}
+
+fn html_escape(s: &str) -> String {
+    let mut e = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => e.push_str("&amp;"),
+            '<' => e.push_str("&lt;"),
+            '>' => e.push_str("&gt;"),
+            '"' => e.push_str("&quot;"),
+            '\'' => e.push_str("&#39;"),
+            c => e.push(c),
+        }
+    }
+    e
+}
+
+// Encode the bytes except the unreserved ones for a path segment.
+fn percent_encode(s: &str) -> String {
+    let mut e = String::with_capacity(s.len());
+    for &b in s.as_bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                e.push(b as char)
+            }
+            b => e.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    e
+}
+
+fn error_of(e: &io::Error) -> Response {
+    match e.kind() {
+        io::ErrorKind::NotFound => Response::error(404),
+        io::ErrorKind::PermissionDenied => Response::error(403),
+        _ => Response::error(500),
+    }
+}
+
+enum Piece {
+    Bytes(Vec<u8>),
+    // The start and the length in the file.
+    Range(u64, u64),
+}
+
+// The body of multipart/byteranges, the file is read part by part.
+struct Multipart {
+    file: File,
+    pieces: VecDeque<Piece>,
+    // The position in the first piece.
+    pos: u64,
+}
+
+impl Read for Multipart {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        loop {
+            let n = match self.pieces.front() {
+                None => return Ok(0),
+                Some(Piece::Bytes(b)) => {
+                    let rest = &b[self.pos as usize..];
+                    let n = rest.len().min(buf.len());
+                    buf[..n].copy_from_slice(&rest[..n]);
+                    n
+                }
+                Some(&Piece::Range(start, len)) => {
+                    if self.pos == 0 {
+                        self.file.seek(SeekFrom::Start(start))?;
+                    }
+                    let rest = (len - self.pos).min(buf.len() as u64) as usize;
+                    match self.file.read(&mut buf[..rest])? {
+                        0 if rest != 0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+                        n => n,
+                    }
+                }
+            };
+
+            if n == 0 {
+                self.pieces.pop_front();
+                self.pos = 0;
+                continue;
+            }
+            self.pos += n as u64;
+            return Ok(n);
+        }
+    }
+}
+
+// Serve the files under the root directory.
+// It is routed by the pattern ending with `*path` like `/files/*path`.
+#[derive(Debug, Clone)]
+pub struct StaticFiles {
+    root: PathBuf,
+    is_listing: bool,
+}
+
+impl StaticFiles {
+    pub fn new<P: Into<PathBuf>>(root: P) -> StaticFiles {
+        StaticFiles {
+            root: root.into(),
+            is_listing: true,
+        }
+    }
+
+    // The directory without index.html is listed or forbidden.
+    pub fn with_listing(mut self, is_listing: bool) -> StaticFiles {
+        self.is_listing = is_listing;
+        self
+    }
+
+    // The path in the root, the symbolic links don't lead out of it either.
+    fn resolve(&self, path: &str) -> Result<PathBuf, Response> {
+        let rel = sanitize(path).ok_or_else(|| Response::error(403))?;
+        let root = self.root.canonicalize().map_err(|e| error_of(&e))?;
+        let full = root.join(rel).canonicalize().map_err(|e| error_of(&e))?;
+        if full.starts_with(&root) {
+            Ok(full)
+        } else {
+            Err(Response::error(403))
+        }
+    }
+
+    fn serve_dir(&self, ctx: &Context, dir: &Path) -> Response {
+        let req_path = ctx.request.path();
+        if !req_path.ends_with('/') {
+            let location = match ctx.request.query() {
+                Some(q) => format!("{}/?{}", req_path, q),
+                None => format!("{}/", req_path),
+            };
+            return Response::error(301).with_header("Location", &location);
+        }
+
+        let index = dir.join("index.html");
+        if index.is_file() {
+            return self.serve_file(ctx, &index);
+        }
+        if !self.is_listing {
+            return Response::error(403);
+        }
+
+        let mut entries = match fs::read_dir(dir) {
+            Ok(rd) => rd
+                .filter_map(|e| e.ok())
+                .map(|e| {
+                    let is_dir = e.file_type().map(|t| t.is_dir()).unwrap_or(false);
+                    (e.file_name().to_string_lossy().into_owned(), is_dir)
+                })
+                .collect::<Vec<_>>(),
+            Err(e) => return error_of(&e),
+        };
+        entries.sort();
+
+        let title = html_escape(&percent_decode(req_path, false).unwrap_or_default());
+        let mut html = format!(
+            "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>Index of {0}</title></head>\n<body>\n<h1>Index of {0}</h1>\n<ul>\n<li><a href=\"../\">../</a></li>\n",
+            title
+        );
+        for (name, is_dir) in entries {
+            let slash = if is_dir { "/" } else { "" };
+            html.push_str(&format!(
+                "<li><a href=\"{}{}\">{}{}</a></li>\n",
+                percent_encode(&name),
+                slash,
+                html_escape(&name),
+                slash
+            ));
+        }
+        html.push_str("</ul>\n</body>\n</html>\n");
+
+        Response::ok()
+            .with_header("Content-Type", "text/html; charset=utf-8")
+            .with_bytes(html)
+    }
+
+    fn serve_file(&self, ctx: &Context, path: &Path) -> Response {
+        let file = match File::open(path) {
+            Ok(f) => f,
+            Err(e) => return error_of(&e),
+        };
+        let meta = match file.metadata() {
+            Ok(m) => m,
+            Err(e) => return error_of(&e),
+        };
+        let len = meta.len();
+        let etag = etag_of(&meta);
+        let modified = meta.modified().ok().map(truncate_secs);
+
+        let mut res = Response::ok().with_header("Accept-Ranges", "bytes");
+        if let Some(ref etag) = etag {
+            res.set_header("ETag", etag);
+        }
+        if let Some(t) = modified {
+            res.set_header("Last-Modified", &format_http_date(t));
+        }
+
+        // If-None-Match takes precedence over If-Modified-Since.
+        let headers = &ctx.request.headers;
+        let is_not_modified = match headers.get("If-None-Match") {
+            Some(v) => etag.as_ref().is_some_and(|e| etag_matches(v, e)),
+            None => headers
+                .get("If-Modified-Since")
+                .and_then(parse_http_date)
+                .is_some_and(|since| modified.is_some_and(|m| m <= since)),
+        };
+        if is_not_modified {
+            res.status = 304;
+            res.reason = "Not Modified".to_string();
+            return res;
+        }
+
+        let content_type = mime_type(path);
+        res.set_header("Content-Type", content_type);
+
+        // The range is ignored if the file has changed from If-Range.
+        let is_range_valid = match headers.get("If-Range") {
+            None => true,
+            Some(v) if v.trim_start().starts_with('"') => {
+                etag.as_ref().is_some_and(|e| v.trim() == e)
+            }
+            Some(v) => parse_http_date(v).is_some_and(|t| modified == Some(t)),
+        };
+        let ranges = match headers.get("Range") {
+            Some(v) if is_range_valid => parse_range(v, len),
+            _ => None,
+        };
+
+        match ranges {
+            None => match res.with_file(file) {
+                Ok(res) => res,
+                Err(e) => error_of(&e),
+            },
+            Some(ref ranges) if ranges.is_empty() => {
+                let mut res = Response::error(416);
+                res.set_header("Content-Range", &format!("bytes */{}", len));
+                res
+            }
+            Some(ranges) => partial(res, file, len, content_type, ranges),
+        }
+    }
+}
+
+fn partial(
+    mut res: Response,
+    mut file: File,
+    len: u64,
+    content_type: &str,
+    ranges: Vec<(u64, u64)>,
+) -> Response {
+    res.status = 206;
+    res.reason = "Partial Content".to_string();
+
+    if let [(first, last)] = *ranges.as_slice() {
+        res.set_header(
+            "Content-Range",
+            &format!("bytes {}-{}/{}", first, last, len),
+        );
+        return match file.seek(SeekFrom::Start(first)) {
+            Ok(_) => {
+                res.body = ResponseBody::File(file, last - first + 1);
+                res
+            }
+            Err(e) => error_of(&e),
+        };
+    }
+
+    let boundary = format!(
+        "{:016x}",
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs() ^ u64::from(d.subsec_nanos()) << 32)
+    );
+    let mut pieces = VecDeque::new();
+    for (first, last) in ranges {
+        let head = format!(
+            "\r\n--{}\r\nContent-Type: {}\r\nContent-Range: bytes {}-{}/{}\r\n\r\n",
+            boundary, content_type, first, last, len
+        );
+        pieces.push_back(Piece::Bytes(head.into_bytes()));
+        pieces.push_back(Piece::Range(first, last - first + 1));
+    }
+    pieces.push_back(Piece::Bytes(
+        format!("\r\n--{}--\r\n", boundary).into_bytes(),
+    ));
+
+    let total = pieces
+        .iter()
+        .map(|p| match *p {
+            Piece::Bytes(ref b) => b.len() as u64,
+            Piece::Range(_, n) => n,
+        })
+        .sum();
+    res.set_header(
+        "Content-Type",
+        &format!("multipart/byteranges; boundary={}", boundary),
+    );
+    res.with_sized_stream(
+        Multipart {
+            file,
+            pieces,
+            pos: 0,
+        },
+        total,
+    )
+}
+
+impl Handler for StaticFiles {
+    fn handle(&self, ctx: &Context) -> Response {
+        let path = ctx.params.get("path").unwrap_or("");
+        let full = match self.resolve(path) {
+            Ok(p) => p,
+            Err(res) => return res,
+        };
+
+        if full.is_dir() {
+            self.serve_dir(ctx, &full)
+        } else {
+            self.serve_file(ctx, &full)
+        }
+    }
+}
+
+#[cfg(test)]
+fn test_root(name: &str) -> PathBuf {
+    let root =
+        ::std::env::temp_dir().join(format!("http_server_{}_{}", name, ::std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(root.join("sub")).unwrap();
+    fs::write(root.join("a.txt"), "0123456789").unwrap();
+    fs::write(root.join("sub/b <&>.bin"), "b").unwrap();
+    root
+}
+
+#[cfg(test)]
+fn fetch(root: &Path, path: &str, headers: &str) -> String {
+    use parser::{parse, Method, ParseResult};
+    use router::Router;
+
+    let router = Router::new().get("/files/*path", StaticFiles::new(root));
+    let buf = format!("GET {} HTTP/1.1\r\nHost: a\r\n{}\r\n", path, headers);
+    let req = match parse(buf.as_bytes()) {
+        ParseResult::Complete(req) => req,
+        r => panic!("{:?}", r),
+    };
+    let mut out = Vec::new();
+    router
+        .handle(&req, b"")
+        .write_to(&mut out, req.version, Method::Get, true)
+        .unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn static_files_helpers() {
+    assert_eq!(mime_type(Path::new("a/b.HTML")), "text/html; charset=utf-8");
+    assert_eq!(mime_type(Path::new("x.tar.gz")), "application/gzip");
+    assert_eq!(mime_type(Path::new("README")), "application/octet-stream");
+
+    assert_eq!(sanitize("/a/./b//c"), Some(PathBuf::from("a/b/c")));
+    assert_eq!(sanitize("a/../../etc/passwd"), None);
+    assert_eq!(sanitize("a\\..\\b"), None);
+
+    assert_eq!(parse_range("bytes=0-4", 10), Some(vec![(0, 4)]));
+    assert_eq!(parse_range("bytes=5-, -3", 10), Some(vec![(5, 9), (7, 9)]));
+    assert_eq!(parse_range("bytes=8-100", 10), Some(vec![(8, 9)]));
+    assert_eq!(parse_range("bytes=-20", 10), Some(vec![(0, 9)]));
+    assert_eq!(parse_range("bytes=10-", 10), Some(vec![]));
+    assert_eq!(parse_range("bytes=5-4", 10), None);
+    assert_eq!(parse_range("items=0-1", 10), None);
+    assert_eq!(parse_range("bytes=a-b", 10), None);
+
+    assert!(etag_matches("\"x\", W/\"y\"", "\"y\""));
+    assert!(etag_matches("*", "\"y\""));
+    assert!(!etag_matches("\"x\"", "\"y\""));
+}
+
+#[test]
+fn static_files_conditional() {
+    let root = test_root("conditional");
+    let res = fetch(&root, "/files/a.txt", "");
+    assert!(res.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(res.contains("Content-Type: text/plain; charset=utf-8\r\n"));
+    assert!(res.contains("Content-Length: 10\r\n"));
+    assert!(res.ends_with("\r\n\r\n0123456789"));
+
+    let header = |name: &str| {
+        let start = res.find(&format!("{}: ", name)).unwrap() + name.len() + 2;
+        res[start..start + res[start..].find("\r\n").unwrap()].to_string()
+    };
+    let etag = header("ETag");
+    let modified = header("Last-Modified");
+
+    let res = fetch(
+        &root,
+        "/files/a.txt",
+        &format!("If-None-Match: {}\r\n", etag),
+    );
+    assert!(res.starts_with("HTTP/1.1 304 Not Modified\r\n"));
+    assert!(res.ends_with("\r\n\r\n"));
+    let res = fetch(&root, "/files/a.txt", "If-None-Match: \"other\"\r\n");
+    assert!(res.starts_with("HTTP/1.1 200 OK\r\n"));
+
+    let res = fetch(
+        &root,
+        "/files/a.txt",
+        &format!("If-Modified-Since: {}\r\n", modified),
+    );
+    assert!(res.starts_with("HTTP/1.1 304 Not Modified\r\n"));
+    let res = fetch(
+        &root,
+        "/files/a.txt",
+        "If-Modified-Since: Thu, 01 Jan 1970 00:00:00 GMT\r\n",
+    );
+    assert!(res.starts_with("HTTP/1.1 200 OK\r\n"));
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn static_files_range() {
+    let root = test_root("range");
+    let res = fetch(&root, "/files/a.txt", "Range: bytes=2-4\r\n");
+    assert!(res.starts_with("HTTP/1.1 206 Partial Content\r\n"));
+    assert!(res.contains("Content-Range: bytes 2-4/10\r\n"));
+    assert!(res.ends_with("Content-Length: 3\r\n\r\n234"));
+
+    let res = fetch(&root, "/files/a.txt", "Range: bytes=0-1,-2\r\n");
+    assert!(res.starts_with("HTTP/1.1 206 Partial Content\r\n"));
+    let start = res.find("boundary=").unwrap() + 9;
+    let boundary = &res[start..start + 16];
+    let body = &res[res.find("\r\n\r\n").unwrap() + 4..];
+    assert_eq!(
+        body,
+        format!(
+            "\r\n--{0}\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Range: bytes 0-1/10\r\n\r\n01\
+             \r\n--{0}\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Range: bytes 8-9/10\r\n\r\n89\
+             \r\n--{0}--\r\n",
+            boundary
+        )
+    );
+    assert!(res.contains(&format!("Content-Length: {}\r\n", body.len())));
+
+    let res = fetch(&root, "/files/a.txt", "Range: bytes=10-\r\n");
+    assert!(res.starts_with("HTTP/1.1 416 Range Not Satisfiable\r\n"));
+    assert!(res.contains("Content-Range: bytes */10\r\n"));
+
+    // The stale If-Range gets the whole file.
+    let res = fetch(
+        &root,
+        "/files/a.txt",
+        "Range: bytes=2-4\r\nIf-Range: \"old\"\r\n",
+    );
+    assert!(res.starts_with("HTTP/1.1 200 OK\r\n"));
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn static_files_directory() {
+    let root = test_root("directory");
+    let res = fetch(&root, "/files/sub", "");
+    assert!(res.starts_with("HTTP/1.1 301 Moved Permanently\r\n"));
+    assert!(res.contains("Location: /files/sub/\r\n"));
+
+    let res = fetch(&root, "/files/", "");
+    assert!(res.contains("<a href=\"a.txt\">a.txt</a>"));
+    assert!(res.contains("<a href=\"sub/\">sub/</a>"));
+    let res = fetch(&root, "/files/sub/", "");
+    assert!(res.contains("<a href=\"b%20%3C%26%3E.bin\">b &lt;&amp;&gt;.bin</a>"));
+
+    fs::write(root.join("sub/index.html"), "index").unwrap();
+    let res = fetch(&root, "/files/sub/", "");
+    assert!(res.contains("Content-Type: text/html; charset=utf-8\r\n"));
+    assert!(res.ends_with("\r\n\r\nindex"));
+
+    assert!(fetch(&root, "/files/none", "").starts_with("HTTP/1.1 404 Not Found\r\n"));
+    assert!(fetch(&root, "/files/sub/%2e%2e/%2e%2e/etc/passwd", "")
+        .starts_with("HTTP/1.1 403 Forbidden\r\n"));
+
+    // The symbolic link can't lead out of the root.
+    ::std::os::unix::fs::symlink("/etc", root.join("etc")).unwrap();
+    assert!(fetch(&root, "/files/etc/passwd", "").starts_with("HTTP/1.1 403 Forbidden\r\n"));
+
+    fs::remove_dir_all(&root).unwrap();
+}
@@ -0,0 +1,158 @@
+// The system calls which std doesn't provide.
+#![allow(non_camel_case_types)]
+
+use std::io;
+use std::os::raw::{c_int, c_short, c_ulong};
+use std::os::unix::io::RawFd;
+
+pub const SIGHUP: c_int = 1;
+pub const SIGINT: c_int = 2;
+pub const SIGTERM: c_int = 15;
+
+const POLLIN: c_short = 0x1;
+
+#[repr(C)]
+struct pollfd {
+    fd: c_int,
+    events: c_short,
+    revents: c_short,
+}
+
+pub const EPOLLIN: u32 = 0x1;
+pub const EPOLLOUT: u32 = 0x4;
+pub const EPOLLRDHUP: u32 = 0x2000;
+
+const EPOLL_CLOEXEC: c_int = 0o2_000_000;
+const EPOLL_CTL_ADD: c_int = 1;
+const EPOLL_CTL_DEL: c_int = 2;
+const EPOLL_CTL_MOD: c_int = 3;
+
+// The kernel packs it on x86_64 only.
+// The errors and the hang-ups are reported always, they are found by the following read or write.
+#[cfg_attr(target_arch = "x86_64", repr(C, packed))]
+#[cfg_attr(not(target_arch = "x86_64"), repr(C))]
+#[derive(Clone, Copy)]
+pub struct epoll_event {
+    events: u32,
+    data: u64,
+}
+
+impl epoll_event {
+    pub fn token(&self) -> u64 {
+        self.data
+    }
+}
+
+type sighandler_t = extern "C" fn(c_int);
+
+extern "C" {
+    fn poll(fds: *mut pollfd, nfds: c_ulong, timeout: c_int) -> c_int;
+    fn signal(signum: c_int, handler: sighandler_t) -> usize;
+    fn epoll_create1(flags: c_int) -> c_int;
+    fn epoll_ctl(epfd: c_int, op: c_int, fd: c_int, event: *mut epoll_event) -> c_int;
+    fn epoll_wait(epfd: c_int, events: *mut epoll_event, maxevents: c_int, timeout: c_int)
+        -> c_int;
+    fn close(fd: c_int) -> c_int;
+}
+
+fn check(r: c_int) -> io::Result<c_int> {
+    if r == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(r)
+    }
+}
+
+// Wait until some of the fds become readable, and return their indices.
+// Nothing is returned if the time is out or a signal comes.
+pub fn wait_readable(fds: &[RawFd], timeout_ms: i32) -> io::Result<Vec<usize>> {
+    let mut pfds: Vec<pollfd> = fds
+        .iter()
+        .map(|&fd| pollfd {
+            fd,
+            events: POLLIN,
+            revents: 0,
+        })
+        .collect();
+    if unsafe { poll(pfds.as_mut_ptr(), pfds.len() as c_ulong, timeout_ms) } == -1 {
+        let e = io::Error::last_os_error();
+        return if e.kind() == io::ErrorKind::Interrupted {
+            Ok(Vec::new())
+        } else {
+            Err(e)
+        };
+    }
+    Ok(pfds
+        .iter()
+        .enumerate()
+        .filter(|&(_, p)| p.revents != 0)
+        .map(|(i, _)| i)
+        .collect())
+}
+
+pub fn set_signal_handler(signum: c_int, handler: extern "C" fn(c_int)) {
+    unsafe {
+        signal(signum, handler);
+    }
+}
+
+pub struct Epoll {
+    fd: RawFd,
+}
+
+impl Epoll {
+    pub fn new() -> io::Result<Epoll> {
+        let fd = check(unsafe { epoll_create1(EPOLL_CLOEXEC) })?;
+        Ok(Epoll { fd })
+    }
+
+    fn ctl(&self, op: c_int, fd: RawFd, events: u32, token: u64) -> io::Result<()> {
+        let mut e = epoll_event {
+            events,
+            data: token,
+        };
+        check(unsafe { epoll_ctl(self.fd, op, fd, &mut e) }).map(|_| ())
+    }
+
+    // The events are level-triggered.
+    pub fn add(&self, fd: RawFd, events: u32, token: u64) -> io::Result<()> {
+        self.ctl(EPOLL_CTL_ADD, fd, events, token)
+    }
+
+    pub fn modify(&self, fd: RawFd, events: u32, token: u64) -> io::Result<()> {
+        self.ctl(EPOLL_CTL_MOD, fd, events, token)
+    }
+
+    pub fn delete(&self, fd: RawFd) -> io::Result<()> {
+        self.ctl(EPOLL_CTL_DEL, fd, 0, 0)
+    }
+
+    // Fill the events and return the number of them, zero if the time is out or a signal comes.
+    pub fn wait(&self, events: &mut [epoll_event], timeout_ms: i32) -> io::Result<usize> {
+        let n = unsafe {
+            epoll_wait(
+                self.fd,
+                events.as_mut_ptr(),
+                events.len() as c_int,
+                timeout_ms,
+            )
+        };
+        match check(n) {
+            Ok(n) => Ok(n as usize),
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => Ok(0),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn events(capacity: usize) -> Vec<epoll_event> {
+        vec![epoll_event { events: 0, data: 0 }; capacity]
+    }
+}
+
+impl Drop for Epoll {
+    fn drop(&mut self) {
+        unsafe {
+            close(self.fd);
+        }
+    }
+}
@@ -0,0 +1,9 @@
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate tutti;
+
+// cargo fuzz run execute
+fuzz_target!(|data: &[u8]| {
+    tutti::fuzz::execute_genomes(data);
+});
@@ -0,0 +1,12 @@
+use instruction::Instruction;
+use instruction::Instruction::*;
+
+// The self-replicating ancestor which seeds the soup.
+pub const ANCESTOR: [Instruction; 80] = [
+    Nop1, Nop1, Nop1, Nop1, Zero, Or1, Shl, Shl, MovCd, Adrb, Nop0, Nop0, Nop0, Nop0, SubAc, MovAb,
+    Adrf, Nop0, Nop0, Nop0, Nop1, IncA, SubAb, Nop1, Nop1, Nop0, Nop1, Mal, Call, Nop0, Nop0, Nop1,
+    Nop1, Divide, Jmpb, Nop0, Nop0, Nop1, Nop0, IfCz, Nop1, Nop1, Nop0, Nop0, PushAx, PushBx,
+    PushCx, Nop1, Nop0, Nop1, Nop0, MovIab, DecC, IfCz, Jmp, Nop0, Nop1, Nop0, Nop0, IncA, IncB,
+    Jmpb, Nop0, Nop1, Nop0, Nop1, IfCz, Nop1, Nop0, Nop1, Nop1, PopCx, PopBx, PopAx, Ret, Nop1,
+    Nop1, Nop1, Nop0, IfCz,
+];
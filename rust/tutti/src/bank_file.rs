@@ -0,0 +1,654 @@
+use gene_bank::GeneBank;
+use instruction::{decode_genome, encode_genome, Instruction};
+use std::collections::HashMap;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::{BufReader, BufWriter};
+use std::iter::Peekable;
+use std::path::{Path, PathBuf};
+use std::str::Chars;
+
+pub const BANK_FILE_FORMAT: &str = "tutti-bank";
+pub const BANK_FILE_VERSION: usize = 1;
+
+// The bank file is JSON lines, the header is followed by the genotypes like below.
+//   {"format":"tutti-bank","version":1,"world_clock":1000}
+//   {"id":1,"parent":0,"geno_type":"80-1","alive":3,"dead":10,"first_seen":20,"last_seen":990,"genome":"0101..."}
+// The id is unique while different genomes can have the same geno_type.
+// The counts of creatures and the clocks are of the geno_type.
+// The files without the header are read as version 0 which doesn't have the clocks.
+// The objects can be formatted in any way like the output of jq.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BankRecord {
+    pub id: usize,
+    pub parent: Option<usize>,
+    pub geno_type: String,
+    pub genome: Vec<Instruction>,
+    pub alive: usize,
+    pub dead: usize,
+    pub first_seen: usize,
+    pub last_seen: usize,
+}
+
+impl BankRecord {
+    pub fn size(&self) -> usize {
+        self.genome.len()
+    }
+
+    pub fn births(&self) -> usize {
+        self.alive + self.dead
+    }
+
+    fn to_json(&self) -> String {
+        format!(
+            "{{\"id\":{},\"parent\":{},\"geno_type\":\"{}\",\"alive\":{},\"dead\":{},\"first_seen\":{},\"last_seen\":{},\"genome\":\"{}\"}}",
+            self.id,
+            self.parent
+                .map_or("null".to_string(), |p| p.to_string()),
+            escape(&self.geno_type),
+            self.alive,
+            self.dead,
+            self.first_seen,
+            self.last_seen,
+            encode_genome(&self.genome)
+        )
+    }
+
+    fn from_json(mut obj: HashMap<String, JsonValue>, version: usize) -> Option<BankRecord> {
+        let mut num = |key: &str| match obj.remove(key) {
+            Some(JsonValue::Number(n)) => Some(n),
+            _ => None,
+        };
+        let (id, alive, dead) = (num("id")?, num("alive")?, num("dead")?);
+        let (first_seen, last_seen) = if version == 0 {
+            (0, 0)
+        } else {
+            (num("first_seen")?, num("last_seen")?)
+        };
+        let parent = match obj.remove("parent") {
+            Some(JsonValue::Null) => None,
+            Some(JsonValue::Number(n)) => Some(n),
+            _ => return None,
+        };
+        let (geno_type, genome) = match (obj.remove("geno_type"), obj.remove("genome")) {
+            (Some(JsonValue::Str(t)), Some(JsonValue::Str(g))) => (t, decode_genome(&g)?),
+            _ => return None,
+        };
+
+        Some(BankRecord {
+            id,
+            parent,
+            geno_type,
+            genome,
+            alive,
+            dead,
+            first_seen,
+            last_seen,
+        })
+    }
+
+    // One instruction per line with its offset.
+    pub fn to_assembly(&self) -> String {
+        let mut s = format!(
+            "; genotype: {}, id: {}, parent: {}, size: {}\n",
+            self.geno_type,
+            self.id,
+            self.parent.map_or("-".to_string(), |p| p.to_string()),
+            self.size()
+        );
+        for (i, ins) in self.genome.iter().enumerate() {
+            s.push_str(&format!("{:<8}; {}\n", ins.mnemonic(), i));
+        }
+        s
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RecordFilter {
+    pub min_size: Option<usize>,
+    pub max_size: Option<usize>,
+    pub min_births: Option<usize>,
+    pub min_alive: Option<usize>,
+}
+
+impl RecordFilter {
+    pub fn matches(&self, r: &BankRecord) -> bool {
+        self.min_size.is_none_or(|n| n <= r.size())
+            && self.max_size.is_none_or(|n| r.size() <= n)
+            && self.min_births.is_none_or(|n| n <= r.births())
+            && self.min_alive.is_none_or(|n| n <= r.alive)
+    }
+}
+
+pub struct BankFile {
+    // The clock when the snapshot was taken.
+    pub world_clock: usize,
+    records: Vec<BankRecord>,
+}
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+impl BankFile {
+    pub fn from_gene_bank(bank: &GeneBank, world_clock: usize) -> BankFile {
+        let records = bank
+            .records()
+            .iter()
+            .map(|r| {
+                let geno_type = r.geno_type();
+                let alive = bank.count_alive(&geno_type);
+                let (first_seen, last_seen) = bank.seen_clocks(&geno_type).unwrap_or((0, 0));
+                BankRecord {
+                    id: r.id(),
+                    parent: r.mother().map(|m| m.id()),
+                    alive,
+                    dead: bank.count_dead(&geno_type),
+                    geno_type,
+                    genome: r.genome().to_vec(),
+                    first_seen,
+                    // The alive genotypes are seen now.
+                    last_seen: if alive == 0 { last_seen } else { world_clock },
+                }
+            })
+            .collect();
+
+        BankFile {
+            world_clock,
+            records,
+        }
+    }
+
+    // Rebuild the GeneBank, the ids have to be the indices and the parents have to be older.
+    pub fn to_gene_bank(&self) -> io::Result<GeneBank> {
+        let mut bank = GeneBank::new();
+        for (i, r) in self.records.iter().enumerate() {
+            let tag = if r.id == i {
+                bank.restore_record(r.genome.clone(), r.parent.filter(|&p| p < i))
+            } else {
+                None
+            };
+            if tag.as_ref() != Some(&r.geno_type) {
+                return Err(invalid_data(format!("record {} is inconsistent", r.id)));
+            }
+
+            bank.restore_counts(&r.geno_type, r.alive, r.dead);
+            bank.restore_seen_clocks(&r.geno_type, r.first_seen, r.last_seen);
+        }
+        Ok(bank)
+    }
+
+    pub fn read<R: BufRead>(mut reader: R) -> io::Result<BankFile> {
+        let mut text = String::new();
+        reader.read_to_string(&mut text)?;
+
+        let mut chars = text.chars().peekable();
+        let mut version = None;
+        let mut world_clock = 0;
+        let mut records = Vec::new();
+        for n in 1.. {
+            skip_whitespace(&mut chars);
+            if chars.peek().is_none() {
+                break;
+            }
+            let invalid = || invalid_data(format!("object {}: invalid record", n));
+            let obj = next_json_object(&mut chars).ok_or_else(invalid)?;
+
+            if version.is_none() {
+                if obj.get("format") == Some(&JsonValue::Str(BANK_FILE_FORMAT.into())) {
+                    match (obj.get("version"), obj.get("world_clock")) {
+                        (Some(&JsonValue::Number(v)), Some(&JsonValue::Number(c)))
+                            if v <= BANK_FILE_VERSION =>
+                        {
+                            version = Some(v);
+                            world_clock = c;
+                        }
+                        _ => return Err(invalid_data("unsupported header".to_string())),
+                    }
+                    continue;
+                }
+                version = Some(0);
+            }
+
+            let r = BankRecord::from_json(obj, version.unwrap()).ok_or_else(invalid)?;
+            records.push(r);
+        }
+
+        Ok(BankFile {
+            world_clock,
+            records,
+        })
+    }
+
+    pub fn load(path: &Path) -> io::Result<BankFile> {
+        BankFile::read(BufReader::new(File::open(path)?))
+    }
+
+    pub fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        writeln!(
+            w,
+            "{{\"format\":\"{}\",\"version\":{},\"world_clock\":{}}}",
+            BANK_FILE_FORMAT, BANK_FILE_VERSION, self.world_clock
+        )?;
+        for r in self.records.iter() {
+            writeln!(w, "{}", r.to_json())?;
+        }
+        Ok(())
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut w = BufWriter::new(File::create(path)?);
+        self.write(&mut w)?;
+        w.flush()
+    }
+
+    pub fn records(&self) -> &[BankRecord] {
+        &self.records
+    }
+
+    pub fn find_by_id(&self, id: usize) -> Option<&BankRecord> {
+        self.records.iter().find(|r| r.id == id)
+    }
+
+    // The key is the id or the geno_type.
+    pub fn find(&self, key: &str) -> Option<&BankRecord> {
+        match key.parse() {
+            Ok(id) => self.find_by_id(id),
+            Err(_) => self.records.iter().find(|r| r.geno_type == key),
+        }
+    }
+
+    pub fn filter(&self, filter: &RecordFilter) -> Vec<&BankRecord> {
+        self.records.iter().filter(|r| filter.matches(r)).collect()
+    }
+
+    // The record and its ancestors from the mother to the first one.
+    pub fn ancestry(&self, id: usize) -> Vec<&BankRecord> {
+        let mut v = Vec::new();
+        let mut next = Some(id);
+        while let Some(r) = next.and_then(|id| self.find_by_id(id)) {
+            // The records are only linked to the older ones, but the file may be broken.
+            if v.len() == self.records.len() {
+                break;
+            }
+            v.push(r);
+            next = r.parent;
+        }
+        v
+    }
+
+    pub fn find_template(&self, template: &[Instruction]) -> Vec<&BankRecord> {
+        if template.is_empty() {
+            return Vec::new();
+        }
+
+        self.records
+            .iter()
+            .filter(|r| r.genome.windows(template.len()).any(|w| w == template))
+            .collect()
+    }
+}
+
+// Snapshots of the bank are saved into the numbered files and the old ones are removed.
+pub struct BankRotation {
+    prefix: String,
+    keep: usize,
+    count_snapshots: usize,
+}
+
+impl BankRotation {
+    pub fn new(prefix: &str, keep: usize) -> BankRotation {
+        BankRotation {
+            prefix: prefix.to_string(),
+            keep,
+            count_snapshots: 0,
+        }
+    }
+
+    fn path(&self, index: usize) -> PathBuf {
+        PathBuf::from(format!("{}.{:06}.bank.jsonl", self.prefix, index))
+    }
+
+    pub fn save(&mut self, file: &BankFile) -> io::Result<PathBuf> {
+        let path = self.path(self.count_snapshots);
+        file.save(&path)?;
+        self.count_snapshots += 1;
+
+        if self.keep < self.count_snapshots {
+            match fs::remove_file(self.path(self.count_snapshots - self.keep - 1)) {
+                Err(ref e) if e.kind() != io::ErrorKind::NotFound => {
+                    return Err(io::Error::new(e.kind(), e.to_string()))
+                }
+                _ => {}
+            }
+        }
+
+        Ok(path)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum JsonValue {
+    Null,
+    Number(usize),
+    Str(String),
+}
+
+fn escape(s: &str) -> String {
+    let mut e = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => e.push_str("\\\""),
+            '\\' => e.push_str("\\\\"),
+            '\n' => e.push_str("\\n"),
+            '\r' => e.push_str("\\r"),
+            '\t' => e.push_str("\\t"),
+            c if c.is_control() => e.push_str(&format!("\\u{:04x}", c as u32)),
+            c => e.push(c),
+        }
+    }
+    e
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars>) {
+    while let Some(' ') | Some('\t') | Some('\n') | Some('\r') = chars.peek() {
+        chars.next();
+    }
+}
+
+fn parse_hex4(chars: &mut Peekable<Chars>) -> Option<u32> {
+    let mut n = 0;
+    for _ in 0..4 {
+        n = n * 16 + chars.next()?.to_digit(16)?;
+    }
+    Some(n)
+}
+
+// The rest of the string after the opening quote.
+fn parse_string(chars: &mut Peekable<Chars>) -> Option<String> {
+    let mut s = String::new();
+    loop {
+        let c = match chars.next()? {
+            '"' => return Some(s),
+            '\\' => match chars.next()? {
+                '"' => '"',
+                '\\' => '\\',
+                '/' => '/',
+                'b' => '\u{8}',
+                'f' => '\u{c}',
+                'n' => '\n',
+                'r' => '\r',
+                't' => '\t',
+                'u' => {
+                    let high = parse_hex4(chars)?;
+                    // The characters out of BMP are the surrogate pairs.
+                    let code = if (0xD800..0xDC00).contains(&high) {
+                        if chars.next()? != '\\' || chars.next()? != 'u' {
+                            return None;
+                        }
+                        let low = parse_hex4(chars).filter(|n| (0xDC00..0xE000).contains(n))?;
+                        0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+                    } else {
+                        high
+                    };
+                    char::from_u32(code)?
+                }
+                _ => return None,
+            },
+            c if c < ' ' => return None,
+            c => c,
+        };
+        s.push(c);
+    }
+}
+
+// The next flat object of the stream, the whitespace before it is skipped.
+// The values are null, non-negative integers or strings.
+fn next_json_object(chars: &mut Peekable<Chars>) -> Option<HashMap<String, JsonValue>> {
+    let mut obj = HashMap::new();
+
+    skip_whitespace(chars);
+    if chars.next()? != '{' {
+        return None;
+    }
+
+    loop {
+        skip_whitespace(chars);
+        match chars.next()? {
+            '}' if obj.is_empty() => break,
+            '"' => {}
+            _ => return None,
+        }
+        let key = parse_string(chars)?;
+        skip_whitespace(chars);
+        if chars.next()? != ':' {
+            return None;
+        }
+        skip_whitespace(chars);
+
+        let value = match *chars.peek()? {
+            '"' => {
+                chars.next();
+                JsonValue::Str(parse_string(chars)?)
+            }
+            'n' => {
+                if chars.by_ref().take(4).collect::<String>() != "null" {
+                    return None;
+                }
+                JsonValue::Null
+            }
+            _ => {
+                let mut digits = String::new();
+                while let Some(&c) = chars.peek().filter(|c| c.is_ascii_digit()) {
+                    digits.push(c);
+                    chars.next();
+                }
+                JsonValue::Number(digits.parse().ok()?)
+            }
+        };
+        obj.insert(key, value);
+
+        skip_whitespace(chars);
+        match chars.next()? {
+            ',' => {}
+            '}' => break,
+            _ => return None,
+        }
+    }
+    Some(obj)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use instruction::Instruction::*;
+    use std::env;
+    use std::process;
+
+    fn prepare_bank_file() -> BankFile {
+        let mut bank = GeneBank::new();
+        let t0 = bank
+            .register_genome(&vec![Nop1, Mal, Divide], None)
+            .unwrap();
+        let t1 = bank
+            .register_genome(&vec![Nop1, Mal, Nop0, Divide], Some(&t0))
+            .unwrap();
+        bank.register_genome(&vec![Nop1, Nop0, Divide], Some(&t1));
+        bank.count_up_alive_genome(&t0);
+        bank.count_up_alive_genome(&t1);
+        bank.count_up_alive_genome(&t1);
+        bank.count_up_dead_genome(&t1);
+        bank.record_seen(&t0, 0);
+        bank.record_seen(&t1, 10);
+        bank.record_seen(&t1, 20);
+
+        BankFile::from_gene_bank(&bank, 30)
+    }
+
+    fn parse_json_object(s: &str) -> Option<HashMap<String, JsonValue>> {
+        let mut chars = s.chars().peekable();
+        next_json_object(&mut chars).filter(|_| chars.next().is_none())
+    }
+
+    #[test]
+    fn test_parse_json_object() {
+        let obj = parse_json_object(r#"{"a":null,"b":12,"c":"x\"y"}"#).unwrap();
+        assert_eq!(obj["a"], JsonValue::Null);
+        assert_eq!(obj["b"], JsonValue::Number(12));
+        assert_eq!(obj["c"], JsonValue::Str("x\"y".to_string()));
+        assert_eq!(parse_json_object("{}"), Some(HashMap::new()));
+        assert_eq!(parse_json_object(" { } "), None);
+        assert_eq!(parse_json_object(r#"{"a":1"#), None);
+        assert_eq!(parse_json_object(r#"{"a":-1}"#), None);
+        assert_eq!(parse_json_object(r#"{"a":1} x"#), None);
+
+        let obj = parse_json_object(
+            "{ \"a\" :\tnull ,\n  \"b\": 12,\r\n\"c\": \"\\n\\t\\/\\\\\\u00e9\\uD83D\\uDE00\" }",
+        )
+        .unwrap();
+        assert_eq!(obj["a"], JsonValue::Null);
+        assert_eq!(obj["b"], JsonValue::Number(12));
+        assert_eq!(
+            obj["c"],
+            JsonValue::Str("\n\t/\\\u{e9}\u{1F600}".to_string())
+        );
+        assert_eq!(parse_json_object(r#"{"a":"\x"}"#), None);
+        assert_eq!(parse_json_object(r#"{"a":"\uD83D"}"#), None);
+        assert_eq!(parse_json_object("{\"a\":\"\n\"}"), None);
+
+        let s = "a\"\\\n\u{1}\u{e9}";
+        let obj = parse_json_object(&format!("{{\"a\":\"{}\"}}", escape(s))).unwrap();
+        assert_eq!(obj["a"], JsonValue::Str(s.to_string()));
+    }
+
+    // The bank file edited by hand or formatted by jq.
+    #[test]
+    fn test_read_formatted_bank_file() {
+        let text = r#"
+{
+  "format": "tutti-bank",
+  "version": 1,
+  "world_clock": 30
+}
+{
+  "id": 0,
+  "parent": null,
+  "geno_type": "3\u002d0 \"x\"",
+  "alive": 1, "dead": 0,
+  "first_seen": 0, "last_seen": 30,
+  "genome": "011e1f"
+}
+  {"id":1, "parent":0, "geno_type":"4-0", "alive":0, "dead":1, "first_seen":3, "last_seen":4, "genome":"011e001f"}
+"#;
+        let file = BankFile::read(text.as_bytes()).unwrap();
+        assert_eq!(file.world_clock, 30);
+        let records = file.records();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].geno_type, "3-0 \"x\"");
+        assert_eq!(records[0].genome, vec![Nop1, Mal, Divide]);
+        assert_eq!(records[1].parent, Some(0));
+
+        let e = BankFile::read(
+            &b"{\"format\":\"tutti-bank\",\"version\":1,\"world_clock\":0} {\"id\""[..],
+        );
+        assert_eq!(
+            e.err().map(|e| e.to_string()),
+            Some("object 2: invalid record".to_string())
+        );
+    }
+
+    #[test]
+    fn test_write_read_bank_file() {
+        let file = prepare_bank_file();
+        let mut buf = Vec::new();
+        file.write(&mut buf).unwrap();
+        assert!(buf.starts_with(
+            b"{\"format\":\"tutti-bank\",\"version\":1,\"world_clock\":30}\n\
+              {\"id\":0,\"parent\":null,\"geno_type\":\"3-0\",\"alive\":1,\"dead\":0,\"first_seen\":0,\"last_seen\":30,\"genome\":\"011e1f\"}\n"
+        ));
+
+        let loaded = BankFile::read(&buf[..]).unwrap();
+        assert_eq!(loaded.world_clock, 30);
+        assert_eq!(loaded.records(), file.records());
+        assert!(BankFile::read(&b"{\"id\":0}\n"[..]).is_err());
+        assert!(BankFile::read(
+            &b"{\"format\":\"tutti-bank\",\"version\":2,\"world_clock\":0}\n"[..]
+        )
+        .is_err());
+
+        // The file without the header doesn't have the clocks.
+        let old = BankFile::read(
+            &b"{\"id\":0,\"parent\":null,\"geno_type\":\"3-0\",\"alive\":1,\"dead\":0,\"genome\":\"011e1f\"}\n"[..],
+        )
+        .unwrap();
+        assert_eq!(old.world_clock, 0);
+        assert_eq!(old.records()[0].genome, vec![Nop1, Mal, Divide]);
+        assert_eq!(old.records()[0].last_seen, 0);
+    }
+
+    #[test]
+    fn test_to_gene_bank() {
+        let file = prepare_bank_file();
+        let bank = file.to_gene_bank().unwrap();
+        assert_eq!(bank.count_records(), 3);
+        assert_eq!(bank.count_alive("4-1"), 1);
+        assert_eq!(bank.count_dead("4-1"), 1);
+        assert_eq!(bank.seen_clocks("3-0"), Some((0, 30)));
+        assert_eq!(bank.seen_clocks("4-1"), Some((10, 30)));
+        assert_eq!(bank.find_record("3-2").unwrap().mother().unwrap().id(), 1);
+        assert_eq!(
+            BankFile::from_gene_bank(&bank, 30).records(),
+            file.records()
+        );
+
+        let mut broken = prepare_bank_file();
+        broken.records[2].parent = None;
+        assert!(broken.to_gene_bank().is_err());
+    }
+
+    #[test]
+    fn test_bank_rotation() {
+        let dir = env::temp_dir().join(format!("tutti-bank-rotation-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let prefix = dir.join("bank");
+        let mut rotation = BankRotation::new(prefix.to_str().unwrap(), 2);
+
+        let file = prepare_bank_file();
+        let paths = (0..3)
+            .map(|_| rotation.save(&file).unwrap())
+            .collect::<Vec<_>>();
+        assert!(paths[2].ends_with("bank.000002.bank.jsonl"));
+        assert!(!paths[0].exists());
+        assert!(paths[1].exists());
+        assert_eq!(BankFile::load(&paths[2]).unwrap().records(), file.records());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_query_bank_file() {
+        let file = prepare_bank_file();
+        assert_eq!(file.find("4-1").unwrap().id, 1);
+        assert_eq!(file.find("2").unwrap().geno_type, "3-2");
+
+        let ids = |v: Vec<&BankRecord>| v.iter().map(|r| r.id).collect::<Vec<_>>();
+        let filter = RecordFilter {
+            max_size: Some(3),
+            ..Default::default()
+        };
+        assert_eq!(ids(file.filter(&filter)), vec![0, 2]);
+        let filter = RecordFilter {
+            min_births: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(ids(file.filter(&filter)), vec![1]);
+
+        assert_eq!(ids(file.ancestry(2)), vec![2, 1, 0]);
+        assert_eq!(ids(file.find_template(&[Nop0, Divide])), vec![1, 2]);
+        assert!(file.records()[0]
+            .to_assembly()
+            .ends_with("mal     ; 1\ndivide  ; 2\n"));
+    }
+}
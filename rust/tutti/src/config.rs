@@ -0,0 +1,79 @@
+use creature::COPY_FAIL_INTERVAL;
+use energy::EnergyConfig;
+use environment::Environment;
+use instruction::Instruction;
+use species::SpeciesCriterion;
+use universe::{COSMIC_RAY_INTERVAL, UNIVERSE_TOTAL_GENOME_CAPACITY};
+
+// What to do when all creatures have died out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExtinctionPolicy {
+    // Leave the soup empty, the caller is expected to stop.
+    Stop,
+    // Inject the given genome.
+    Reseed(Vec<Instruction>),
+    // Inject the genomes of the genotypes which were alive at the beginning of the last round.
+    ReseedFromSnapshot,
+}
+
+// The universe is stagnant if no new genotype appears for the given ticks of the world clock.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StagnationPolicy {
+    pub ticks: usize,
+    // The mutation rates are multiplied by this while stagnant.
+    pub mutation_boost: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    pub genome_capacity: usize,
+    pub is_enable_random_mutate: bool,
+    // A copy error happens once in the copies of a random number in this range.
+    pub copy_fail_interval: (usize, usize),
+    // A cosmic ray hits once in the ticks of a random number in this range.
+    pub cosmic_ray_interval: (usize, usize),
+    // The random number generator of the current thread is seeded by this if it is not None.
+    pub seed: Option<u64>,
+    // time slice of each creature is size^slice_power.
+    pub slice_power: f64,
+    // the reaper kills creatures while the usage rate of the soup is over this.
+    pub reaper_threshold: f64,
+    // Mal fails if the requested size is smaller than this.
+    // Too small daughters cannot replicate themselves.
+    pub minimum_daughter_size: usize,
+    // The resource model is disabled if this is None.
+    pub energy: Option<EnergyConfig>,
+    // Split and Join are enabled if this is larger than 1.
+    pub maximum_threads: usize,
+    // Put, Get and Send are enabled if this is true.
+    pub is_enable_communication: bool,
+    // In and Out are enabled and the tasks are rewarded if this is not None.
+    pub environment: Option<Environment>,
+    pub extinction_policy: ExtinctionPolicy,
+    // Stagnation is not detected if this is None.
+    pub stagnation: Option<StagnationPolicy>,
+    // Genotypes are clustered into species if this is not None.
+    pub species: Option<SpeciesCriterion>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            genome_capacity: UNIVERSE_TOTAL_GENOME_CAPACITY,
+            is_enable_random_mutate: false,
+            copy_fail_interval: COPY_FAIL_INTERVAL,
+            cosmic_ray_interval: COSMIC_RAY_INTERVAL,
+            seed: None,
+            slice_power: 1.2,
+            reaper_threshold: 0.8,
+            minimum_daughter_size: 21,
+            energy: None,
+            maximum_threads: 1,
+            is_enable_communication: false,
+            environment: None,
+            extinction_policy: ExtinctionPolicy::Stop,
+            stagnation: None,
+            species: None,
+        }
+    }
+}
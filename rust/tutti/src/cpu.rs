@@ -1,6 +1,6 @@
 use std::fmt;
 
-pub type Register = u16;
+pub type Register = u32;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Cpu {
@@ -28,6 +28,12 @@ impl fmt::Display for Cpu {
     }
 }
 
+impl Default for Cpu {
+    fn default() -> Cpu {
+        Cpu::new()
+    }
+}
+
 impl Cpu {
     pub fn new() -> Cpu {
         Cpu {
@@ -1,9 +1,12 @@
-extern crate rand;
-
-use cpu::Cpu;
+use cpu::{Cpu, Register};
+use environment::TaskState;
 use memory_region::MemoryRegion;
-use rand::Rng;
+use random;
 use std::cmp::Ordering;
+use std::mem;
+
+// A copy error happens once in the copies of this range by default.
+pub const COPY_FAIL_INTERVAL: (usize, usize) = (1000, 2500);
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Creature {
@@ -13,6 +16,13 @@ pub struct Creature {
     pub mutate_threshold_copy_fail: usize,
     pub count_copy: usize,
     pub geno_type: Option<String>,
+    pub energy: usize,
+    // The threads forked by Split, they share the genome and the time slice with the core.
+    pub threads: Vec<Cpu>,
+    // The thread which executes the next instruction, 0 is the core.
+    pub thread_index: usize,
+    pub is_joined: bool,
+    pub task_state: TaskState,
 }
 
 impl Ord for Creature {
@@ -30,7 +40,7 @@ impl PartialOrd for Creature {
 impl Creature {
     pub fn new(g: MemoryRegion) -> Creature {
         let mut core = Cpu::new();
-        core.ip = g.addr as u16;
+        core.ip = g.addr as Register;
         Creature {
             core: core,
             genome_region: g,
@@ -38,10 +48,32 @@ impl Creature {
             mutate_threshold_copy_fail: 0,
             count_copy: 0,
             geno_type: None,
+            energy: 0,
+            threads: Vec::new(),
+            thread_index: 0,
+            is_joined: false,
+            task_state: TaskState::new(),
+        }
+    }
+
+    pub fn count_threads(&self) -> usize {
+        self.threads.len() + 1
+    }
+
+    // Exchange the core and the given thread, so the thread can be executed as the core.
+    pub fn switch_thread(&mut self, index: usize) {
+        if index != 0 {
+            mem::swap(&mut self.core, &mut self.threads[index - 1]);
         }
     }
 
     pub fn randomize_mutate_threshold_copy_fail(&mut self) {
-        self.mutate_threshold_copy_fail = rand::thread_rng().gen_range(1000, 2500);
+        self.randomize_mutate_threshold_copy_fail_by(COPY_FAIL_INTERVAL, 1.0);
+    }
+
+    // The copy errors happen more frequently by the larger rate.
+    pub fn randomize_mutate_threshold_copy_fail_by(&mut self, interval: (usize, usize), rate: f64) {
+        let threshold = random::gen_range(interval.0, interval.1) as f64 / rate;
+        self.mutate_threshold_copy_fail = (threshold as usize).max(1);
     }
 }
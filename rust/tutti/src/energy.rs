@@ -0,0 +1,40 @@
+use instruction::{Instruction, INSTRUCTION_COUNT};
+
+// Parameters of the optional resource model.
+// Every instruction costs energy and a creature stops its time slice when it runs out.
+// Creatures are replenished from the global pool at the beginning of each round.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnergyConfig {
+    pub pool_capacity: usize,
+    // energy which flows into the pool every round.
+    pub pool_inflow: usize,
+    pub creature_capacity: usize,
+    // energy which each creature can take from the pool every round.
+    pub replenish_amount: usize,
+    // Divide fails unless the mother has this energy, it is handed to the daughter.
+    pub divide_cost: usize,
+    pub instruction_costs: [usize; INSTRUCTION_COUNT],
+}
+
+impl Default for EnergyConfig {
+    fn default() -> EnergyConfig {
+        let mut costs = [1; INSTRUCTION_COUNT];
+        costs[Instruction::MovIab as usize] = 2;
+        costs[Instruction::Mal as usize] = 10;
+
+        EnergyConfig {
+            pool_capacity: 1_000_000,
+            pool_inflow: 100_000,
+            creature_capacity: 1000,
+            replenish_amount: 150,
+            divide_cost: 100,
+            instruction_costs: costs,
+        }
+    }
+}
+
+impl EnergyConfig {
+    pub fn instruction_cost(&self, ins: Instruction) -> usize {
+        self.instruction_costs[ins as usize]
+    }
+}
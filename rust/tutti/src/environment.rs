@@ -0,0 +1,244 @@
+use cpu::Register;
+use rand::Rng;
+use random;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::path::Path;
+
+// The number of the inputs which each creature can read by In cyclically.
+pub const INPUT_COUNT: usize = 3;
+pub const TASK_COUNT: usize = 5;
+
+// Logic functions which are rewarded if a creature outputs the result of them by Out.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Task {
+    Not = 0,
+    Nand = 1,
+    And = 2,
+    Or = 3,
+    Equ = 4,
+}
+
+pub const TASKS: [Task; TASK_COUNT] = [Task::Not, Task::Nand, Task::And, Task::Or, Task::Equ];
+
+impl Task {
+    pub fn name(self) -> &'static str {
+        match self {
+            Task::Not => "not",
+            Task::Nand => "nand",
+            Task::And => "and",
+            Task::Or => "or",
+            Task::Equ => "equ",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Task> {
+        TASKS
+            .iter()
+            .cloned()
+            .find(|t| t.name() == name.to_lowercase())
+    }
+
+    // Check the output against the last two inputs which the creature has read.
+    pub fn is_performed(self, output: Register, inputs: &[Register]) -> bool {
+        match self {
+            Task::Not => inputs.iter().any(|&a| output == !a),
+            _ => {
+                if inputs.len() < 2 || inputs[0] == inputs[1] {
+                    return false;
+                }
+
+                let (a, b) = (inputs[0], inputs[1]);
+                output
+                    == match self {
+                        Task::Nand => !(a & b),
+                        Task::And => a & b,
+                        Task::Or => a | b,
+                        _ => !(a ^ b),
+                    }
+            }
+        }
+    }
+}
+
+// Per-creature bookkeeping of In and Out.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct TaskState {
+    pub inputs: [Register; INPUT_COUNT],
+    input_index: usize,
+    // The last two inputs which have been read.
+    read_inputs: Vec<Register>,
+    // The number of times each task has been performed.
+    pub counts: [usize; TASK_COUNT],
+}
+
+impl TaskState {
+    pub fn new() -> TaskState {
+        TaskState::default()
+    }
+
+    // The inputs are distinct so that a creature cannot be rewarded by echoing them.
+    pub fn with_random_inputs() -> TaskState {
+        let mut inputs = [0; INPUT_COUNT];
+        let mut i = 0;
+        while i < INPUT_COUNT {
+            let v = random::with_rng(|r| r.gen::<Register>());
+            if !inputs[..i].contains(&v) {
+                inputs[i] = v;
+                i += 1;
+            }
+        }
+
+        TaskState {
+            inputs,
+            ..Default::default()
+        }
+    }
+
+    pub fn read_input(&mut self) -> Register {
+        let v = self.inputs[self.input_index];
+        self.input_index = (self.input_index + 1) % INPUT_COUNT;
+
+        if self.read_inputs.len() == 2 {
+            self.read_inputs.remove(0);
+        }
+        self.read_inputs.push(v);
+
+        v
+    }
+
+    // Record the tasks which the output performs and return the number of them.
+    pub fn write_output(&mut self, output: Register) -> usize {
+        let mut count = 0;
+        for &t in TASKS.iter() {
+            if t.is_performed(output, &self.read_inputs) {
+                self.counts[t as usize] += 1;
+                count += 1;
+            }
+        }
+        count
+    }
+
+    pub fn is_performed(&self, task: Task) -> bool {
+        self.counts[task as usize] != 0
+    }
+}
+
+// Rewards of the tasks.
+// The time slice of a creature is multiplied by the bonus of each task she has performed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Environment {
+    pub bonuses: [Option<f64>; TASK_COUNT],
+}
+
+impl Default for Environment {
+    fn default() -> Environment {
+        Environment::new()
+    }
+}
+
+impl Environment {
+    pub fn new() -> Environment {
+        Environment {
+            bonuses: [None; TASK_COUNT],
+        }
+    }
+
+    // The more complex task gives the larger bonus like Avida.
+    pub fn logic_tasks() -> Environment {
+        Environment {
+            bonuses: [Some(2.0), Some(2.0), Some(4.0), Some(8.0), Some(32.0)],
+        }
+    }
+
+    // The spec consists of the lines of a task name and its bonus like below.
+    //   # comment
+    //   nand 2.0
+    //   equ 32
+    pub fn parse(spec: &str) -> Result<Environment, String> {
+        let mut env = Environment::new();
+
+        for (i, line) in spec.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let mut words = line.split_whitespace();
+            let name = words.next().unwrap();
+            let task = Task::from_name(name)
+                .ok_or_else(|| format!("line {}: unknown task {}", i + 1, name))?;
+            let bonus = match (words.next().map(|w| w.parse::<f64>()), words.next()) {
+                (Some(Ok(b)), None) if 0.0 < b => b,
+                _ => return Err(format!("line {}: invalid bonus", i + 1)),
+            };
+            env.bonuses[task as usize] = Some(bonus);
+        }
+
+        Ok(env)
+    }
+
+    pub fn load(path: &Path) -> io::Result<Environment> {
+        let mut spec = String::new();
+        File::open(path)?.read_to_string(&mut spec)?;
+        Environment::parse(&spec).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn is_rewarded(&self, task: Task) -> bool {
+        self.bonuses[task as usize].is_some()
+    }
+
+    pub fn merit(&self, state: &TaskState) -> f64 {
+        TASKS
+            .iter()
+            .filter(|&&t| state.is_performed(t))
+            .filter_map(|&t| self.bonuses[t as usize])
+            .product()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_task() {
+        let (a, b) = (0b1100, 0b1010);
+        assert!(Task::Not.is_performed(!b, &[a, b]));
+        assert!(Task::Nand.is_performed(!0b1000, &[a, b]));
+        assert!(Task::And.is_performed(0b1000, &[a, b]));
+        assert!(Task::Or.is_performed(0b1110, &[a, b]));
+        assert!(Task::Equ.is_performed(!0b0110, &[a, b]));
+        assert!(!Task::And.is_performed(a, &[a, a]));
+        assert!(!Task::Or.is_performed(a, &[a]));
+    }
+
+    #[test]
+    fn test_task_state() {
+        let mut s = TaskState::with_random_inputs();
+        s.inputs = [0b1100, 0b1010, 0b0001];
+        assert_eq!(s.read_input(), 0b1100);
+        assert_eq!(s.read_input(), 0b1010);
+        assert_eq!(s.write_output(0b1000), 1);
+        assert!(s.is_performed(Task::And));
+        assert_eq!(s.read_input(), 0b0001);
+        assert_eq!(s.read_input(), 0b1100);
+        assert_eq!(s.write_output(!0b0001), 1);
+        assert_eq!(s.counts, [1, 0, 1, 0, 0]);
+    }
+
+    #[test]
+    fn test_parse_environment() {
+        let env = Environment::parse("# logic tasks\nNOT 2\n\nequ 32.5 # reward\n").unwrap();
+        assert_eq!(env.bonuses, [Some(2.0), None, None, None, Some(32.5)]);
+        assert!(Environment::parse("xor 2").is_err());
+        assert!(Environment::parse("and").is_err());
+        assert!(Environment::parse("and 2 3").is_err());
+
+        let mut s = TaskState::new();
+        assert_eq!(env.merit(&s), 1.0);
+        s.counts = [3, 1, 0, 0, 1];
+        assert_eq!(env.merit(&s), 65.0);
+    }
+}
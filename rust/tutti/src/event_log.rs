@@ -0,0 +1,436 @@
+use creature::Creature;
+use instruction::{decode_genome, encode_genome, Instruction};
+use observer::{MutationCause, UniverseObserver};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+use universe::Universe;
+
+// The event log consists of the lines below, the genomes are written as hex bytes.
+//   tutti-log 2
+//   S <clock> <capacity>        the snapshot at the beginning of the log
+//   P <genome>                  the whole soup of the snapshot
+//   G <addr> <size>             a creature alive in the snapshot
+//   I <clock> <addr> <genome>   injection
+//   B <clock> <addr> <genome>   birth by Divide
+//   D <clock> <addr> <size>     death
+//   W <clock> <addr> <ins>      write by MovIab
+//   M <clock> <addr> <ins>      copy error, the write by MovIab which is mutated
+//   C <clock> <addr> <ins>      cosmic ray
+// The events during an instruction are logged with the clock after it,
+// so the state at a clock is the soup when Universe::world_clock returns the clock.
+const LOG_HEADER: &str = "tutti-log 2";
+
+// UniverseObserver which writes the event log.
+// Writing stops at the first error, it can be checked by error().
+pub struct EventLog<W: Write> {
+    writer: W,
+    error: Option<io::Error>,
+}
+
+impl EventLog<BufWriter<File>> {
+    pub fn create(path: &Path, univ: &Universe) -> io::Result<EventLog<BufWriter<File>>> {
+        EventLog::new(BufWriter::new(File::create(path)?), univ)
+    }
+}
+
+impl<W: Write> EventLog<W> {
+    pub fn new(mut writer: W, univ: &Universe) -> io::Result<EventLog<W>> {
+        writeln!(writer, "{}", LOG_HEADER)?;
+        writeln!(
+            writer,
+            "S {} {}",
+            univ.world_clock(),
+            univ.genome_capacity()
+        )?;
+        writeln!(writer, "P {}", encode_genome(univ.genome_soup()))?;
+        for c in univ.creatures() {
+            writeln!(
+                writer,
+                "G {} {}",
+                c.genome_region.addr, c.genome_region.size
+            )?;
+        }
+
+        Ok(EventLog {
+            writer,
+            error: None,
+        })
+    }
+
+    pub fn error(&self) -> Option<&io::Error> {
+        self.error.as_ref()
+    }
+
+    fn log(&mut self, line: String) {
+        if self.error.is_none() {
+            if let Err(e) = writeln!(self.writer, "{}", line) {
+                self.error = Some(e);
+            }
+        }
+    }
+}
+
+// The clock when the instruction in progress is counted.
+fn instruction_clock(univ: &Universe) -> usize {
+    univ.world_clock() + 1
+}
+
+impl<W: Write> UniverseObserver for EventLog<W> {
+    fn on_inject(&mut self, univ: &Universe, creature: &Creature) {
+        self.log(format!(
+            "I {} {} {}",
+            univ.world_clock(),
+            creature.genome_region.addr,
+            encode_genome(univ.genome_of(creature))
+        ));
+    }
+
+    fn on_birth(&mut self, univ: &Universe, _mother: &Creature, daughter: &Creature) {
+        self.log(format!(
+            "B {} {} {}",
+            instruction_clock(univ),
+            daughter.genome_region.addr,
+            encode_genome(univ.genome_of(daughter))
+        ));
+    }
+
+    fn on_death(&mut self, univ: &Universe, creature: &Creature) {
+        self.log(format!(
+            "D {} {} {}",
+            univ.world_clock(),
+            creature.genome_region.addr,
+            creature.genome_region.size
+        ));
+    }
+
+    fn on_write(&mut self, univ: &Universe, addr: usize, ins: Instruction) {
+        self.log(format!(
+            "W {} {} {:02x}",
+            instruction_clock(univ),
+            addr,
+            ins as usize
+        ));
+    }
+
+    fn on_mutation(
+        &mut self,
+        univ: &Universe,
+        addr: usize,
+        _before: Instruction,
+        after: Instruction,
+        cause: MutationCause,
+    ) {
+        let (kind, clock) = match cause {
+            MutationCause::CopyError => ('M', instruction_clock(univ)),
+            // The cosmic ray hits after the instruction is counted.
+            MutationCause::CosmicRay => ('C', univ.world_clock()),
+        };
+        self.log(format!(
+            "{} {} {} {:02x}",
+            kind, clock, addr, after as usize
+        ));
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    Inject(usize, Vec<Instruction>),
+    Birth(usize, Vec<Instruction>),
+    Death(usize, usize),
+    Write(usize, Instruction),
+    Mutation(usize, Instruction, MutationCause),
+}
+
+// The soup reconstructed from the event log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplayState {
+    pub clock: usize,
+    pub genome_soup: Vec<Instruction>,
+    // The sizes of the alive creatures by their addresses.
+    pub creatures: BTreeMap<usize, usize>,
+}
+
+impl ReplayState {
+    fn is_in_soup(&self, event: &Event) -> bool {
+        let capacity = self.genome_soup.len();
+        // The address is read from the log, it can be any number.
+        let is_in = |addr: usize, len: usize| addr.checked_add(len).is_some_and(|e| e <= capacity);
+        match *event {
+            Event::Inject(addr, ref g) | Event::Birth(addr, ref g) => is_in(addr, g.len()),
+            Event::Death(addr, size) => is_in(addr, size),
+            Event::Write(addr, _) | Event::Mutation(addr, _, _) => addr < capacity,
+        }
+    }
+
+    fn apply(&mut self, event: &Event) {
+        match *event {
+            Event::Inject(addr, ref genome) | Event::Birth(addr, ref genome) => {
+                self.genome_soup[addr..(addr + genome.len())].copy_from_slice(genome);
+                self.creatures.insert(addr, genome.len());
+            }
+            Event::Death(addr, _) => {
+                self.creatures.remove(&addr);
+            }
+            Event::Write(addr, ins) | Event::Mutation(addr, ins, _) => self.genome_soup[addr] = ins,
+        }
+    }
+
+    pub fn genome_at(&self, addr: usize) -> Option<&[Instruction]> {
+        self.creatures
+            .get(&addr)
+            .map(|&size| &self.genome_soup[addr..(addr + size)])
+    }
+}
+
+pub struct Replay {
+    start: ReplayState,
+    // The events ordered by the world clock.
+    events: Vec<(usize, Event)>,
+}
+
+impl Replay {
+    pub fn load(path: &Path) -> io::Result<Replay> {
+        Replay::read(BufReader::new(File::open(path)?))
+    }
+
+    pub fn read<R: BufRead>(reader: R) -> io::Result<Replay> {
+        let invalid = |n: usize| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("line {}: invalid event", n + 1),
+            )
+        };
+
+        let mut lines = reader.lines();
+        match lines.next() {
+            Some(Ok(ref l)) if l == LOG_HEADER => {}
+            _ => return Err(invalid(0)),
+        }
+
+        let mut start = None;
+        let mut events = Vec::new();
+        for (n, line) in lines.enumerate().map(|(n, l)| (n + 1, l)) {
+            let line = line?;
+            let words = line.split_whitespace().collect::<Vec<_>>();
+            let num = |i: usize| words.get(i).and_then(|w| w.parse::<usize>().ok());
+            let genome = |i: usize| words.get(i).and_then(|w| decode_genome(w));
+
+            match (words.first().cloned(), start.as_mut()) {
+                (Some("S"), None) => {
+                    let (clock, capacity) = num(1).zip(num(2)).ok_or_else(|| invalid(n))?;
+                    start = Some(ReplayState {
+                        clock,
+                        genome_soup: vec![Instruction::IfCz; capacity],
+                        creatures: BTreeMap::new(),
+                    });
+                }
+                (Some("P"), Some(s)) => match genome(1) {
+                    Some(ref g) if g.len() == s.genome_soup.len() => s.genome_soup = g.clone(),
+                    _ => return Err(invalid(n)),
+                },
+                (Some("G"), Some(s)) => {
+                    let (addr, size) = num(1).zip(num(2)).ok_or_else(|| invalid(n))?;
+                    if !s.is_in_soup(&Event::Death(addr, size)) {
+                        return Err(invalid(n));
+                    }
+                    s.creatures.insert(addr, size);
+                }
+                (Some(kind), Some(s)) => {
+                    let clock = num(1).ok_or_else(|| invalid(n))?;
+                    let addr = num(2).ok_or_else(|| invalid(n))?;
+                    let event = match kind {
+                        "I" => genome(3).map(|g| Event::Inject(addr, g)),
+                        "B" => genome(3).map(|g| Event::Birth(addr, g)),
+                        "D" => num(3).map(|size| Event::Death(addr, size)),
+                        "W" | "M" | "C" => genome(3).filter(|g| g.len() == 1).map(|g| match kind {
+                            "W" => Event::Write(addr, g[0]),
+                            "M" => Event::Mutation(addr, g[0], MutationCause::CopyError),
+                            _ => Event::Mutation(addr, g[0], MutationCause::CosmicRay),
+                        }),
+                        _ => None,
+                    };
+                    match event {
+                        Some(e) if s.is_in_soup(&e) => events.push((clock, e)),
+                        _ => return Err(invalid(n)),
+                    }
+                }
+                _ => return Err(invalid(n)),
+            }
+        }
+
+        let start = start.ok_or_else(|| invalid(0))?;
+        Ok(Replay { start, events })
+    }
+
+    pub fn start_clock(&self) -> usize {
+        self.start.clock
+    }
+
+    pub fn end_clock(&self) -> usize {
+        self.events.last().map_or(self.start.clock, |&(c, _)| c)
+    }
+
+    pub fn events(&self) -> &[(usize, Event)] {
+        &self.events
+    }
+
+    // The soup just after all the events until the given clock.
+    pub fn state_at(&self, clock: usize) -> ReplayState {
+        let mut state = self.start.clone();
+        for (_, e) in self.events.iter().take_while(|&&(c, _)| c <= clock) {
+            state.apply(e);
+        }
+        state.clock = clock;
+        state
+    }
+
+    // The clock when a creature whose genome satisfies the predicate was born for the first time.
+    pub fn find_first_birth<F>(&self, f: F) -> Option<usize>
+    where
+        F: Fn(&[Instruction]) -> bool,
+    {
+        self.events
+            .iter()
+            .find(|(_, e)| match *e {
+                Event::Birth(_, ref g) => f(g),
+                _ => false,
+            })
+            .map(|&(c, _)| c)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::Config;
+    use instruction::Instruction::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_replay() {
+        let mut univ = Universe::with_config(Config {
+            genome_capacity: 1024,
+            ..Default::default()
+        });
+        univ.generate_creature(&[Nop1; 30]);
+
+        let buffer = SharedBuffer::default();
+        let log = EventLog::new(buffer.clone(), &univ).unwrap();
+        univ.add_observer(Box::new(log));
+
+        // The daughter is written directly as MovIab does.
+        let mut mother = vec![Mal, Divide];
+        mother.resize(25, Nop0);
+        let r = univ.inject_genome(&mother).unwrap();
+        univ.creatures[1].core.cx = 25;
+        univ.execute_all_creatures(1.0);
+        let daughter_addr = univ.creatures[2].genome_region.addr;
+        assert_eq!(daughter_addr, r.end_addr());
+
+        let replay = Replay::read(&buffer.0.borrow()[..]).unwrap();
+        assert_eq!(replay.start_clock(), 0);
+        assert_eq!(replay.events().len(), 2);
+
+        let s = replay.state_at(0);
+        assert_eq!(s.creatures.len(), 2);
+        assert_eq!(s.genome_at(0), Some(&[Nop1; 30][..]));
+        assert_eq!(s.genome_at(r.addr), Some(&mother[..]));
+
+        let clock = replay.find_first_birth(|g| g.len() == 25).unwrap();
+        assert_eq!(replay.state_at(clock - 1).creatures.len(), 2);
+        let s = replay.state_at(clock);
+        assert_eq!(s.creatures.len(), 3);
+        assert_eq!(
+            s.genome_at(daughter_addr),
+            Some(univ.genome_of(&univ.creatures[2]))
+        );
+        assert_eq!(replay.find_first_birth(|g| g.len() == 30), None);
+    }
+
+    // The soup is reconstructed with the copies, the copy errors, the cosmic rays and the reaper.
+    #[test]
+    fn test_replay_soup() {
+        use ancestor::ANCESTOR;
+
+        let mut univ = Universe::with_config(Config {
+            genome_capacity: 2048,
+            is_enable_random_mutate: true,
+            copy_fail_interval: (50, 100),
+            cosmic_ray_interval: (1000, 2000),
+            seed: Some(3),
+            ..Default::default()
+        });
+        univ.generate_creature(&ANCESTOR);
+        univ.randomize_mutate_thresholds();
+        univ.step(5000);
+
+        let buffer = SharedBuffer::default();
+        let log = EventLog::new(buffer.clone(), &univ).unwrap();
+        univ.add_observer(Box::new(log));
+
+        let mut states = Vec::new();
+        for &n in [0, 1, 777, 3000, 10000, 50000, 100000].iter() {
+            univ.step(n);
+            let creatures = univ
+                .creatures()
+                .iter()
+                .map(|c| (c.genome_region.addr, c.genome_region.size))
+                .collect::<BTreeMap<_, _>>();
+            states.push((
+                univ.world_clock(),
+                univ.genome_soup_slice(0..univ.genome_capacity()).to_vec(),
+                creatures,
+            ));
+        }
+
+        let replay = Replay::read(&buffer.0.borrow()[..]).unwrap();
+        assert_eq!(replay.start_clock(), 5000);
+        let text = String::from_utf8(buffer.0.borrow().clone()).unwrap();
+        for kind in ["B ", "D ", "W ", "M ", "C "].iter() {
+            assert!(
+                text.lines().any(|l| l.starts_with(kind)),
+                "no {}event",
+                kind
+            );
+        }
+        for (clock, soup, creatures) in states {
+            let s = replay.state_at(clock);
+            assert_eq!(s.genome_soup, soup, "the soup at {}", clock);
+            assert_eq!(s.creatures, creatures, "the creatures at {}", clock);
+        }
+    }
+
+    #[test]
+    fn test_read_invalid_log() {
+        assert!(Replay::read(&b"S 0 10\n"[..]).is_err());
+        assert!(Replay::read(&b"tutti-log 1\nS 0 10\n"[..]).is_err());
+        assert!(Replay::read(&b"tutti-log 2\nB 0 0 00\n"[..]).is_err());
+        assert!(Replay::read(&b"tutti-log 2\nS 0 10\nB 0 9 0000\n"[..]).is_err());
+        assert!(Replay::read(&b"tutti-log 2\nS 0 10\nX 0 0 00\n"[..]).is_err());
+        assert!(Replay::read(&b"tutti-log 2\nS 0 2\nP 00\n"[..]).is_err());
+        assert!(Replay::read(&b"tutti-log 2\nS 0 10\nG 8 3\n"[..]).is_err());
+        let overflow = b"tutti-log 2\nS 0 10\nI 0 18446744073709551615 00\n";
+        assert!(Replay::read(&overflow[..]).is_err());
+        assert!(Replay::read(&b"tutti-log 2\nS 0 10\nG 18446744073709551615 1\n"[..]).is_err());
+        assert!(Replay::read(&b"tutti-log 2\nS 0 2\nP 0001\nW 1 1 02\n"[..]).is_ok());
+        assert!(Replay::read(&b"tutti-log 2\nS 0 10\nD 3 0 5\n"[..]).is_ok());
+    }
+}
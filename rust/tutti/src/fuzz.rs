@@ -0,0 +1,83 @@
+use config::Config;
+use energy::EnergyConfig;
+use environment::Environment;
+use instruction::Instruction;
+use universe::Universe;
+
+pub const FUZZ_GENOME_CAPACITY: usize = 4096;
+pub const FUZZ_ROUNDS: usize = 16;
+
+// Entry point of the fuzzing harness, see fuzz/fuzz_targets/execute.rs.
+// The first byte switches the optional features and the rest are genomes separated by 0xFF.
+// The bytes which do not match any instruction are ignored.
+pub fn execute_genomes(data: &[u8]) {
+    let flags = data.first().cloned().unwrap_or(0);
+    let mut univ = Universe::with_config(Config {
+        genome_capacity: FUZZ_GENOME_CAPACITY,
+        minimum_daughter_size: 1,
+        energy: if (flags & 0x01) != 0 {
+            Some(EnergyConfig::default())
+        } else {
+            None
+        },
+        maximum_threads: if (flags & 0x02) != 0 { 4 } else { 1 },
+        is_enable_communication: (flags & 0x04) != 0,
+        environment: if (flags & 0x08) != 0 {
+            Some(Environment::logic_tasks())
+        } else {
+            None
+        },
+        ..Default::default()
+    });
+
+    for bytes in data.get(1..).unwrap_or(&[]).split(|&b| b == 0xFF) {
+        let genome = bytes
+            .iter()
+            .filter_map(|&b| Instruction::try_from_usize(b as usize))
+            .collect::<Vec<_>>();
+        if !genome.is_empty() && univ.inject_genome(&genome).is_none() {
+            break;
+        }
+    }
+
+    for _ in 0..FUZZ_ROUNDS {
+        if univ.run_round() == 0 {
+            break;
+        }
+
+        if let Err(e) = univ.check_invariants() {
+            panic!("{}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate rand;
+
+    use super::*;
+    use instruction::Instruction::*;
+    use instruction::INSTRUCTION_COUNT;
+    use rand::Rng;
+
+    #[test]
+    fn test_execute_random_genomes() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..200 {
+            let len = rng.gen_range(1, 256);
+            let data = (0..len)
+                .map(|_| rng.gen_range(0, INSTRUCTION_COUNT as u8 + 2))
+                .collect::<Vec<u8>>();
+            execute_genomes(&data);
+        }
+    }
+
+    #[test]
+    fn test_execute_regressions() {
+        // Ret to the address out of the soup.
+        let mut ret = vec![0x00, Or1 as u8];
+        ret.extend_from_slice(&[Shl as u8; 15]);
+        ret.extend_from_slice(&[PushCx as u8, Ret as u8]);
+        execute_genomes(&ret);
+    }
+}
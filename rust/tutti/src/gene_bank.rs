@@ -4,7 +4,9 @@ use std::collections::HashSet;
 use std::fmt;
 
 #[derive(Debug, PartialEq, Eq, Clone)]
-struct GenomeRecord {
+pub struct GenomeRecord {
+    // The index in GeneBank::records, it is unique unlike geno_type.
+    id: usize,
     genome: Vec<Instruction>,
     genome_type: usize,
     mother_info: Option<Box<GenomeRecord>>,
@@ -17,7 +19,23 @@ impl fmt::Display for GenomeRecord {
 }
 
 impl GenomeRecord {
-    fn new(genome: Vec<Instruction>, mother: Option<Box<GenomeRecord>>) -> GenomeRecord {
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    pub fn genome(&self) -> &[Instruction] {
+        &self.genome
+    }
+
+    pub fn geno_type(&self) -> String {
+        self.to_string()
+    }
+
+    pub fn mother(&self) -> Option<&GenomeRecord> {
+        self.mother_info.as_ref().map(|m| m.as_ref())
+    }
+
+    fn new(id: usize, genome: Vec<Instruction>, mother: Option<Box<GenomeRecord>>) -> GenomeRecord {
         let t = if let Some(m) = mother.as_ref() {
             m.genome_type + 1
         } else {
@@ -25,6 +43,7 @@ impl GenomeRecord {
         };
 
         GenomeRecord {
+            id,
             genome: genome,
             genome_type: t,
             mother_info: mother,
@@ -36,6 +55,10 @@ pub struct GeneBank {
     records: Vec<GenomeRecord>,
     alive_count_map: HashMap<String, usize>,
     dead_count_map: HashMap<String, usize>,
+    // The number of the messages for each pair of the sender and the receiver.
+    communication_count_map: HashMap<(String, String), usize>,
+    // The first and the last clocks when a creature of each genotype was born or died.
+    seen_clock_map: HashMap<String, (usize, usize)>,
 }
 
 impl fmt::Display for GeneBank {
@@ -77,12 +100,20 @@ impl fmt::Display for GeneBank {
     }
 }
 
+impl Default for GeneBank {
+    fn default() -> GeneBank {
+        GeneBank::new()
+    }
+}
+
 impl GeneBank {
     pub fn new() -> GeneBank {
         GeneBank {
             records: Vec::new(),
             alive_count_map: HashMap::new(),
             dead_count_map: HashMap::new(),
+            communication_count_map: HashMap::new(),
+            seen_clock_map: HashMap::new(),
         }
     }
 
@@ -93,7 +124,7 @@ impl GeneBank {
     ) -> Option<String> {
         let genome = (*genome).clone();
         if mother.is_none() && self.find_genome_record(&genome).is_none() {
-            let r = GenomeRecord::new(genome, None);
+            let r = GenomeRecord::new(self.records.len(), genome, None);
             let tag = r.to_string();
             self.records.push(r);
 
@@ -105,7 +136,7 @@ impl GeneBank {
             self.find_genome_record_by_type(mother),
         ) {
             (None, Some(mother)) => {
-                let r = GenomeRecord::new(genome, Some(Box::new(mother)));
+                let r = GenomeRecord::new(self.records.len(), genome, Some(Box::new(mother)));
                 let tag = r.to_string();
                 self.records.push(r);
                 Some(tag)
@@ -115,6 +146,79 @@ impl GeneBank {
         }
     }
 
+    // Put the record which was saved before, the records have to be restored in the order of id.
+    pub fn restore_record(
+        &mut self,
+        genome: Vec<Instruction>,
+        mother_id: Option<usize>,
+    ) -> Option<String> {
+        let mother = match mother_id {
+            None => None,
+            Some(id) => Some(Box::new(self.records.get(id)?.clone())),
+        };
+        let r = GenomeRecord::new(self.records.len(), genome, mother);
+        let tag = r.to_string();
+        self.records.push(r);
+        Some(tag)
+    }
+
+    pub fn restore_counts(&mut self, geno_type: &str, alive: usize, dead: usize) {
+        self.alive_count_map.insert(geno_type.to_string(), alive);
+        self.dead_count_map.insert(geno_type.to_string(), dead);
+    }
+
+    pub fn record_seen(&mut self, geno_type: &str, clock: usize) {
+        self.seen_clock_map
+            .entry(geno_type.to_string())
+            .or_insert((clock, clock))
+            .1 = clock;
+    }
+
+    pub fn seen_clocks(&self, geno_type: &str) -> Option<(usize, usize)> {
+        self.seen_clock_map.get(geno_type).cloned()
+    }
+
+    pub fn restore_seen_clocks(&mut self, geno_type: &str, first: usize, last: usize) {
+        self.seen_clock_map
+            .insert(geno_type.to_string(), (first, last));
+    }
+
+    pub fn count_records(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn records(&self) -> &[GenomeRecord] {
+        &self.records
+    }
+
+    pub fn find_record(&self, geno_type: &str) -> Option<&GenomeRecord> {
+        self.records.iter().find(|x| x.to_string() == geno_type)
+    }
+
+    pub fn find_record_by_genome(&self, genome: &[Instruction]) -> Option<&GenomeRecord> {
+        self.records.iter().find(|r| r.genome == genome)
+    }
+
+    pub fn count_alive(&self, geno_type: &str) -> usize {
+        *self.alive_count_map.get(geno_type).unwrap_or(&0)
+    }
+
+    pub fn count_dead(&self, geno_type: &str) -> usize {
+        *self.dead_count_map.get(geno_type).unwrap_or(&0)
+    }
+
+    // Genotypes which have at least one alive creature.
+    pub fn alive_geno_types(&self) -> Vec<String> {
+        let mut v = self
+            .alive_count_map
+            .iter()
+            .filter(|&(_, &c)| c != 0)
+            .map(|(k, _)| k.clone())
+            .collect::<Vec<String>>();
+        v.sort();
+        v
+    }
+
     fn find_genome_record(&self, target: &Vec<Instruction>) -> Option<usize> {
         self.records.iter().position(|r| r.genome == *target)
     }
@@ -143,6 +247,39 @@ impl GeneBank {
         }
     }
 
+    pub fn count_up_communication(&mut self, sender: &str, receiver: &str) {
+        *self
+            .communication_count_map
+            .entry((sender.to_string(), receiver.to_string()))
+            .or_insert(0) += 1;
+    }
+
+    pub fn count_communication(&self, sender: &str, receiver: &str) -> usize {
+        *self
+            .communication_count_map
+            .get(&(sender.to_string(), receiver.to_string()))
+            .unwrap_or(&0)
+    }
+
+    pub fn is_communicating(&self, geno_type: &str) -> bool {
+        self.communication_count_map
+            .keys()
+            .any(|(s, r)| s == geno_type || r == geno_type)
+    }
+
+    pub fn dump_communications(&self) -> String {
+        let mut v = self
+            .communication_count_map
+            .iter()
+            .collect::<Vec<(&(String, String), &usize)>>();
+        v.sort();
+
+        v.into_iter()
+            .map(|((s, r), c)| format!("sender: {}, receiver: {}, messages: {}", s, r, c))
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
     pub fn dump_all_recorded_genoms(&self) -> String {
         let mut keys = self
             .records
@@ -1,8 +1,11 @@
-extern crate rand;
+use random;
+use std::fmt::Write;
 
-use rand::Rng;
+// The instructions after the base ones are available only if they are enabled by Config.
+pub const BASE_INSTRUCTION_COUNT: usize = 0x1F + 1;
+pub const INSTRUCTION_COUNT: usize = 0x26 + 1;
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum Instruction {
     Nop0 = 0x00,   // no operation
     Nop1 = 0x01,   // no operation
@@ -36,6 +39,13 @@ pub enum Instruction {
     Adrf = 0x1D,   // search forward for template
     Mal = 0x1E,    // allocate memory for daughter cell
     Divide = 0x1F, // cell division
+    Split = 0x20,  // fork a new thread, cx = 0 in the new thread and cx = 1 in the current one
+    Join = 0x21,   // terminate the current thread, the first thread terminates all the others
+    Put = 0x22,    // put dx into the mailbox of the creature at address in ax
+    Get = 0x23,    // get a message from own mailbox into dx
+    Send = 0x24,   // send dx to the mailboxes of the neighbours
+    In = 0x25,     // read the next input into dx
+    Out = 0x26,    // output dx, it is checked against the tasks of the environment
 }
 
 impl Instruction {
@@ -43,9 +53,21 @@ impl Instruction {
         (x == Instruction::Nop0) || (x == Instruction::Nop1)
     }
 
+    pub fn is_template_search(x: Instruction) -> bool {
+        use instruction::Instruction::*;
+        matches!(x, Jmp | Jmpb | Call | Adr | Adrb | Adrf)
+    }
+
     pub fn from_usize(x: usize) -> Instruction {
-        use Instruction::*;
-        match x {
+        match Instruction::try_from_usize(x) {
+            Some(ins) => ins,
+            None => panic!("it does not match any instruction."),
+        }
+    }
+
+    pub fn try_from_usize(x: usize) -> Option<Instruction> {
+        use instruction::Instruction::*;
+        let ins = match x {
             0x00 => Nop0,
             0x01 => Nop1,
             0x02 => Or1,
@@ -78,13 +100,87 @@ impl Instruction {
             0x1D => Adrf,
             0x1E => Mal,
             0x1F => Divide,
-            _ => panic!("it does not match any instruction."),
-        }
+            0x20 => Split,
+            0x21 => Join,
+            0x22 => Put,
+            0x23 => Get,
+            0x24 => Send,
+            0x25 => In,
+            0x26 => Out,
+            _ => return None,
+        };
+        Some(ins)
     }
 
     pub fn mutate_bit_randomly(&self) -> Instruction {
         // let target_bit = rand::thread_rng().gen_range(0, 5);
         // Instruction::from_usize((*self as usize) ^ (1 << target_bit))
-        Instruction::from_usize(rand::thread_rng().gen_range(0, 0x1F + 1))
+        Instruction::from_usize(random::gen_range(0, BASE_INSTRUCTION_COUNT))
+    }
+
+    pub fn mutate_randomly_within(&self, instruction_set: &[Instruction]) -> Instruction {
+        instruction_set[random::gen_range(0, instruction_set.len())]
+    }
+
+    // The name in the assembly, e.g. "moviab" for MovIab.
+    pub fn mnemonic(&self) -> String {
+        format!("{:?}", self).to_lowercase()
+    }
+
+    pub fn from_mnemonic(s: &str) -> Option<Instruction> {
+        let s = s.to_lowercase();
+        (0..INSTRUCTION_COUNT)
+            .map(Instruction::from_usize)
+            .find(|ins| ins.mnemonic() == s)
+    }
+
+    pub fn is_base(x: Instruction) -> bool {
+        (x as usize) < BASE_INSTRUCTION_COUNT
+    }
+}
+
+// The genome in hex bytes.
+pub fn encode_genome(genome: &[Instruction]) -> String {
+    let mut s = String::with_capacity(genome.len() * 2);
+    for &ins in genome {
+        write!(s, "{:02x}", ins as usize).unwrap();
+    }
+    s
+}
+
+pub fn decode_genome(s: &str) -> Option<Vec<Instruction>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+
+    (0..s.len())
+        .step_by(2)
+        .map(|i| {
+            s.get(i..(i + 2))
+                .and_then(|b| usize::from_str_radix(b, 16).ok())
+                .and_then(Instruction::try_from_usize)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use instruction::Instruction::*;
+
+    #[test]
+    fn test_encode_genome() {
+        let genome = [Nop0, Divide, Out];
+        assert_eq!(encode_genome(&genome), "001f26");
+        assert_eq!(decode_genome("001f26"), Some(genome.to_vec()));
+        assert_eq!(decode_genome("001"), None);
+        assert_eq!(decode_genome("ff"), None);
+    }
+
+    #[test]
+    fn test_mnemonic() {
+        assert_eq!(MovIab.mnemonic(), "moviab");
+        assert_eq!(Instruction::from_mnemonic("MovIab"), Some(MovIab));
+        assert_eq!(Instruction::from_mnemonic("nop2"), None);
     }
 }
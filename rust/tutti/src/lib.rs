@@ -0,0 +1,22 @@
+extern crate rand;
+
+pub mod ancestor;
+pub mod bank_file;
+pub mod config;
+pub mod cpu;
+pub mod creature;
+pub mod energy;
+pub mod environment;
+pub mod event_log;
+pub mod fuzz;
+pub mod gene_bank;
+pub mod instruction;
+pub mod memory_region;
+pub mod message_board;
+pub mod observer;
+pub mod profiler;
+pub mod random;
+pub mod render;
+pub mod species;
+pub mod sweep;
+pub mod universe;
@@ -2,58 +2,118 @@
 extern crate chan;
 extern crate chan_signal;
 extern crate chrono;
-extern crate rand;
-
-mod cpu;
-mod creature;
-mod gene_bank;
-mod instruction;
-mod memory_region;
-mod universe;
+extern crate tutti;
 
 use chan_signal::Signal;
 use chrono::Local;
-use gene_bank::GeneBank;
-use instruction::Instruction;
+use std::env;
 use std::fs::File;
+use std::io;
 use std::io::prelude::*;
+use std::path::Path;
+use std::process;
 use std::thread;
-use universe::Universe;
+use tutti::ancestor::ANCESTOR;
+use tutti::bank_file::{BankFile, BankRecord, BankRotation, RecordFilter};
+use tutti::config::{Config, ExtinctionPolicy, StagnationPolicy};
+use tutti::energy::EnergyConfig;
+use tutti::environment::{Environment, TASKS};
+use tutti::event_log::{EventLog, Replay};
+use tutti::instruction::Instruction;
+use tutti::profiler::Profiler;
+use tutti::render;
+use tutti::render::{ColorMode, ImageFormat, Timelapse};
+use tutti::species::SpeciesCriterion;
+use tutti::sweep;
+use tutti::sweep::SweepGrid;
+use tutti::universe::Universe;
 
 fn main() {
-    let mut univ = Universe::new();
+    let args = env::args().collect::<Vec<String>>();
+    match args.get(1).map(|s| s.as_str()) {
+        Some("replay") => return replay(&args[2..]).unwrap_or_else(|e| exit_with_error(&e)),
+        Some("bank") => return bank(&args[2..]).unwrap_or_else(|e| exit_with_error(&e)),
+        Some("sweep") => return sweep(&args[2..]).unwrap_or_else(|e| exit_with_error(&e)),
+        _ => {}
+    }
+
+    let mut univ = Universe::with_config(Config {
+        is_enable_random_mutate: true,
+        energy: if args.iter().any(|arg| arg == "--energy") {
+            Some(EnergyConfig::default())
+        } else {
+            None
+        },
+        maximum_threads: number_option(&args, "--threads")
+            .unwrap_or_else(|e| exit_with_error(&e))
+            .unwrap_or(1),
+        is_enable_communication: args.iter().any(|arg| arg == "--communication"),
+        // The logic tasks are rewarded by the default bonuses if the spec file is not given.
+        environment: args.iter().position(|arg| arg == "--environment").map(|i| {
+            match args.get(i + 1) {
+                Some(path) if !path.starts_with("--") => Environment::load(Path::new(path))
+                    .unwrap_or_else(|e| exit_with_error(&format!("{}: {}", path, e))),
+                _ => Environment::logic_tasks(),
+            }
+        }),
+        extinction_policy: extinction_policy(&args).unwrap_or_else(|e| exit_with_error(&e)),
+        // The mutation rates are quadrupled while no new genotype appears for the given ticks.
+        stagnation: number_option(&args, "--stagnation")
+            .unwrap_or_else(|e| exit_with_error(&e))
+            .map(|ticks| StagnationPolicy {
+                ticks,
+                mutation_boost: 4.0,
+            }),
+        species: species_criterion(&args).unwrap_or_else(|e| exit_with_error(&e)),
+        ..Default::default()
+    });
 
     let signal = chan_signal::notify(&[Signal::INT, Signal::TERM]);
     let (sdone, rdone) = chan::sync(0);
     thread::spawn(move || run(sdone));
 
     let dump_filename = Local::now().format("%Y%m%d%H%M%S.txt").to_string();
+    // The last 5 snapshots of the bank are kept as <prefix>.<n>.bank.jsonl.
+    let mut bank_rotation = BankRotation::new(&Local::now().format("%Y%m%d%H%M%S").to_string(), 5);
+    let profile_filename = Local::now().format("%Y%m%d%H%M%S.profile.txt").to_string();
     let mut loop_cnt = 0;
 
-    use Instruction::*;
-
-    let insts = [
-        Nop1, Nop1, Nop1, Nop1, Zero, Or1, Shl, Shl, MovCd, Adrb, Nop0, Nop0, Nop0, Nop0, SubAc,
-        MovAb, Adrf, Nop0, Nop0, Nop0, Nop1, IncA, SubAb, Nop1, Nop1, Nop0, Nop1, Mal, Call, Nop0,
-        Nop0, Nop1, Nop1, Divide, Jmpb, Nop0, Nop0, Nop1, Nop0, IfCz, Nop1, Nop1, Nop0, Nop0,
-        PushAx, PushBx, PushCx, Nop1, Nop0, Nop1, Nop0, MovIab, DecC, IfCz, Jmp, Nop0, Nop1, Nop0,
-        Nop0, IncA, IncB, Jmpb, Nop0, Nop1, Nop0, Nop1, IfCz, Nop1, Nop0, Nop1, Nop1, PopCx, PopBx,
-        PopAx, Ret, Nop1, Nop1, Nop1, Nop0, IfCz,
-    ];
-    univ.generate_creature(&insts);
+    univ.generate_creature(&ANCESTOR);
 
-    univ.enable_random_mutate();
     univ.randomize_mutate_thresholds();
+    if let Some(path) = args
+        .iter()
+        .position(|arg| arg == "--log")
+        .and_then(|i| args.get(i + 1))
+    {
+        let log = EventLog::create(Path::new(path), &univ)
+            .unwrap_or_else(|e| exit_with_error(&format!("{}: {}", path, e)));
+        univ.add_observer(Box::new(log));
+    }
+    if args.iter().any(|arg| arg == "--profile") {
+        univ.enable_profiler();
+    }
+
+    // Frames of the soup are saved into the directory given by `--frames <dir>`,
+    // and they are assembled into the animated PNGs like `genotype_timelapse.png`.
+    let mut timelapses = args
+        .iter()
+        .position(|arg| arg == "--frames")
+        .and_then(|i| args.get(i + 1))
+        .map(|dir| {
+            univ.enable_write_heat_map();
+            create_timelapses(Path::new(dir))
+                .unwrap_or_else(|e| exit_with_error(&format!("{}: {}", dir, e)))
+        });
     loop {
-        univ.execute_all_creatures(1.2);
-        univ.wakeup_reaper_if_genome_usage_over(0.8);
+        univ.run_round();
 
         let free_size = univ.compute_genome_soup_free_size();
-        let used_size = universe::UNIVERSE_TOTAL_GENOME_CAPACITY - free_size;
+        let used_size = univ.genome_capacity() - free_size;
         println!("free: {}, used: {}", free_size, used_size);
 
         assert_eq!(
-            universe::UNIVERSE_TOTAL_GENOME_CAPACITY,
+            univ.genome_capacity(),
             univ.compute_genome_soup_free_size() + univ.compute_genome_soup_used_size()
         );
 
@@ -63,31 +123,85 @@ fn main() {
             univ.compute_genome_soup_free_rate()
         );
         println!("# of creatures: {}", univ.count_creatures());
-        println!("Bank Info\n{}", univ.gene_bank());
+        if univ.is_stagnant() {
+            println!("stagnant, mutation rate: {}", univ.mutation_rate());
+        }
+        if univ.config().energy.is_some() {
+            println!("energy pool: {}", univ.energy_pool());
+        }
+        // The species report is shown instead of the genotypes, they are still dumped into the file.
+        match univ.species() {
+            Some(species) => println!("Species Info\n{}", species.report(univ.gene_bank())),
+            None => println!("Bank Info\n{}", univ.gene_bank()),
+        }
+        if univ.config().is_enable_communication {
+            println!(
+                "Communication Info\n{}",
+                univ.gene_bank().dump_communications()
+            );
+        }
+        if univ.config().environment.is_some() {
+            let counts = univ.count_task_performers();
+            println!("Task Info");
+            for &t in TASKS.iter() {
+                println!("{}: {}", t.name(), counts[t as usize]);
+            }
+        }
+        if univ.profiler().is_enabled() {
+            println!("Profile Info\n{}", univ.profiler());
+        }
         println!("# of free regions {:?}", univ.free_regions.len());
         // println!("{:?}", univ.free_regions);
         println!("==========");
 
-        if univ.count_creatures() == 0 {
-            panic!("NO CREATURES !");
-        }
-
-        let dump_to_file = |filename: &String, bank: &GeneBank| {
+        let mut dump_to_file = |filename: &String, univ: &Universe| {
             let mut file = File::create(filename).unwrap();
-            file.write_fmt(format_args!("{}", bank.dump_all_recorded_genoms()))
+            file.write_fmt(format_args!(
+                "{}",
+                univ.gene_bank.dump_all_recorded_genoms()
+            ))
+            .unwrap();
+            bank_rotation
+                .save(&BankFile::from_gene_bank(
+                    &univ.gene_bank,
+                    univ.world_clock(),
+                ))
                 .unwrap();
         };
 
+        let dump_profile_to_file = |filename: &String, profiler: &Profiler| {
+            if profiler.is_enabled() {
+                let mut file = File::create(filename).unwrap();
+                file.write_fmt(format_args!("{}", profiler.dump_all_profiles()))
+                    .unwrap();
+            }
+        };
+
+        if univ.count_creatures() == 0 {
+            println!(
+                "\n\nALL CREATURES DIED OUT, DUMP ALL GENOMEs to {}",
+                dump_filename
+            );
+            dump_to_file(&dump_filename, &univ);
+            dump_profile_to_file(&profile_filename, univ.profiler());
+            break;
+        }
+
         chan_select! {
             signal.recv() -> _ => {
                 println!("\n\nDUMP ALL GENOMEs to {}", dump_filename);
-                dump_to_file(&dump_filename, &univ.gene_bank);
+                dump_to_file(&dump_filename, &univ);
+                dump_profile_to_file(&profile_filename, univ.profiler());
                 break;
             },
             rdone.recv() => {
                 loop_cnt += 1;
                 if loop_cnt % 10 == 0 {
-                    dump_to_file(&dump_filename, &univ.gene_bank);
+                    dump_to_file(&dump_filename, &univ);
+                    dump_profile_to_file(&profile_filename, univ.profiler());
+                    if let Some(ref mut timelapses) = timelapses {
+                        save_frames(&univ, timelapses).unwrap();
+                    }
                     loop_cnt = 0;
                 }
             }
@@ -95,4 +209,283 @@ fn main() {
     }
 }
 
+const FRAME_WIDTH: usize = 512;
+
+fn exit_with_error(message: &str) -> ! {
+    eprintln!("tutti: {}", message);
+    process::exit(1);
+}
+
+// The positive number following the option, None if the option is not given.
+fn number_option(args: &[String], name: &str) -> Result<Option<usize>, String> {
+    match args.iter().position(|arg| arg == name) {
+        None => Ok(None),
+        Some(i) => args
+            .get(i + 1)
+            .and_then(|n| n.parse().ok())
+            .filter(|&n| 0 < n)
+            .map(Some)
+            .ok_or_else(|| format!("{} requires a positive number", name)),
+    }
+}
+
+// `--species-distance <n>` or `--species-trace <steps>`.
+fn species_criterion(args: &[String]) -> Result<Option<SpeciesCriterion>, String> {
+    let i = match args
+        .iter()
+        .position(|arg| arg == "--species-distance" || arg == "--species-trace")
+    {
+        None => return Ok(None),
+        Some(i) => i,
+    };
+
+    let n = args
+        .get(i + 1)
+        .and_then(|n| n.parse().ok())
+        .ok_or_else(|| format!("{} requires a number", args[i]))?;
+    if args[i] == "--species-distance" {
+        Ok(Some(SpeciesCriterion::EditDistance(n)))
+    } else {
+        Ok(Some(SpeciesCriterion::Trace(n)))
+    }
+}
+
+// `--reseed [ancestor|snapshot]`, the ancestor is reseeded if the value is omitted.
+// The creatures stop at the extinction without `--reseed`.
+fn extinction_policy(args: &[String]) -> Result<ExtinctionPolicy, String> {
+    let i = match args.iter().position(|arg| arg == "--reseed") {
+        None => return Ok(ExtinctionPolicy::Stop),
+        Some(i) => i,
+    };
+
+    match args.get(i + 1).map(|s| s.as_str()) {
+        None | Some("ancestor") => Ok(ExtinctionPolicy::Reseed(ANCESTOR.to_vec())),
+        Some(v) if v.starts_with("--") => Ok(ExtinctionPolicy::Reseed(ANCESTOR.to_vec())),
+        Some("snapshot") => Ok(ExtinctionPolicy::ReseedFromSnapshot),
+        Some(v) => Err(format!(
+            "unknown value of --reseed: {}, it is ancestor or snapshot",
+            v
+        )),
+    }
+}
+
+fn create_timelapses(dir: &Path) -> io::Result<[Timelapse; 3]> {
+    Ok([
+        Timelapse::new(dir, "genotype", ImageFormat::Png)?,
+        Timelapse::new(dir, "opcode", ImageFormat::Png)?,
+        Timelapse::new(dir, "heat", ImageFormat::Png)?,
+    ])
+}
+
+fn save_frames(univ: &Universe, timelapses: &mut [Timelapse; 3]) -> io::Result<()> {
+    let [ref mut genotype, ref mut opcode, ref mut heat] = *timelapses;
+    genotype.push(&render::render_genome_soup(
+        univ,
+        FRAME_WIDTH,
+        ColorMode::Genotype,
+    ))?;
+    opcode.push(&render::render_genome_soup(
+        univ,
+        FRAME_WIDTH,
+        ColorMode::Opcode,
+    ))?;
+    if let Some(h) = univ.write_heat_map() {
+        heat.push(&render::render_write_heat_map(h, FRAME_WIDTH))?;
+    }
+    Ok(())
+}
+
+const REPLAY_USAGE: &str =
+    "usage: tutti replay <log> [<clock>] [--first-birth-below <size>] [--frame <file>]";
+
+// It shows the soup at the given clock, the last clock of the log is used by default.
+fn replay(args: &[String]) -> Result<(), String> {
+    let path = args
+        .first()
+        .ok_or_else(|| format!("missing arguments\n{}", REPLAY_USAGE))?;
+    let replay = Replay::load(Path::new(path)).map_err(|e| format!("{}: {}", path, e))?;
+    println!("log: {} - {}", replay.start_clock(), replay.end_clock());
+
+    // Find the first parasite like creature by her size.
+    let first_birth = match number_option(args, "--first-birth-below")? {
+        None => None,
+        Some(size) => {
+            let clock = replay.find_first_birth(|g| g.len() < size);
+            println!("first birth below {}: {:?}", size, clock);
+            clock
+        }
+    };
+
+    let clock = match args.get(1) {
+        Some(c) if !c.starts_with("--") => c
+            .parse()
+            .map_err(|_| format!("invalid clock {}\n{}", c, REPLAY_USAGE))?,
+        _ => first_birth.unwrap_or_else(|| replay.end_clock()),
+    };
+
+    let state = replay.state_at(clock);
+    println!("clock: {}", state.clock);
+    println!("# of creatures: {}", state.creatures.len());
+    for (addr, size) in state.creatures.iter() {
+        println!("{:>8}: {}", addr, size);
+    }
+
+    match args
+        .iter()
+        .position(|arg| arg == "--frame")
+        .map(|i| args.get(i + 1))
+    {
+        Some(Some(path)) => {
+            let format = if path.ends_with(".png") {
+                ImageFormat::Png
+            } else {
+                ImageFormat::Ppm
+            };
+            render::render_instructions(&state.genome_soup, FRAME_WIDTH)
+                .save(Path::new(path), format)
+                .map_err(|e| format!("{}: {}", path, e))?;
+        }
+        Some(None) => return Err("--frame requires a file".to_string()),
+        None => {}
+    }
+    Ok(())
+}
+
+const BANK_USAGE: &str = "usage: tutti bank <file> list [--min-size <n>] [--max-size <n>] [--min-births <n>] [--min-alive <n>]
+       tutti bank <file> ancestry <id|geno_type>
+       tutti bank <file> find <instruction>...
+       tutti bank <file> export <id|geno_type>... [--out <file>]";
+
+fn bank(args: &[String]) -> Result<(), String> {
+    let usage = || format!("missing arguments\n{}", BANK_USAGE);
+    let path = args.first().ok_or_else(usage)?;
+    let file = BankFile::load(Path::new(path)).map_err(|e| format!("{}: {}", path, e))?;
+    println!("world clock: {}", file.world_clock);
+    let command = args.get(1).map_or("list", |s| s.as_str());
+    let params = args.get(2..).unwrap_or(&[]);
+    let option = |name: &str| -> Result<Option<usize>, String> {
+        match params.iter().position(|arg| arg == name) {
+            None => Ok(None),
+            Some(i) => params
+                .get(i + 1)
+                .and_then(|n| n.parse().ok())
+                .map(Some)
+                .ok_or_else(|| format!("{} requires a number", name)),
+        }
+    };
+    let find = |key: &String| -> Result<&BankRecord, String> {
+        file.find(key)
+            .ok_or_else(|| format!("{} is not found", key))
+    };
+    let print_record = |r: &BankRecord| {
+        println!(
+            "id: {}, type: {}, parent: {}, size: {}, borns: {}, alives: {}, seen: {} - {}",
+            r.id,
+            r.geno_type,
+            r.parent.map_or("-".to_string(), |p| p.to_string()),
+            r.size(),
+            r.births(),
+            r.alive,
+            r.first_seen,
+            r.last_seen
+        );
+    };
+
+    match command {
+        "list" => {
+            let filter = RecordFilter {
+                min_size: option("--min-size")?,
+                max_size: option("--max-size")?,
+                min_births: option("--min-births")?,
+                min_alive: option("--min-alive")?,
+            };
+            for r in file.filter(&filter) {
+                print_record(r);
+            }
+        }
+        "ancestry" => {
+            let key = params.first().ok_or_else(usage)?;
+            for r in file.ancestry(find(key)?.id) {
+                print_record(r);
+            }
+        }
+        "find" => {
+            let template = params
+                .iter()
+                .map(|s| {
+                    Instruction::from_mnemonic(s)
+                        .ok_or_else(|| format!("{} is not an instruction", s))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            if template.is_empty() {
+                return Err(usage());
+            }
+            for r in file.find_template(&template) {
+                print_record(r);
+            }
+        }
+        "export" => {
+            let out = params.iter().position(|arg| arg == "--out");
+            let keys = &params[..out.unwrap_or(params.len())];
+            if keys.is_empty() {
+                return Err(usage());
+            }
+            let assembly = keys
+                .iter()
+                .map(|key| find(key).map(|r| r.to_assembly()))
+                .collect::<Result<Vec<_>, _>>()?
+                .join("\n");
+            match out.map(|i| params.get(i + 1)) {
+                Some(Some(path)) => File::create(path)
+                    .and_then(|mut f| f.write_all(assembly.as_bytes()))
+                    .map_err(|e| format!("{}: {}", path, e))?,
+                Some(None) => return Err("--out requires a file".to_string()),
+                None => print!("{}", assembly),
+            }
+        }
+        _ => return Err(format!("unknown command {}\n{}", command, BANK_USAGE)),
+    }
+    Ok(())
+}
+
+const SWEEP_USAGE: &str = "usage: tutti sweep <spec> [--jobs <n>] [--out <file>]";
+
+// It runs every combination of the parameters in the spec, see SweepGrid::parse.
+// The number of jobs is the number of the CPUs by default.
+fn sweep(args: &[String]) -> Result<(), String> {
+    let path = args
+        .first()
+        .ok_or_else(|| format!("missing arguments\n{}", SWEEP_USAGE))?;
+    let grid = SweepGrid::load(Path::new(path)).map_err(|e| format!("{}: {}", path, e))?;
+    let jobs = number_option(args, "--jobs")?
+        .unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()));
+
+    // The output file is created first not to lose a long sweep.
+    let mut out = match args
+        .iter()
+        .position(|arg| arg == "--out")
+        .map(|i| args.get(i + 1))
+    {
+        Some(Some(path)) => Some((
+            File::create(path).map_err(|e| format!("{}: {}", path, e))?,
+            path,
+        )),
+        Some(None) => return Err("--out requires a file".to_string()),
+        None => None,
+    };
+
+    let runs = grid.runs();
+    println!("{} runs by {} jobs", runs.len(), jobs);
+    let summaries = sweep::run_all(&runs, grid.rounds, jobs);
+
+    match out {
+        Some((ref mut f, path)) => {
+            sweep::write_table(f, &summaries).map_err(|e| format!("{}: {}", path, e))?;
+            println!("results are written to {}", path);
+        }
+        None => sweep::write_table(&mut io::stdout(), &summaries).map_err(|e| e.to_string())?,
+    }
+    Ok(())
+}
+
 fn run(_sdone: chan::Sender<()>) {}
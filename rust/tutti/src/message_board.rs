@@ -0,0 +1,121 @@
+use cpu::Register;
+use memory_region::MemoryRegion;
+use std::collections::{BTreeMap, VecDeque};
+
+pub const MAILBOX_CAPACITY: usize = 8;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub value: Register,
+    pub sender: Option<String>,
+}
+
+#[derive(Debug)]
+struct Mailbox {
+    end_addr: usize,
+    messages: VecDeque<Message>,
+}
+
+// Mailboxes of the alive creatures ordered by their addresses.
+// A creature is identified by the beginning address of her genome region.
+#[derive(Debug, Default)]
+pub struct MessageBoard {
+    mailboxes: BTreeMap<usize, Mailbox>,
+}
+
+impl MessageBoard {
+    pub fn new() -> MessageBoard {
+        MessageBoard {
+            mailboxes: BTreeMap::new(),
+        }
+    }
+
+    pub fn open(&mut self, r: &MemoryRegion) {
+        self.mailboxes.insert(
+            r.addr,
+            Mailbox {
+                end_addr: r.end_addr(),
+                messages: VecDeque::new(),
+            },
+        );
+    }
+
+    pub fn close(&mut self, r: &MemoryRegion) {
+        self.mailboxes.remove(&r.addr);
+    }
+
+    // The creature whose genome contains the given address.
+    pub fn find_owner(&self, addr: usize) -> Option<usize> {
+        self.mailboxes
+            .range(..=addr)
+            .next_back()
+            .filter(|&(_, m)| addr < m.end_addr)
+            .map(|(&owner, _)| owner)
+    }
+
+    // The creatures just before and after the given one in the soup.
+    pub fn find_neighbours(&self, owner: usize) -> Vec<usize> {
+        let prev = self.mailboxes.range(..owner).next_back();
+        let next = self.mailboxes.range((owner + 1)..).next();
+        prev.into_iter()
+            .chain(next)
+            .map(|(&addr, _)| addr)
+            .collect()
+    }
+
+    pub fn deliver(&mut self, owner: usize, msg: Message) -> bool {
+        match self.mailboxes.get_mut(&owner) {
+            Some(ref mut m) if m.messages.len() < MAILBOX_CAPACITY => {
+                m.messages.push_back(msg);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn receive(&mut self, owner: usize) -> Option<Message> {
+        self.mailboxes
+            .get_mut(&owner)
+            .and_then(|m| m.messages.pop_front())
+    }
+
+    pub fn count_messages(&self, owner: usize) -> usize {
+        self.mailboxes.get(&owner).map_or(0, |m| m.messages.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_board() {
+        let mut board = MessageBoard::new();
+        board.open(&MemoryRegion::new(0, 10));
+        board.open(&MemoryRegion::new(10, 5));
+        board.open(&MemoryRegion::new(30, 5));
+
+        assert_eq!(board.find_owner(0), Some(0));
+        assert_eq!(board.find_owner(14), Some(10));
+        assert_eq!(board.find_owner(15), None);
+        assert_eq!(board.find_owner(34), Some(30));
+        assert_eq!(board.find_neighbours(10), vec![0, 30]);
+        assert_eq!(board.find_neighbours(0), vec![10]);
+
+        let msg = Message {
+            value: 1,
+            sender: None,
+        };
+        for _ in 0..MAILBOX_CAPACITY {
+            assert!(board.deliver(10, msg.clone()));
+        }
+        assert!(!board.deliver(10, msg.clone()));
+        assert!(!board.deliver(20, msg.clone()));
+        assert_eq!(board.receive(10), Some(msg));
+        assert_eq!(board.count_messages(10), MAILBOX_CAPACITY - 1);
+
+        board.close(&MemoryRegion::new(10, 5));
+        assert_eq!(board.find_owner(14), None);
+        assert_eq!(board.receive(10), None);
+    }
+}
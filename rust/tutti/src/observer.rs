@@ -0,0 +1,51 @@
+use creature::Creature;
+use instruction::Instruction;
+use universe::Universe;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MutationCause {
+    CopyError,
+    CosmicRay,
+}
+
+// Callbacks invoked by Universe.
+// The creature which is executing is not in Universe::creatures while it is running,
+// so it is passed to the callbacks explicitly.
+// The callbacks during an instruction see the world clock before the instruction is counted.
+pub trait UniverseObserver {
+    // on_tick is called every time world_clock becomes a multiple of this interval.
+    fn tick_interval(&self) -> Option<usize> {
+        None
+    }
+
+    // It is called when a creature is put into the soup by Universe::inject_genome.
+    fn on_inject(&mut self, _univ: &Universe, _creature: &Creature) {}
+
+    fn on_birth(&mut self, _univ: &Universe, _mother: &Creature, _daughter: &Creature) {}
+
+    fn on_death(&mut self, _univ: &Universe, _creature: &Creature) {}
+
+    fn on_new_genotype(&mut self, _univ: &Universe, _geno_type: &str, _mother: Option<&str>) {}
+
+    // It is called when MovIab copies an instruction, the copy error is on_mutation instead.
+    fn on_write(&mut self, _univ: &Universe, _addr: usize, _ins: Instruction) {}
+
+    fn on_mutation(
+        &mut self,
+        _univ: &Universe,
+        _addr: usize,
+        _before: Instruction,
+        _after: Instruction,
+        _cause: MutationCause,
+    ) {
+    }
+
+    fn on_allocation_failure(&mut self, _univ: &Universe, _request_size: usize) {}
+
+    fn on_tick(&mut self, _univ: &Universe) {}
+
+    // It is called before the universe is reseeded by the extinction policy.
+    fn on_extinction(&mut self, _univ: &Universe) {}
+
+    fn on_stagnation(&mut self, _univ: &Universe) {}
+}
@@ -0,0 +1,166 @@
+use instruction::{Instruction, INSTRUCTION_COUNT};
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GenotypeProfile {
+    pub executed: [usize; INSTRUCTION_COUNT],
+    pub failed: [usize; INSTRUCTION_COUNT],
+    // instructions executed between Mal and Divide, except template searches.
+    pub copy_loop: usize,
+    // instructions which search a complement template (Jmp, Jmpb, Call, Adr, Adrb, Adrf).
+    pub template_search: usize,
+}
+
+impl GenotypeProfile {
+    fn new() -> GenotypeProfile {
+        GenotypeProfile {
+            executed: [0; INSTRUCTION_COUNT],
+            failed: [0; INSTRUCTION_COUNT],
+            copy_loop: 0,
+            template_search: 0,
+        }
+    }
+
+    pub fn count_executed(&self) -> usize {
+        self.executed.iter().sum()
+    }
+
+    pub fn count_failed(&self) -> usize {
+        self.failed.iter().sum()
+    }
+}
+
+impl fmt::Display for GenotypeProfile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "executed: {}, fails: {}, copy loop: {}, template search: {}",
+            self.count_executed(),
+            self.count_failed(),
+            self.copy_loop,
+            self.template_search
+        )
+    }
+}
+
+pub struct Profiler {
+    is_enable: bool,
+    profiles: HashMap<String, GenotypeProfile>,
+}
+
+impl fmt::Display for Profiler {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = self
+            .sorted_keys()
+            .into_iter()
+            .map(|key| format!("GenoType: {}, {}", key, self.profiles[key]))
+            .collect::<Vec<String>>()
+            .join("\n");
+
+        write!(f, "{}", s)
+    }
+}
+
+impl Default for Profiler {
+    fn default() -> Profiler {
+        Profiler::new()
+    }
+}
+
+impl Profiler {
+    pub fn new() -> Profiler {
+        Profiler {
+            is_enable: false,
+            profiles: HashMap::new(),
+        }
+    }
+
+    pub fn enable(&mut self) {
+        self.is_enable = true;
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.is_enable
+    }
+
+    pub fn record(&mut self, geno_type: &str, ins: Instruction, is_copying: bool, is_failed: bool) {
+        if !self.profiles.contains_key(geno_type) {
+            self.profiles
+                .insert(geno_type.to_string(), GenotypeProfile::new());
+        }
+        let p = self.profiles.get_mut(geno_type).unwrap();
+
+        let index = ins as usize;
+        p.executed[index] += 1;
+        if is_failed {
+            p.failed[index] += 1;
+        }
+
+        if Instruction::is_template_search(ins) {
+            p.template_search += 1;
+        } else if is_copying {
+            p.copy_loop += 1;
+        }
+    }
+
+    fn sorted_keys(&self) -> Vec<&String> {
+        let mut keys = self.profiles.keys().collect::<Vec<&String>>();
+        keys.sort();
+        keys
+    }
+
+    pub fn dump_all_profiles(&self) -> String {
+        self.sorted_keys()
+            .into_iter()
+            .map(|key| {
+                let p = &self.profiles[key];
+                let opcodes = (0..INSTRUCTION_COUNT)
+                    .filter(|&i| p.executed[i] != 0)
+                    .map(|i| {
+                        format!(
+                            "{:?}: {}/{}",
+                            Instruction::from_usize(i),
+                            p.executed[i],
+                            p.failed[i]
+                        )
+                    })
+                    .collect::<Vec<String>>()
+                    .join(", ");
+                format!(
+                    "type: {}, {}, opcodes(executed/fails): [{}]",
+                    key, p, opcodes
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use instruction::Instruction::*;
+
+    #[test]
+    fn test_record() {
+        let mut p = Profiler::new();
+        let t = "80-0".to_string();
+
+        p.record(&t, MovIab, true, false);
+        p.record(&t, MovIab, true, true);
+        p.record(&t, Jmpb, true, false);
+        p.record(&t, Adrf, false, true);
+        p.record(&t, IncA, false, false);
+
+        let r = &p.profiles["80-0"];
+        assert_eq!(r.executed[MovIab as usize], 2);
+        assert_eq!(r.failed[MovIab as usize], 1);
+        assert_eq!(r.failed[Adrf as usize], 1);
+        assert_eq!(r.count_executed(), 5);
+        assert_eq!(r.count_failed(), 2);
+        assert_eq!(r.copy_loop, 2);
+        assert_eq!(r.template_search, 2);
+        assert!(!p.profiles.contains_key("81-0"));
+    }
+}
@@ -0,0 +1,41 @@
+extern crate rand;
+
+use rand::{Rng, SeedableRng, XorShiftRng};
+use std::cell::RefCell;
+
+// Every random number of the simulation comes from the generator of the current thread.
+// A universe is reproducible if its thread is seeded before it is created.
+thread_local!(static RNG: RefCell<XorShiftRng> = RefCell::new(rand::thread_rng().gen()));
+
+pub fn seed(seed: u64) {
+    // XorShift doesn't accept the zero seed, so the constant words are mixed in.
+    let s = [seed as u32, (seed >> 32) as u32, 0x9e37_79b9, 0x7f4a_7c15];
+    RNG.with(|r| *r.borrow_mut() = XorShiftRng::from_seed(s));
+}
+
+pub fn with_rng<T, F>(f: F) -> T
+where
+    F: FnOnce(&mut XorShiftRng) -> T,
+{
+    RNG.with(|r| f(&mut r.borrow_mut()))
+}
+
+// A number in [low, high).
+pub fn gen_range(low: usize, high: usize) -> usize {
+    with_rng(|r| r.gen_range(low, high))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seed() {
+        seed(42);
+        let a = (0..8).map(|_| gen_range(0, 1000)).collect::<Vec<_>>();
+        seed(42);
+        let b = (0..8).map(|_| gen_range(0, 1000)).collect::<Vec<_>>();
+        assert_eq!(a, b);
+        assert!(a.iter().all(|&n| n < 1000));
+    }
+}
@@ -0,0 +1,507 @@
+use instruction::Instruction;
+use std::collections::HashMap;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::{BufWriter, SeekFrom};
+use std::path::{Path, PathBuf};
+use universe::Universe;
+
+pub type Rgb = [u8; 3];
+
+const FREE_COLOR: Rgb = [0, 0, 0];
+// The chunks before acTL are the signature and IHDR.
+const ACTL_OFFSET: u64 = 8 + 12 + 13;
+const IEND_SIZE: i64 = 12;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ColorMode {
+    // same color for the instructions which belong to the same genotype.
+    Genotype,
+    // same color for the same instruction.
+    Opcode,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ImageFormat {
+    Ppm,
+    Png,
+}
+
+impl ImageFormat {
+    pub fn extension(&self) -> &'static str {
+        match *self {
+            ImageFormat::Ppm => "ppm",
+            ImageFormat::Png => "png",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Rgb>,
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize) -> Image {
+        Image {
+            width,
+            height,
+            pixels: vec![FREE_COLOR; width * height],
+        }
+    }
+
+    // One pixel per cell, the cells are laid out from left to right and top to bottom.
+    fn with_cells(width: usize, count_cells: usize) -> Image {
+        debug_assert!(width != 0);
+        Image::new(width, count_cells.div_ceil(width))
+    }
+
+    pub fn write_ppm<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        write!(w, "P6\n{} {}\n255\n", self.width, self.height)?;
+        for p in self.pixels.iter() {
+            w.write_all(p)?;
+        }
+        Ok(())
+    }
+
+    fn write_png_header<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        w.write_all(b"\x89PNG\r\n\x1a\n")?;
+
+        let mut header = Vec::new();
+        header.extend_from_slice(&(self.width as u32).to_be_bytes());
+        header.extend_from_slice(&(self.height as u32).to_be_bytes());
+        // bit depth 8, truecolor, deflate, adaptive filtering, no interlace.
+        header.extend_from_slice(&[8, 2, 0, 0, 0]);
+        write_png_chunk(w, b"IHDR", &header)
+    }
+
+    // The compressed scanlines of IDAT.
+    fn png_data(&self) -> Vec<u8> {
+        let mut raw = Vec::with_capacity((self.width * 3 + 1) * self.height);
+        for row in self.pixels.chunks(self.width) {
+            // filter type None.
+            raw.push(0);
+            for p in row {
+                raw.extend_from_slice(p);
+            }
+        }
+        zlib_store(&raw)
+    }
+
+    pub fn write_png<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.write_png_header(w)?;
+        write_png_chunk(w, b"IDAT", &self.png_data())?;
+        write_png_chunk(w, b"IEND", &[])
+    }
+
+    pub fn save(&self, path: &Path, format: ImageFormat) -> io::Result<()> {
+        let mut w = BufWriter::new(File::create(path)?);
+        match format {
+            ImageFormat::Ppm => self.write_ppm(&mut w),
+            ImageFormat::Png => self.write_png(&mut w),
+        }
+    }
+}
+
+// Animated PNG, the file is valid after every frame.
+// The frame count in acTL is patched and IEND is overwritten by the next frame.
+pub struct Animation<W: Write + Seek> {
+    w: W,
+    width: usize,
+    height: usize,
+    delay_ms: u16,
+    count_frames: u32,
+    // Shared by fcTL and fdAT.
+    sequence: u32,
+}
+
+impl<W: Write + Seek> Animation<W> {
+    // The first frame decides the size of the animation.
+    pub fn new(mut w: W, first: &Image, delay_ms: u16) -> io::Result<Animation<W>> {
+        first.write_png_header(&mut w)?;
+        // The number of frames and infinite loop.
+        write_png_chunk(&mut w, b"acTL", &[0; 8])?;
+        write_png_chunk(&mut w, b"IEND", &[])?;
+
+        let mut a = Animation {
+            w,
+            width: first.width,
+            height: first.height,
+            delay_ms,
+            count_frames: 0,
+            sequence: 0,
+        };
+        a.push(first)?;
+        Ok(a)
+    }
+
+    pub fn push(&mut self, img: &Image) -> io::Result<()> {
+        if (img.width, img.height) != (self.width, self.height) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the frame size differs from the first frame",
+            ));
+        }
+
+        self.w.seek(SeekFrom::End(-IEND_SIZE))?;
+
+        let mut control = Vec::with_capacity(26);
+        control.extend_from_slice(&self.sequence.to_be_bytes());
+        control.extend_from_slice(&(self.width as u32).to_be_bytes());
+        control.extend_from_slice(&(self.height as u32).to_be_bytes());
+        // The offset, the delay in milliseconds, no dispose and source blend.
+        control.extend_from_slice(&[0; 8]);
+        control.extend_from_slice(&self.delay_ms.to_be_bytes());
+        control.extend_from_slice(&1000u16.to_be_bytes());
+        control.extend_from_slice(&[0, 0]);
+        write_png_chunk(&mut self.w, b"fcTL", &control)?;
+        self.sequence += 1;
+
+        // The first frame is the default image too.
+        if self.count_frames == 0 {
+            write_png_chunk(&mut self.w, b"IDAT", &img.png_data())?;
+        } else {
+            let mut data = self.sequence.to_be_bytes().to_vec();
+            data.extend_from_slice(&img.png_data());
+            write_png_chunk(&mut self.w, b"fdAT", &data)?;
+            self.sequence += 1;
+        }
+        write_png_chunk(&mut self.w, b"IEND", &[])?;
+        self.count_frames += 1;
+
+        self.w.seek(SeekFrom::Start(ACTL_OFFSET))?;
+        let mut actl = self.count_frames.to_be_bytes().to_vec();
+        actl.extend_from_slice(&[0; 4]);
+        write_png_chunk(&mut self.w, b"acTL", &actl)?;
+        self.w.seek(SeekFrom::End(0))?;
+        self.w.flush()
+    }
+
+    pub fn count_frames(&self) -> usize {
+        self.count_frames as usize
+    }
+}
+
+fn write_png_chunk<W: Write>(w: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    w.write_all(&(data.len() as u32).to_be_bytes())?;
+    w.write_all(kind)?;
+    w.write_all(data)?;
+
+    let crc = crc32(kind.iter().chain(data.iter()));
+    w.write_all(&crc.to_be_bytes())
+}
+
+fn crc32<'a, I: Iterator<Item = &'a u8>>(bytes: I) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &b in bytes {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            crc = if (crc & 1) != 0 {
+                0xEDB8_8320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &x in bytes {
+        a = (a + x as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+// zlib stream which consists of the uncompressed deflate blocks only.
+fn zlib_store(data: &[u8]) -> Vec<u8> {
+    let mut v = vec![0x78, 0x01];
+
+    let mut blocks = data.chunks(0xFFFF).peekable();
+    if blocks.peek().is_none() {
+        v.extend_from_slice(&[1, 0x00, 0x00, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        let is_final = blocks.peek().is_none();
+        let len = block.len() as u16;
+        v.push(if is_final { 1 } else { 0 });
+        v.extend_from_slice(&len.to_le_bytes());
+        v.extend_from_slice(&(!len).to_le_bytes());
+        v.extend_from_slice(block);
+    }
+
+    v.extend_from_slice(&adler32(data).to_be_bytes());
+    v
+}
+
+fn hsv_to_rgb(h: f64, s: f64, v: f64) -> Rgb {
+    let c = v * s;
+    let h = (h % 360.0) / 60.0;
+    let x = c * (1.0 - ((h % 2.0) - 1.0).abs());
+    let (r, g, b) = match h as usize {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+    let m = v - c;
+    let f = |x: f64| ((x + m) * 255.0).round() as u8;
+    [f(r), f(g), f(b)]
+}
+
+pub fn opcode_color(ins: Instruction) -> Rgb {
+    if Instruction::is_nop(ins) {
+        let l = if ins == Instruction::Nop0 { 0x60 } else { 0xA0 };
+        return [l, l, l];
+    }
+
+    hsv_to_rgb((ins as usize as f64) * 137.5, 0.8, 0.9)
+}
+
+pub fn geno_type_color(geno_type: &str) -> Rgb {
+    // FNV-1a
+    let hash = geno_type.bytes().fold(0xCBF2_9CE4_8422_2325u64, |h, b| {
+        (h ^ b as u64).wrapping_mul(0x0000_0100_0000_01B3)
+    });
+    let h = (hash % 360) as f64;
+    let s = 0.5 + ((hash >> 16) % 50) as f64 / 100.0;
+    hsv_to_rgb(h, s, 0.95)
+}
+
+fn darken(c: Rgb) -> Rgb {
+    [c[0] / 2, c[1] / 2, c[2] / 2]
+}
+
+pub fn render_genome_soup(univ: &Universe, width: usize, mode: ColorMode) -> Image {
+    let soup = univ.genome_soup();
+    let mut img = Image::with_cells(width, soup.len());
+
+    match mode {
+        ColorMode::Opcode => return render_instructions(soup, width),
+        ColorMode::Genotype => {
+            let mut colors = HashMap::new();
+            for c in univ.creatures() {
+                let color = match c.geno_type {
+                    Some(ref t) => *colors
+                        .entry(t.clone())
+                        .or_insert_with(|| geno_type_color(t)),
+                    None => [0xFF, 0xFF, 0xFF],
+                };
+
+                for p in img.pixels[c.genome_region.range()].iter_mut() {
+                    *p = color;
+                }
+
+                // The daughter under construction is drawn by the darker color of her mother.
+                if let Some(ref d) = c.daughter {
+                    for p in img.pixels[d.genome_region.range()].iter_mut() {
+                        *p = darken(color);
+                    }
+                }
+            }
+        }
+    }
+
+    img
+}
+
+pub fn render_instructions(soup: &[Instruction], width: usize) -> Image {
+    let mut img = Image::with_cells(width, soup.len());
+    for (p, &ins) in img.pixels.iter_mut().zip(soup.iter()) {
+        *p = opcode_color(ins);
+    }
+    img
+}
+
+// Heat colors from black through red and yellow to white in the log scale.
+pub fn render_write_heat_map(heat: &[u32], width: usize) -> Image {
+    let mut img = Image::with_cells(width, heat.len());
+
+    let max = heat.iter().cloned().max().unwrap_or(0);
+    if max == 0 {
+        return img;
+    }
+
+    let log_max = (max as f64).ln_1p();
+    for (p, &h) in img.pixels.iter_mut().zip(heat.iter()) {
+        let t = (h as f64).ln_1p() / log_max * 3.0;
+        let f = |x: f64| (x.clamp(0.0, 1.0) * 255.0).round() as u8;
+        *p = [f(t), f(t - 1.0), f(t - 2.0)];
+    }
+
+    img
+}
+
+// The delay between the frames of the assembled timelapse.
+pub const TIMELAPSE_DELAY_MS: u16 = 100;
+
+// Sequential frames for a timelapse.
+// They are numbered so that `ffmpeg -i soup_%06d.png` can assemble them into a movie.
+// The PNG frames are also assembled into the animated PNG `soup_timelapse.png`.
+pub struct Timelapse {
+    dir: PathBuf,
+    prefix: String,
+    format: ImageFormat,
+    count_frames: usize,
+    animation: Option<Animation<BufWriter<File>>>,
+}
+
+impl Timelapse {
+    pub fn new(dir: &Path, prefix: &str, format: ImageFormat) -> io::Result<Timelapse> {
+        fs::create_dir_all(dir)?;
+
+        Ok(Timelapse {
+            dir: dir.to_path_buf(),
+            prefix: prefix.to_string(),
+            format,
+            count_frames: 0,
+            animation: None,
+        })
+    }
+
+    pub fn push(&mut self, img: &Image) -> io::Result<PathBuf> {
+        let filename = format!(
+            "{}_{:06}.{}",
+            self.prefix,
+            self.count_frames,
+            self.format.extension()
+        );
+        let path = self.dir.join(filename);
+        img.save(&path, self.format)?;
+        self.count_frames += 1;
+
+        if self.format == ImageFormat::Png {
+            match self.animation {
+                Some(ref mut a) => a.push(img)?,
+                None => {
+                    let path = self.animation_path();
+                    let w = BufWriter::new(File::create(path)?);
+                    self.animation = Some(Animation::new(w, img, TIMELAPSE_DELAY_MS)?);
+                }
+            }
+        }
+
+        Ok(path)
+    }
+
+    pub fn count_frames(&self) -> usize {
+        self.count_frames
+    }
+
+    pub fn animation_path(&self) -> PathBuf {
+        self.dir.join(format!("{}_timelapse.png", self.prefix))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc32_adler32() {
+        assert_eq!(crc32(b"IEND".iter()), 0xAE42_6082);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+    }
+
+    #[test]
+    fn test_write_image() {
+        let mut img = Image::with_cells(4, 10);
+        assert_eq!((img.width, img.height), (4, 3));
+        img.pixels[0] = [1, 2, 3];
+
+        let mut ppm = Vec::new();
+        img.write_ppm(&mut ppm).unwrap();
+        assert!(ppm.starts_with(b"P6\n4 3\n255\n\x01\x02\x03"));
+        assert_eq!(ppm.len(), 11 + 4 * 3 * 3);
+
+        let mut png = Vec::new();
+        img.write_png(&mut png).unwrap();
+        assert!(png.starts_with(b"\x89PNG\r\n\x1a\n\x00\x00\x00\x0dIHDR"));
+        assert!(png.ends_with(b"IEND\xAE\x42\x60\x82"));
+    }
+
+    // The kinds and the data of the chunks, the CRCs are checked.
+    fn read_png_chunks(png: &[u8]) -> Vec<(String, Vec<u8>)> {
+        assert!(png.starts_with(b"\x89PNG\r\n\x1a\n"));
+        let mut chunks = Vec::new();
+        let mut rest = &png[8..];
+        while !rest.is_empty() {
+            let mut len = [0; 4];
+            len.copy_from_slice(&rest[..4]);
+            let len = u32::from_be_bytes(len) as usize;
+            let (kind, data) = (&rest[4..8], &rest[8..8 + len]);
+            let mut crc = [0; 4];
+            crc.copy_from_slice(&rest[8 + len..12 + len]);
+            assert_eq!(
+                u32::from_be_bytes(crc),
+                crc32(kind.iter().chain(data.iter()))
+            );
+            chunks.push((String::from_utf8(kind.to_vec()).unwrap(), data.to_vec()));
+            rest = &rest[12 + len..];
+        }
+        chunks
+    }
+
+    #[test]
+    fn test_animation() {
+        use std::io::Cursor;
+
+        let mut img = Image::with_cells(4, 8);
+        let mut a = Animation::new(Cursor::new(Vec::new()), &img, 50).unwrap();
+        img.pixels[0] = [1, 2, 3];
+        a.push(&img).unwrap();
+        a.push(&img).unwrap();
+        assert_eq!(a.count_frames(), 3);
+        assert!(a.push(&Image::new(4, 3)).is_err());
+
+        let chunks = read_png_chunks(a.w.get_ref());
+        let kinds: Vec<&str> = chunks.iter().map(|c| c.0.as_str()).collect();
+        assert_eq!(
+            kinds,
+            vec!["IHDR", "acTL", "fcTL", "IDAT", "fcTL", "fdAT", "fcTL", "fdAT", "IEND"]
+        );
+        assert_eq!(chunks[1].1, vec![0, 0, 0, 3, 0, 0, 0, 0]);
+
+        // The sequence numbers of fcTL and fdAT.
+        let sequences: Vec<u8> = chunks
+            .iter()
+            .filter(|c| c.0 == "fcTL" || c.0 == "fdAT")
+            .map(|c| c.1[3])
+            .collect();
+        assert_eq!(sequences, vec![0, 1, 2, 3, 4]);
+        assert_eq!(&chunks[2].1[20..24], &[0, 50, 0x03, 0xE8]);
+        assert_eq!(&chunks[7].1[4..], &img.png_data()[..]);
+    }
+
+    #[test]
+    fn test_timelapse() {
+        let dir = ::std::env::temp_dir().join(format!("tutti_timelapse_{}", ::std::process::id()));
+        let mut t = Timelapse::new(&dir, "soup", ImageFormat::Png).unwrap();
+        let img = Image::with_cells(4, 8);
+        assert_eq!(t.push(&img).unwrap(), dir.join("soup_000000.png"));
+        t.push(&img).unwrap();
+        assert_eq!(t.count_frames(), 2);
+
+        let png = fs::read(t.animation_path()).unwrap();
+        let chunks = read_png_chunks(&png);
+        assert_eq!(chunks[1].1[..4], [0, 0, 0, 2]);
+        assert_eq!(chunks.last().unwrap().0, "IEND");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_render_write_heat_map() {
+        let img = render_write_heat_map(&[0, 1, 100, 0], 2);
+        assert_eq!(img.pixels[0], [0, 0, 0]);
+        assert_eq!(img.pixels[2], [255, 255, 255]);
+        assert_eq!(img.pixels[3], [0, 0, 0]);
+    }
+}
@@ -0,0 +1,211 @@
+use gene_bank::GeneBank;
+use instruction::Instruction;
+use std::collections::HashMap;
+use std::mem;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SpeciesCriterion {
+    // A genotype belongs to the nearest species whose founder is within this edit distance.
+    EditDistance(usize),
+    // Genotypes belong to the same species if they execute the same instructions in this steps.
+    Trace(usize),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Species {
+    pub id: usize,
+    // The genotype which founded this species.
+    pub founder: String,
+    genome: Vec<Instruction>,
+    pub geno_types: Vec<String>,
+}
+
+impl Species {
+    pub fn count_alive(&self, bank: &GeneBank) -> usize {
+        self.geno_types.iter().map(|t| bank.count_alive(t)).sum()
+    }
+
+    pub fn count_dead(&self, bank: &GeneBank) -> usize {
+        self.geno_types.iter().map(|t| bank.count_dead(t)).sum()
+    }
+}
+
+// Clusters of genotypes.
+// Each new genotype is classified once when it is registered, the species are never merged.
+pub struct SpeciesMap {
+    criterion: SpeciesCriterion,
+    species: Vec<Species>,
+    species_of_trace: HashMap<Vec<Instruction>, usize>,
+    species_of_geno_type: HashMap<String, usize>,
+}
+
+impl SpeciesMap {
+    pub fn new(criterion: SpeciesCriterion) -> SpeciesMap {
+        SpeciesMap {
+            criterion,
+            species: Vec::new(),
+            species_of_trace: HashMap::new(),
+            species_of_geno_type: HashMap::new(),
+        }
+    }
+
+    pub fn criterion(&self) -> &SpeciesCriterion {
+        &self.criterion
+    }
+
+    // The trace is used only by SpeciesCriterion::Trace.
+    pub fn classify(
+        &mut self,
+        geno_type: &str,
+        genome: &[Instruction],
+        trace: Option<Vec<Instruction>>,
+    ) -> usize {
+        // Different genomes can have the same geno_type, it stays in the first species.
+        if let Some(&id) = self.species_of_geno_type.get(geno_type) {
+            return id;
+        }
+
+        let id = match self.criterion {
+            SpeciesCriterion::EditDistance(threshold) => self
+                .species
+                .iter()
+                .filter(|s| genome.len().abs_diff(s.genome.len()) <= threshold)
+                .map(|s| (edit_distance(genome, &s.genome), s.id))
+                .filter(|&(d, _)| d <= threshold)
+                .min()
+                .map(|(_, id)| id),
+            SpeciesCriterion::Trace(_) => trace
+                .as_ref()
+                .and_then(|t| self.species_of_trace.get(t).cloned()),
+        };
+
+        let id = id.unwrap_or_else(|| {
+            let id = self.species.len();
+            self.species.push(Species {
+                id,
+                founder: geno_type.to_string(),
+                genome: genome.to_vec(),
+                geno_types: Vec::new(),
+            });
+            if let Some(t) = trace {
+                self.species_of_trace.insert(t, id);
+            }
+            id
+        });
+
+        self.species[id].geno_types.push(geno_type.to_string());
+        self.species_of_geno_type.insert(geno_type.to_string(), id);
+        id
+    }
+
+    pub fn species(&self) -> &[Species] {
+        &self.species
+    }
+
+    pub fn species_of(&self, geno_type: &str) -> Option<&Species> {
+        self.species_of_geno_type
+            .get(geno_type)
+            .map(|&id| &self.species[id])
+    }
+
+    // The population of the alive species in the descending order.
+    pub fn report(&self, bank: &GeneBank) -> String {
+        let mut v = self
+            .species
+            .iter()
+            .map(|s| (s.count_alive(bank), s))
+            .filter(|&(alive, _)| alive != 0)
+            .collect::<Vec<_>>();
+        v.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.id.cmp(&b.1.id)));
+
+        let mut lines = v
+            .iter()
+            .map(|&(alive, s)| {
+                format!(
+                    "Species: {}, founder: {}, genotypes: {}, alives: {}, deads: {}",
+                    s.id,
+                    s.founder,
+                    s.geno_types.len(),
+                    alive,
+                    s.count_dead(bank)
+                )
+            })
+            .collect::<Vec<String>>();
+        lines.push(format!(
+            "# of species: {}, alive: {}",
+            self.species.len(),
+            v.len()
+        ));
+        lines.join("\n")
+    }
+}
+
+// Levenshtein distance.
+pub fn edit_distance(a: &[Instruction], b: &[Instruction]) -> usize {
+    let mut prev = (0..=b.len()).collect::<Vec<usize>>();
+    let mut curr = vec![0; b.len() + 1];
+
+    for (i, &x) in a.iter().enumerate() {
+        curr[0] = i + 1;
+        for (j, &y) in b.iter().enumerate() {
+            let cost = if x == y { 0 } else { 1 };
+            curr[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(curr[j] + 1);
+        }
+        mem::swap(&mut prev, &mut curr);
+    }
+
+    prev[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use instruction::Instruction::*;
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance(&[], &[Nop0, Nop1]), 2);
+        assert_eq!(edit_distance(&[Nop0, Nop1, Mal], &[Nop0, Nop1, Mal]), 0);
+        assert_eq!(edit_distance(&[Nop0, Nop1, Mal], &[Nop0, Mal]), 1);
+        assert_eq!(edit_distance(&[Nop0, Nop1, Mal], &[Nop1, Nop1, Divide]), 2);
+    }
+
+    #[test]
+    fn test_classify_by_edit_distance() {
+        let mut m = SpeciesMap::new(SpeciesCriterion::EditDistance(1));
+        assert_eq!(m.classify("3-0", &[Nop0, Nop1, Mal], None), 0);
+        assert_eq!(m.classify("3-1", &[Nop0, Nop0, Mal], None), 0);
+        assert_eq!(m.classify("3-2", &[Nop1, Nop0, Mal], None), 1);
+        assert_eq!(m.classify("4-0", &[Nop0, Nop1, Mal, Mal], None), 0);
+        assert_eq!(m.classify("3-2", &[Nop0, Nop1, Mal], None), 1);
+
+        assert_eq!(m.species_of("3-1").unwrap().founder, "3-0");
+        assert_eq!(m.species()[0].geno_types, vec!["3-0", "3-1", "4-0"]);
+
+        let mut bank = GeneBank::new();
+        bank.register_genome(&vec![Nop0, Nop1, Mal], None);
+        bank.count_up_alive_genome(&"3-0".to_string());
+        assert_eq!(
+            m.report(&bank),
+            "Species: 0, founder: 3-0, genotypes: 3, alives: 1, deads: 0\n\
+             # of species: 2, alive: 1"
+        );
+    }
+
+    #[test]
+    fn test_classify_by_trace() {
+        let mut m = SpeciesMap::new(SpeciesCriterion::Trace(2));
+        assert_eq!(
+            m.classify("3-0", &[Nop0, Nop1, Mal], Some(vec![Nop0, Nop1])),
+            0
+        );
+        assert_eq!(
+            m.classify("3-1", &[Nop0, Nop1, Ret], Some(vec![Nop0, Nop1])),
+            0
+        );
+        assert_eq!(
+            m.classify("3-2", &[Nop1, Nop1, Mal], Some(vec![Nop1, Nop1])),
+            1
+        );
+    }
+}
@@ -0,0 +1,341 @@
+use ancestor::ANCESTOR;
+use config::Config;
+use creature::COPY_FAIL_INTERVAL;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use universe::{Universe, COSMIC_RAY_INTERVAL};
+
+// The parameters of one run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunParams {
+    pub copy_fail_interval: (usize, usize),
+    pub cosmic_ray_interval: (usize, usize),
+    pub genome_capacity: usize,
+    pub slice_power: f64,
+    pub reaper_threshold: f64,
+    pub seed: u64,
+}
+
+impl RunParams {
+    pub fn config(&self) -> Config {
+        Config {
+            genome_capacity: self.genome_capacity,
+            is_enable_random_mutate: true,
+            copy_fail_interval: self.copy_fail_interval,
+            cosmic_ray_interval: self.cosmic_ray_interval,
+            seed: Some(self.seed),
+            slice_power: self.slice_power,
+            reaper_threshold: self.reaper_threshold,
+            ..Default::default()
+        }
+    }
+}
+
+// Every combination of these values is run for the given rounds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SweepGrid {
+    pub copy_fail_intervals: Vec<(usize, usize)>,
+    pub cosmic_ray_intervals: Vec<(usize, usize)>,
+    pub genome_capacities: Vec<usize>,
+    pub slice_powers: Vec<f64>,
+    pub reaper_thresholds: Vec<f64>,
+    pub seeds: Vec<u64>,
+    pub rounds: usize,
+}
+
+impl Default for SweepGrid {
+    fn default() -> SweepGrid {
+        let config = Config::default();
+        SweepGrid {
+            copy_fail_intervals: vec![COPY_FAIL_INTERVAL],
+            cosmic_ray_intervals: vec![COSMIC_RAY_INTERVAL],
+            genome_capacities: vec![config.genome_capacity],
+            slice_powers: vec![config.slice_power],
+            reaper_thresholds: vec![config.reaper_threshold],
+            seeds: vec![0],
+            rounds: 100,
+        }
+    }
+}
+
+fn parse_values<T, F>(words: &[&str], f: F) -> Option<Vec<T>>
+where
+    F: Fn(&str) -> Option<T>,
+{
+    let v = words.iter().map(|w| f(w)).collect::<Option<Vec<T>>>()?;
+    if v.is_empty() {
+        None
+    } else {
+        Some(v)
+    }
+}
+
+fn parse_interval(word: &str) -> Option<(usize, usize)> {
+    let mut it = word.splitn(2, '-').map(|n| n.parse::<usize>().ok());
+    match (it.next()?, it.next()?) {
+        (Some(low), Some(high)) if 0 < low && low < high => Some((low, high)),
+        _ => None,
+    }
+}
+
+impl SweepGrid {
+    // The spec consists of the lines of a parameter name and its values like below.
+    // The parameters which are not given take the default values.
+    //   # comment
+    //   copy_fail 1000-2500 500-1250
+    //   cosmic_ray 10000-20000
+    //   capacity 65536 131072
+    //   slice_power 1.0 1.2
+    //   reaper_threshold 0.8
+    //   seeds 1 2 3
+    //   rounds 1000
+    pub fn parse(spec: &str) -> Result<SweepGrid, String> {
+        let mut grid = SweepGrid::default();
+
+        for (i, line) in spec.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let words = line.split_whitespace().collect::<Vec<_>>();
+            let values = &words[1..];
+            let number = |w: &str| w.parse::<usize>().ok().filter(|&n| 0 < n);
+            let rate = |w: &str| w.parse::<f64>().ok().filter(|&r| 0.0 < r);
+            let is_ok = match words[0] {
+                "copy_fail" => parse_values(values, parse_interval)
+                    .map(|v| grid.copy_fail_intervals = v)
+                    .is_some(),
+                "cosmic_ray" => parse_values(values, parse_interval)
+                    .map(|v| grid.cosmic_ray_intervals = v)
+                    .is_some(),
+                "capacity" => parse_values(values, number)
+                    .map(|v| grid.genome_capacities = v)
+                    .is_some(),
+                "slice_power" => parse_values(values, rate)
+                    .map(|v| grid.slice_powers = v)
+                    .is_some(),
+                "reaper_threshold" => parse_values(values, |w| rate(w).filter(|&r| r <= 1.0))
+                    .map(|v| grid.reaper_thresholds = v)
+                    .is_some(),
+                "seeds" => parse_values(values, |w| w.parse::<u64>().ok())
+                    .map(|v| grid.seeds = v)
+                    .is_some(),
+                "rounds" => match *values {
+                    [n] => number(n).map(|n| grid.rounds = n).is_some(),
+                    _ => false,
+                },
+                name => return Err(format!("line {}: unknown parameter {}", i + 1, name)),
+            };
+            if !is_ok {
+                return Err(format!("line {}: invalid values", i + 1));
+            }
+        }
+
+        Ok(grid)
+    }
+
+    pub fn load(path: &Path) -> io::Result<SweepGrid> {
+        let mut spec = String::new();
+        File::open(path)?.read_to_string(&mut spec)?;
+        SweepGrid::parse(&spec).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    // The seeds vary fastest.
+    pub fn runs(&self) -> Vec<RunParams> {
+        let mut runs = Vec::new();
+        for &copy_fail_interval in self.copy_fail_intervals.iter() {
+            for &cosmic_ray_interval in self.cosmic_ray_intervals.iter() {
+                for &genome_capacity in self.genome_capacities.iter() {
+                    for &slice_power in self.slice_powers.iter() {
+                        for &reaper_threshold in self.reaper_thresholds.iter() {
+                            for &seed in self.seeds.iter() {
+                                runs.push(RunParams {
+                                    copy_fail_interval,
+                                    cosmic_ray_interval,
+                                    genome_capacity,
+                                    slice_power,
+                                    reaper_threshold,
+                                    seed,
+                                });
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        runs
+    }
+}
+
+// The state of the universe at the end of a run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunSummary {
+    pub params: RunParams,
+    pub rounds: usize,
+    pub world_clock: usize,
+    pub creatures: usize,
+    pub geno_types: usize,
+    pub alive_geno_types: usize,
+    pub mean_size: f64,
+    pub is_extinct: bool,
+}
+
+impl RunSummary {
+    pub const HEADER: &'static str = "copy_fail\tcosmic_ray\tcapacity\tslice_power\treaper_threshold\tseed\trounds\tworld_clock\tcreatures\tgeno_types\talive_geno_types\tmean_size\textinct";
+
+    pub fn to_row(&self) -> String {
+        let p = &self.params;
+        format!(
+            "{}-{}\t{}-{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{:.2}\t{}",
+            p.copy_fail_interval.0,
+            p.copy_fail_interval.1,
+            p.cosmic_ray_interval.0,
+            p.cosmic_ray_interval.1,
+            p.genome_capacity,
+            p.slice_power,
+            p.reaper_threshold,
+            p.seed,
+            self.rounds,
+            self.world_clock,
+            self.creatures,
+            self.geno_types,
+            self.alive_geno_types,
+            self.mean_size,
+            self.is_extinct
+        )
+    }
+}
+
+// Run the ancestor until the given rounds or the extinction.
+pub fn run(params: &RunParams, rounds: usize) -> RunSummary {
+    let mut univ = Universe::with_config(params.config());
+    univ.generate_creature(&ANCESTOR);
+    univ.randomize_mutate_thresholds();
+
+    let mut count_rounds = 0;
+    while count_rounds < rounds && univ.count_creatures() != 0 {
+        univ.run_round();
+        count_rounds += 1;
+    }
+
+    let creatures = univ.count_creatures();
+    let total_size = univ
+        .creatures()
+        .iter()
+        .map(|c| c.genome_region.size)
+        .sum::<usize>();
+    RunSummary {
+        params: params.clone(),
+        rounds: count_rounds,
+        world_clock: univ.world_clock(),
+        creatures,
+        geno_types: univ.gene_bank().count_records(),
+        alive_geno_types: univ.gene_bank().alive_geno_types().len(),
+        mean_size: if creatures == 0 {
+            0.0
+        } else {
+            total_size as f64 / creatures as f64
+        },
+        is_extinct: creatures == 0,
+    }
+}
+
+// Run all the parameters by the given number of threads.
+// A run is reproducible in any thread because it seeds the generator of the thread.
+// The summaries are in the same order as the parameters.
+pub fn run_all(runs: &[RunParams], rounds: usize, jobs: usize) -> Vec<RunSummary> {
+    let runs = Arc::new(runs.to_vec());
+    let next = Arc::new(AtomicUsize::new(0));
+    let (tx, rx) = mpsc::channel();
+
+    let workers = (0..jobs.max(1).min(runs.len()))
+        .map(|_| {
+            let (runs, next, tx) = (runs.clone(), next.clone(), tx.clone());
+            thread::spawn(move || loop {
+                let i = next.fetch_add(1, Ordering::SeqCst);
+                if runs.len() <= i {
+                    break;
+                }
+                tx.send((i, run(&runs[i], rounds))).unwrap();
+            })
+        })
+        .collect::<Vec<_>>();
+    drop(tx);
+
+    let mut summaries = rx.iter().collect::<Vec<_>>();
+    for w in workers {
+        w.join().unwrap();
+    }
+    summaries.sort_by_key(|&(i, _)| i);
+    summaries.into_iter().map(|(_, s)| s).collect()
+}
+
+// Tab separated values with the header.
+pub fn write_table<W: Write>(w: &mut W, summaries: &[RunSummary]) -> io::Result<()> {
+    writeln!(w, "{}", RunSummary::HEADER)?;
+    for s in summaries {
+        writeln!(w, "{}", s.to_row())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_sweep_grid() {
+        let grid = SweepGrid::parse(
+            "# sweep\ncopy_fail 1000-2500 500-1250\ncapacity 4096\nslice_power 1.0 1.2 # power\nseeds 1 2\nrounds 5\n",
+        )
+        .unwrap();
+        assert_eq!(grid.copy_fail_intervals, vec![(1000, 2500), (500, 1250)]);
+        assert_eq!(grid.cosmic_ray_intervals, vec![COSMIC_RAY_INTERVAL]);
+        assert_eq!(grid.genome_capacities, vec![4096]);
+        assert_eq!(grid.rounds, 5);
+
+        let runs = grid.runs();
+        assert_eq!(runs.len(), 8);
+        assert_eq!(runs[1].seed, 2);
+        assert_eq!(runs[2].slice_power, 1.2);
+        assert_eq!(runs[4].copy_fail_interval, (500, 1250));
+
+        assert!(SweepGrid::parse("mutation 1").is_err());
+        assert!(SweepGrid::parse("copy_fail 2500-1000").is_err());
+        assert!(SweepGrid::parse("capacity").is_err());
+        assert!(SweepGrid::parse("reaper_threshold 1.5").is_err());
+        assert!(SweepGrid::parse("rounds 1 2").is_err());
+    }
+
+    #[test]
+    fn test_run_all() {
+        let grid = SweepGrid {
+            genome_capacities: vec![4096],
+            seeds: vec![7, 8, 7],
+            rounds: 100,
+            ..Default::default()
+        };
+        let summaries = run_all(&grid.runs(), grid.rounds, 2);
+        assert_eq!(summaries.len(), 3);
+        assert_eq!(summaries[1].params.seed, 8);
+        assert!(summaries.iter().all(|s| s.rounds == 100 && !s.is_extinct));
+        // The same seed gives the same result.
+        assert_eq!(summaries[0], summaries[2]);
+
+        let mut buf = Vec::new();
+        write_table(&mut buf, &summaries).unwrap();
+        let table = String::from_utf8(buf).unwrap();
+        assert_eq!(table.lines().count(), 4);
+        assert!(table
+            .lines()
+            .nth(3)
+            .unwrap()
+            .starts_with("1000-2500\t10000-20000\t4096\t1.2\t0.8\t7\t100\t"));
+    }
+}
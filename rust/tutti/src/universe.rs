@@ -1,38 +1,109 @@
-extern crate rand;
-
+use config::{Config, ExtinctionPolicy};
+use cpu::Register;
 use creature::Creature;
+use environment::{TaskState, TASKS, TASK_COUNT};
 use gene_bank::GeneBank;
-use instruction::Instruction;
+use instruction::{Instruction, BASE_INSTRUCTION_COUNT};
 use memory_region::MemoryRegion;
-use rand::Rng;
+use message_board::{Message, MessageBoard};
+use observer::{MutationCause, UniverseObserver};
+use profiler::Profiler;
+use random;
+use species::{SpeciesCriterion, SpeciesMap};
+use std::collections::HashSet;
 use std::mem;
+use std::ops::Range;
 
 pub const UNIVERSE_TOTAL_GENOME_CAPACITY: usize = 128 * 1024;
+// A cosmic ray hits once in the ticks of this range by default.
+pub const COSMIC_RAY_INTERVAL: (usize, usize) = (10000, 20000);
+
+// The round in progress, `step` can suspend it between two instructions.
+#[derive(Debug, Clone, Copy)]
+struct Round {
+    power: f64,
+    // The creatures born in the round wait for the next one.
+    count_creatures: usize,
+    index: usize,
+    // The rest of the time slice of the creature at the index.
+    time_slice: Option<usize>,
+    is_alive: bool,
+    count_executed: usize,
+}
 
 pub struct Universe {
-    genome_soup: [Instruction; UNIVERSE_TOTAL_GENOME_CAPACITY],
+    genome_soup: Vec<Instruction>,
     pub free_regions: Vec<MemoryRegion>,
     pub creatures: Vec<Creature>,
     world_clock: usize,
+    energy_pool: usize,
+    config: Config,
     is_enable_random_mutate: bool,
     mutate_threshold_cosmic_rays: usize,
     pub gene_bank: GeneBank,
+    profiler: Profiler,
+    write_heat_map: Option<Vec<u32>>,
+    // Mutations choose one of these.
+    instruction_set: Vec<Instruction>,
+    message_board: Option<MessageBoard>,
+    // The genomes for ExtinctionPolicy::ReseedFromSnapshot.
+    snapshot: Vec<Vec<Instruction>>,
+    last_new_genotype_clock: usize,
+    is_stagnant: bool,
+    mutation_rate: f64,
+    species: Option<SpeciesMap>,
+    observers: Vec<(Box<dyn UniverseObserver>, Option<usize>)>,
+    round: Option<Round>,
+}
+
+impl Default for Universe {
+    fn default() -> Universe {
+        Universe::new()
+    }
 }
 
 impl Universe {
     pub fn new() -> Universe {
-        let soup = [Instruction::IfCz; UNIVERSE_TOTAL_GENOME_CAPACITY];
+        Universe::with_config(Config::default())
+    }
+
+    pub fn with_config(config: Config) -> Universe {
+        if let Some(seed) = config.seed {
+            random::seed(seed);
+        }
+
+        let soup = vec![Instruction::IfCz; config.genome_capacity];
         let mut free_regions = Vec::new();
         free_regions.push(MemoryRegion::new(0, soup.len()));
+        let instruction_set = Universe::compute_instruction_set(&config);
+        let message_board = if config.is_enable_communication {
+            Some(MessageBoard::new())
+        } else {
+            None
+        };
+        let species = config.species.clone().map(SpeciesMap::new);
 
         Universe {
             genome_soup: soup,
             free_regions: free_regions,
             creatures: Vec::new(),
             world_clock: 0,
-            is_enable_random_mutate: false,
+            energy_pool: config.energy.as_ref().map_or(0, |e| e.pool_capacity),
+            is_enable_random_mutate: config.is_enable_random_mutate,
+            config,
             mutate_threshold_cosmic_rays: 2500,
             gene_bank: GeneBank::new(),
+            profiler: Profiler::new(),
+            write_heat_map: None,
+            instruction_set,
+            message_board,
+            snapshot: Vec::new(),
+            last_new_genotype_clock: 0,
+            is_stagnant: false,
+            mutation_rate: 1.0,
+            species,
+            observers: Vec::new(),
+            round: None,
         }
     }
 
@@ -41,41 +112,226 @@ impl Universe {
             return;
         }
 
+        let (interval, rate) = (self.config.copy_fail_interval, self.mutation_rate);
         for c in self.creatures.iter_mut() {
-            c.randomize_mutate_threshold_copy_fail();
+            c.randomize_mutate_threshold_copy_fail_by(interval, rate);
         }
         self.randomize_mutate_threshold_cosmic_rays();
     }
 
     fn randomize_mutate_threshold_cosmic_rays(&mut self) {
-        self.mutate_threshold_cosmic_rays = rand::thread_rng().gen_range(10000, 20000);
+        let (low, high) = self.config.cosmic_ray_interval;
+        let threshold = random::gen_range(low, high) as f64 / self.mutation_rate;
+        self.mutate_threshold_cosmic_rays = (threshold as usize).max(1);
     }
 
     pub fn enable_random_mutate(&mut self) {
         self.is_enable_random_mutate = true;
     }
 
-    pub fn generate_creature(&mut self, instructions: &[Instruction]) {
-        match self.allocate_genome_soup(instructions.len()) {
-            None => panic!("no memory"),
-            Some(genome_region) => {
-                let mut c = Creature::new(genome_region);
-                self.write_instructions(c.genome_region.addr, instructions);
-
-                let v = instructions.to_vec();
-                {
-                    c.geno_type = self.gene_bank.register_genome(&v, None);
-                    self.gene_bank
-                        .count_up_alive_genome(c.geno_type.as_ref().unwrap());
-                }
+    fn compute_instruction_set(config: &Config) -> Vec<Instruction> {
+        let mut v = (0..BASE_INSTRUCTION_COUNT)
+            .map(Instruction::from_usize)
+            .collect::<Vec<Instruction>>();
+
+        if 1 < config.maximum_threads {
+            v.push(Instruction::Split);
+            v.push(Instruction::Join);
+        }
+
+        if config.is_enable_communication {
+            v.push(Instruction::Put);
+            v.push(Instruction::Get);
+            v.push(Instruction::Send);
+        }
+
+        if config.environment.is_some() {
+            v.push(Instruction::In);
+            v.push(Instruction::Out);
+        }
+
+        v
+    }
+
+    pub fn instruction_set(&self) -> &[Instruction] {
+        &self.instruction_set
+    }
+
+    fn is_enabled_instruction(&self, ins: Instruction) -> bool {
+        Instruction::is_base(ins) || self.instruction_set.contains(&ins)
+    }
+
+    fn mutate_instruction(&self, ins: Instruction) -> Instruction {
+        if self.instruction_set.len() == BASE_INSTRUCTION_COUNT {
+            ins.mutate_bit_randomly()
+        } else {
+            ins.mutate_randomly_within(&self.instruction_set)
+        }
+    }
 
-                self.creatures.push(c);
+    pub fn enable_profiler(&mut self) {
+        self.profiler.enable();
+    }
+
+    // Count writes by MovIab and cosmic rays for each address.
+    pub fn enable_write_heat_map(&mut self) {
+        if self.write_heat_map.is_none() {
+            self.write_heat_map = Some(vec![0; self.genome_soup.len()]);
+        }
+    }
+
+    fn heat_up(&mut self, addr: usize) {
+        if let Some(ref mut heat) = self.write_heat_map {
+            heat[addr] = heat[addr].saturating_add(1);
+        }
+    }
+
+    pub fn add_observer(&mut self, observer: Box<dyn UniverseObserver>) {
+        let interval = observer.tick_interval().filter(|&n| n != 0);
+        self.observers.push((observer, interval));
+    }
+
+    fn notify<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&mut dyn UniverseObserver, &Universe),
+    {
+        if self.observers.is_empty() {
+            return;
+        }
+
+        let mut observers = mem::take(&mut self.observers);
+        for &mut (ref mut o, _) in observers.iter_mut() {
+            f(o.as_mut(), self);
+        }
+        self.observers = observers;
+    }
+
+    fn notify_tick(&mut self) {
+        let clock = self.world_clock;
+        let is_tick = self
+            .observers
+            .iter()
+            .any(|&(_, interval)| interval.is_some_and(|n| clock.is_multiple_of(n)));
+        if !is_tick {
+            return;
+        }
+
+        let mut observers = mem::take(&mut self.observers);
+        for &mut (ref mut o, interval) in observers.iter_mut() {
+            if interval.is_some_and(|n| clock.is_multiple_of(n)) {
+                o.on_tick(self);
             }
         }
+        self.observers = observers;
+    }
+
+    fn register_genome(
+        &mut self,
+        genome: Vec<Instruction>,
+        mother: Option<&String>,
+    ) -> Option<String> {
+        let count_records = self.gene_bank.count_records();
+        let geno_type = self.gene_bank.register_genome(&genome, mother);
+
+        if count_records < self.gene_bank.count_records() {
+            self.last_new_genotype_clock = self.world_clock;
+            if self.is_stagnant {
+                self.is_stagnant = false;
+                self.mutation_rate = 1.0;
+            }
+
+            let t = geno_type.clone().unwrap();
+            if let Some(criterion) = self.species.as_ref().map(|s| s.criterion().clone()) {
+                let trace = match criterion {
+                    SpeciesCriterion::Trace(steps) => Some(self.trace_genome(&genome, steps)),
+                    _ => None,
+                };
+                self.species.as_mut().unwrap().classify(&t, &genome, trace);
+            }
+            self.notify(|o, u| o.on_new_genotype(u, &t, mother.map(|m| m.as_str())));
+        }
+
+        geno_type
+    }
+
+    // The instructions executed by the creature which has the given genome alone in a small soup.
+    pub fn trace_genome(&self, genome: &[Instruction], steps: usize) -> Vec<Instruction> {
+        let mut univ = Universe::with_config(Config {
+            // room for the daughters.
+            genome_capacity: genome.len() * 4,
+            is_enable_random_mutate: false,
+            // The generator of the running universe must not be reseeded.
+            seed: None,
+            energy: None,
+            extinction_policy: ExtinctionPolicy::Stop,
+            stagnation: None,
+            species: None,
+            ..self.config.clone()
+        });
+
+        let mut c = match univ.inject_genome(genome) {
+            None => return Vec::new(),
+            Some(_) => univ.creatures.pop().unwrap(),
+        };
+
+        let mut trace = Vec::with_capacity(steps);
+        for _ in 0..steps {
+            let ip = match c.thread_index {
+                0 => c.core.ip,
+                i => c.threads[i - 1].ip,
+            };
+            trace.push(univ.genome_soup[ip as usize]);
+            univ.one_thread_cycle(&mut c);
+        }
+        trace
+    }
+
+    pub fn generate_creature(&mut self, instructions: &[Instruction]) {
+        if self.inject_genome(instructions).is_none() {
+            panic!("no memory");
+        }
+    }
+
+    // Put a new creature which has the given genome into the soup.
+    // None is returned if there is no free region for it.
+    pub fn inject_genome(&mut self, instructions: &[Instruction]) -> Option<MemoryRegion> {
+        let genome_region = self.allocate_genome_soup(instructions.len())?;
+
+        let mut c = Creature::new(genome_region);
+        self.write_instructions(c.genome_region.addr, instructions);
+
+        let v = instructions.to_vec();
+        {
+            c.geno_type = self.register_genome(v, None);
+            self.gene_bank
+                .count_up_alive_genome(c.geno_type.as_ref().unwrap());
+            self.gene_bank
+                .record_seen(c.geno_type.as_ref().unwrap(), self.world_clock);
+        }
+
+        if self.is_enable_random_mutate {
+            c.randomize_mutate_threshold_copy_fail_by(
+                self.config.copy_fail_interval,
+                self.mutation_rate,
+            );
+        }
+        if let Some(ref e) = self.config.energy {
+            c.energy = e.creature_capacity;
+        }
+        if let Some(ref mut board) = self.message_board {
+            board.open(&c.genome_region);
+        }
+        if self.config.environment.is_some() {
+            c.task_state = TaskState::with_random_inputs();
+        }
+        self.notify(|o, u| o.on_inject(u, &c));
+        self.creatures.push(c);
+
+        Some(genome_region)
     }
 
     fn allocate_genome_soup(&mut self, request_size: usize) -> Option<MemoryRegion> {
-        if request_size <= 20 {
+        if request_size == 0 {
             return None;
         }
 
@@ -102,6 +358,14 @@ impl Universe {
         }
     }
 
+    fn allocate_daughter_genome_soup(&mut self, request_size: usize) -> Option<MemoryRegion> {
+        if request_size < self.config.minimum_daughter_size {
+            return None;
+        }
+
+        self.allocate_genome_soup(request_size)
+    }
+
     fn free_genome_soup(&mut self, r: MemoryRegion) {
         debug_assert!(r.size != 0);
 
@@ -135,7 +399,7 @@ impl Universe {
     }
 
     pub fn compute_genome_soup_free_rate(&self) -> f64 {
-        (self.compute_genome_soup_free_size() as f64 / (UNIVERSE_TOTAL_GENOME_CAPACITY as f64))
+        (self.compute_genome_soup_free_size() as f64 / (self.genome_soup.len() as f64))
     }
 
     pub fn compute_genome_soup_used_rate(&self) -> f64 {
@@ -157,7 +421,7 @@ impl Universe {
                     .clone()
                     .into_iter()
                     .map(|&x| {
-                        use Instruction::*;
+                        use instruction::Instruction::*;
                         match x {
                             Nop0 => Nop1,
                             Nop1 => Nop0,
@@ -217,7 +481,12 @@ impl Universe {
     }
 
     fn execute(&mut self, creature: &mut Creature, ins: Instruction) {
-        use Instruction::*;
+        use instruction::Instruction::*;
+        if !self.is_enabled_instruction(ins) {
+            creature.core.count_up_fails();
+            return;
+        }
+
         let mut cpu = creature.core.clone();
         let (ax, bx, cx, dx) = (cpu.ax, cpu.bx, cpu.cx, cpu.dx);
         match ins {
@@ -266,7 +535,7 @@ impl Universe {
 
                 match self.search_complement_addr(cpu.ip as usize + 1, ins == Jmp || ins == Call) {
                     None => cpu.count_up_fails(),
-                    Some((addr, size)) => cpu.ip = (addr + size - 1) as u16,
+                    Some((addr, size)) => cpu.ip = (addr + size - 1) as Register,
                 }
             }
             Ret => match cpu.pop() {
@@ -298,15 +567,24 @@ impl Universe {
                 };
 
                 if is_write {
+                    self.heat_up(ax);
                     creature.count_copy += 1;
                     let ins = self.genome_soup[bx as usize];
-                    self.genome_soup[ax as usize] = if self.is_enable_random_mutate
+                    if self.is_enable_random_mutate
                         && ((creature.count_copy % creature.mutate_threshold_copy_fail) == 0)
                     {
-                        creature.randomize_mutate_threshold_copy_fail();
-                        ins.mutate_bit_randomly()
+                        creature.randomize_mutate_threshold_copy_fail_by(
+                            self.config.copy_fail_interval,
+                            self.mutation_rate,
+                        );
+                        let mutated = self.mutate_instruction(ins);
+                        self.genome_soup[ax] = mutated;
+                        self.notify(|o, u| {
+                            o.on_mutation(u, ax, ins, mutated, MutationCause::CopyError)
+                        });
                     } else {
-                        ins
+                        self.genome_soup[ax] = ins;
+                        self.notify(|o, u| o.on_write(u, ax, ins));
                     }
                 } else {
                     cpu.count_up_fails();
@@ -318,26 +596,29 @@ impl Universe {
                 let b = self.search_complement_addr_backward(ip);
                 match (f, b) {
                     (None, None) => cpu.count_up_fails(),
-                    (None, Some((addr, size))) => cpu.ax = (addr + size) as u16,
-                    (Some((addr, size)), None) => cpu.ax = (addr + size) as u16,
+                    (None, Some((addr, size))) => cpu.ax = (addr + size) as Register,
+                    (Some((addr, size)), None) => cpu.ax = (addr + size) as Register,
                     (Some((addr_f, size_f)), Some((addr_b, size_b))) => {
                         // Find the nearest one.
                         cpu.ax = if (addr_f - ip) < (ip - addr_b) {
-                            (addr_f + size_f) as u16
+                            (addr_f + size_f) as Register
                         } else {
-                            (addr_b + size_b) as u16
+                            (addr_b + size_b) as Register
                         };
                     }
                 }
             }
             Adrf | Adrb => match self.search_complement_addr(cpu.ip as usize + 1, ins == Adrf) {
                 None => cpu.count_up_fails(),
-                Some((addr, size)) => cpu.ax = (addr + size) as u16,
+                Some((addr, size)) => cpu.ax = (addr + size) as Register,
             },
-            Mal => match self.allocate_genome_soup(cx as usize) {
-                None => cpu.count_up_fails(),
+            Mal => match self.allocate_daughter_genome_soup(cx as usize) {
+                None => {
+                    cpu.count_up_fails();
+                    self.notify(|o, u| o.on_allocation_failure(u, cx as usize));
+                }
                 Some(genome_region) => {
-                    cpu.ax = genome_region.addr as u16;
+                    cpu.ax = genome_region.addr as Register;
                     if let Some(ref mut daughter) = creature.daughter {
                         self.free_genome_soup(daughter.genome_region);
                     }
@@ -345,30 +626,103 @@ impl Universe {
                 }
             },
             Divide => {
-                if creature.daughter.is_some() {
+                let cost = self.config.energy.as_ref().map_or(0, |e| e.divide_cost);
+                if creature.daughter.is_some() && cost <= creature.energy {
                     let daughter = creature.daughter.clone();
                     creature.daughter = None;
 
                     let mut daughter = *daughter.unwrap();
+                    creature.energy -= cost;
+                    daughter.energy = cost;
                     let daughter_genome = self.genome_soup[daughter.genome_region.range()].to_vec();
 
                     {
-                        daughter.geno_type = self
-                            .gene_bank
-                            .register_genome(&daughter_genome, creature.geno_type.as_ref());
+                        daughter.geno_type =
+                            self.register_genome(daughter_genome, creature.geno_type.as_ref());
                         debug_assert_eq!(daughter.geno_type.is_some(), true);
                         self.gene_bank
                             .count_up_alive_genome(daughter.geno_type.as_ref().unwrap());
+                        self.gene_bank
+                            .record_seen(daughter.geno_type.as_ref().unwrap(), self.world_clock);
                     }
 
                     if self.is_enable_random_mutate {
-                        daughter.randomize_mutate_threshold_copy_fail();
+                        daughter.randomize_mutate_threshold_copy_fail_by(
+                            self.config.copy_fail_interval,
+                            self.mutation_rate,
+                        );
                     }
+                    if let Some(ref mut board) = self.message_board {
+                        board.open(&daughter.genome_region);
+                    }
+                    if self.config.environment.is_some() {
+                        daughter.task_state = TaskState::with_random_inputs();
+                    }
+                    self.notify(|o, u| o.on_birth(u, creature, &daughter));
                     self.creatures.push(daughter);
                 } else {
                     cpu.count_up_fails();
                 }
             }
+            Split => {
+                if creature.count_threads() < self.config.maximum_threads {
+                    let mut thread = cpu.clone();
+                    thread.cx = 0;
+                    thread.ip = self.next_ip(creature, cpu.ip);
+                    cpu.cx = 1;
+                    creature.threads.push(thread);
+                } else {
+                    cpu.count_up_fails();
+                }
+            }
+            Join => {
+                if creature.thread_index == 0 {
+                    creature.threads.clear();
+                } else {
+                    creature.is_joined = true;
+                }
+            }
+            Put | Send => {
+                let own = creature.genome_region.addr;
+                let msg = Message {
+                    value: dx,
+                    sender: creature.geno_type.clone(),
+                };
+                let board = self.message_board.as_mut().unwrap();
+                let receivers = if ins == Put {
+                    board
+                        .find_owner(ax as usize)
+                        .filter(|&r| r != own)
+                        .into_iter()
+                        .collect()
+                } else {
+                    board.find_neighbours(own)
+                };
+
+                let count_delivered = receivers
+                    .into_iter()
+                    .filter(|&r| board.deliver(r, msg.clone()))
+                    .count();
+                if count_delivered == 0 {
+                    cpu.count_up_fails();
+                }
+            }
+            Get => {
+                let board = self.message_board.as_mut().unwrap();
+                match board.receive(creature.genome_region.addr) {
+                    None => cpu.count_up_fails(),
+                    Some(msg) => {
+                        cpu.dx = msg.value;
+                        if let (Some(s), Some(r)) = (msg.sender, creature.geno_type.as_ref()) {
+                            self.gene_bank.count_up_communication(&s, r);
+                        }
+                    }
+                }
+            }
+            In => cpu.dx = creature.task_state.read_input(),
+            Out => {
+                creature.task_state.write_output(dx);
+            }
         }
 
         creature.core = cpu;
@@ -378,78 +732,442 @@ impl Universe {
         self.genome_soup[creature.core.ip as usize]
     }
 
-    fn increment_ip(&self, creature: &mut Creature) {
-        let cpu = &mut creature.core;
-        cpu.ip = if (self.genome_soup.len() - 1) <= (cpu.ip as usize) {
-            creature.genome_region.addr as u16
+    fn next_ip(&self, creature: &Creature, ip: Register) -> Register {
+        if (self.genome_soup.len() - 1) <= (ip as usize) {
+            creature.genome_region.addr as Register
         } else {
-            cpu.ip + 1
-        };
+            ip + 1
+        }
+    }
+
+    fn increment_ip(&self, creature: &mut Creature) {
+        creature.core.ip = self.next_ip(creature, creature.core.ip);
     }
 
     fn one_instruction_cycle(&mut self, creature: &mut Creature) {
         let ins = self.fetch(creature);
-        self.execute(creature, ins);
+        if self.profiler.is_enabled() {
+            let count_fails = creature.core.count_fails;
+            let is_copying = creature.daughter.is_some();
+            self.execute(creature, ins);
+
+            if let Some(ref geno_type) = creature.geno_type {
+                let is_failed = count_fails < creature.core.count_fails;
+                self.profiler.record(geno_type, ins, is_copying, is_failed);
+            }
+        } else {
+            self.execute(creature, ins);
+        }
         self.increment_ip(creature);
 
         // println!("Fetch: {:?}", ins);
         // println!("Execute: {}", creature.core);
     }
 
-    fn execute_creature(&mut self, creature: &mut Creature, insts_count: usize) {
-        for _ in 0..insts_count {
+    // The creature cannot execute the next instruction if she doesn't have enough energy.
+    fn consume_energy(&self, creature: &mut Creature) -> bool {
+        match self.config.energy {
+            None => true,
+            Some(ref e) => {
+                let cost = e.instruction_cost(self.fetch(creature));
+                if creature.energy < cost {
+                    false
+                } else {
+                    creature.energy -= cost;
+                    true
+                }
+            }
+        }
+    }
+
+    fn replenish_energy(&mut self) {
+        if let Some(ref e) = self.config.energy {
+            self.energy_pool = e.pool_capacity.min(self.energy_pool + e.pool_inflow);
+
+            for c in self.creatures.iter_mut() {
+                let amount = e
+                    .replenish_amount
+                    .min(e.creature_capacity.saturating_sub(c.energy))
+                    .min(self.energy_pool);
+                c.energy += amount;
+                self.energy_pool -= amount;
+            }
+        }
+    }
+
+    // Execute the thread of the creature which has the turn, and pass the turn to the next one.
+    fn one_thread_cycle(&mut self, creature: &mut Creature) -> bool {
+        let index = creature.thread_index;
+
+        creature.switch_thread(index);
+        let is_executable = self.consume_energy(creature);
+        if is_executable {
             self.one_instruction_cycle(creature);
+        }
+        creature.switch_thread(index);
+
+        if creature.is_joined {
+            creature.is_joined = false;
+            creature.threads.remove(index - 1);
+            creature.thread_index = index % creature.count_threads();
+        } else {
+            creature.thread_index = (index + 1) % creature.count_threads();
+        }
+
+        is_executable
+    }
+
+    // It returns the number of the executed instructions, it is less than insts_count if she starves.
+    fn execute_creature(&mut self, creature: &mut Creature, insts_count: usize) -> usize {
+        for i in 0..insts_count {
+            if !self.one_thread_cycle(creature) {
+                return i;
+            }
 
             self.world_clock += 1;
+            self.notify_tick();
 
             if self.is_enable_random_mutate
                 && ((self.world_clock % self.mutate_threshold_cosmic_rays) == 0)
             {
                 self.randomize_mutate_threshold_cosmic_rays();
 
-                let target_index = rand::thread_rng().gen_range(0, self.genome_soup.len());
-                let p = &mut self.genome_soup[target_index];
-                *p = p.mutate_bit_randomly();
+                let target_index = random::gen_range(0, self.genome_soup.len());
+                let before = self.genome_soup[target_index];
+                let after = self.mutate_instruction(before);
+                self.genome_soup[target_index] = after;
+                self.heat_up(target_index);
+                self.notify(|o, u| {
+                    o.on_mutation(u, target_index, before, after, MutationCause::CosmicRay)
+                });
             }
         }
+        insts_count
     }
 
-    #[test]
+    #[cfg(test)]
     fn execute_creature_by_index(&mut self, index: usize, insts_count: usize) {
         let mut c = self.creatures[index].clone();
         self.execute_creature(&mut c, insts_count);
         self.creatures[index] = c;
     }
 
-    pub fn execute_all_creatures(&mut self, power: f64) {
+    fn time_slice(&self, creature: &Creature, power: f64) -> usize {
+        let size = creature.genome_region.size as f64;
+        let merit = self
+            .config
+            .environment
+            .as_ref()
+            .map_or(1.0, |e| e.merit(&creature.task_state));
+        (size.powf(power) * merit).floor() as usize
+    }
+
+    fn begin_round(&mut self, power: f64) {
         self.creatures.sort();
+        self.replenish_energy();
+        self.round = Some(Round {
+            power,
+            count_creatures: self.creatures.len(),
+            index: 0,
+            time_slice: None,
+            is_alive: !self.creatures.is_empty(),
+            count_executed: 0,
+        });
+    }
 
-        let mut cs = mem::replace(&mut self.creatures, Vec::new());
-        for c in cs.iter_mut() {
-            let size = c.genome_region.size as f64;
-            let time_slice = size.powf(power).floor() as usize;
-            self.execute_creature(c, time_slice);
+    // Execute the creatures of the round in progress up to the given number of instructions.
+    // It returns the number of the executed instructions, the round is finished if it is less.
+    fn continue_round(&mut self, max_count: usize) -> usize {
+        let mut round = match self.round {
+            None => return 0,
+            Some(r) => r,
+        };
+
+        // The creatures born in this call are appended to the empty list.
+        let mut cs = mem::take(&mut self.creatures);
+        let mut count = 0;
+        while round.index < round.count_creatures {
+            let c = &mut cs[round.index];
+            let time_slice = round
+                .time_slice
+                .unwrap_or_else(|| self.time_slice(c, round.power));
+            if 0 < time_slice && count == max_count {
+                round.time_slice = Some(time_slice);
+                break;
+            }
+
+            let n = time_slice.min(max_count - count);
+            let executed = self.execute_creature(c, n);
+            count += executed;
+            if executed == n && n < time_slice {
+                round.time_slice = Some(time_slice - n);
+            } else {
+                // The time slice is used up or she starves.
+                round.index += 1;
+                round.time_slice = None;
+            }
         }
         cs.append(&mut self.creatures);
         self.creatures = cs;
+
+        round.count_executed += count;
+        self.round = Some(round);
+        count
+    }
+
+    fn is_round_finished(&self) -> bool {
+        self.round.is_some_and(|r| r.index == r.count_creatures)
+    }
+
+    fn end_round(&mut self) -> Round {
+        let round = self.round.take().unwrap();
+        self.wakeup_reaper_if_genome_usage_over(self.config.reaper_threshold);
+        debug_assert_eq!(self.check_invariants(), Ok(()));
+
+        if round.is_alive && self.creatures.is_empty() {
+            self.notify(|o, u| o.on_extinction(u));
+            self.reseed();
+        }
+        self.detect_stagnation();
+        round
+    }
+
+    // Execute all creatures once without the reaper.
+    // The round suspended by `step` is abandoned.
+    pub fn execute_all_creatures(&mut self, power: f64) {
+        self.begin_round(power);
+        self.continue_round(usize::MAX);
+        self.round = None;
+    }
+
+    fn begin_round_with_snapshot(&mut self) {
+        let is_alive = !self.creatures.is_empty();
+        if is_alive && self.config.extinction_policy == ExtinctionPolicy::ReseedFromSnapshot {
+            self.take_snapshot();
+        }
+        let power = self.config.slice_power;
+        self.begin_round(power);
+    }
+
+    // Execute all creatures once and wake up the reaper with the configured parameters.
+    // The round suspended by `step` is finished instead of starting a new one.
+    // It returns the number of the instructions executed by this call.
+    pub fn run_round(&mut self) -> usize {
+        if self.round.is_none() {
+            self.begin_round_with_snapshot();
+        }
+        let count = self.continue_round(usize::MAX);
+        self.end_round();
+        count
+    }
+
+    fn take_snapshot(&mut self) {
+        let mut geno_types = HashSet::new();
+        self.snapshot = self
+            .creatures
+            .iter()
+            .filter(|c| geno_types.insert(c.geno_type.clone()))
+            .map(|c| self.genome_of(c).to_vec())
+            .collect();
+    }
+
+    fn reseed(&mut self) {
+        let genomes = match self.config.extinction_policy {
+            ExtinctionPolicy::Stop => return,
+            ExtinctionPolicy::Reseed(ref genome) => vec![genome.clone()],
+            ExtinctionPolicy::ReseedFromSnapshot => self.snapshot.clone(),
+        };
+
+        for genome in genomes.iter() {
+            if self.inject_genome(genome).is_none() {
+                break;
+            }
+        }
+    }
+
+    fn detect_stagnation(&mut self) {
+        let (ticks, boost) = match self.config.stagnation {
+            None => return,
+            Some(ref s) => (s.ticks, s.mutation_boost),
+        };
+
+        if !self.is_stagnant && ticks <= self.world_clock - self.last_new_genotype_clock {
+            self.is_stagnant = true;
+            self.mutation_rate = boost;
+            self.randomize_mutate_thresholds();
+            self.notify(|o, u| o.on_stagnation(u));
+        }
+    }
+
+    pub fn is_stagnant(&self) -> bool {
+        self.is_stagnant
+    }
+
+    pub fn mutation_rate(&self) -> f64 {
+        self.mutation_rate
+    }
+
+    // Execute exactly n instructions unless all creatures die or starve.
+    // The round is suspended after the n-th instruction and the next call resumes it.
+    pub fn step(&mut self, n: usize) -> usize {
+        let mut count = 0;
+        while count < n {
+            if self.round.is_none() {
+                if self.creatures.is_empty() {
+                    break;
+                }
+                self.begin_round_with_snapshot();
+            }
+
+            count += self.continue_round(n - count);
+            if self.is_round_finished() && self.end_round().count_executed == 0 {
+                // All creatures are starving.
+                break;
+            }
+        }
+        count
     }
 
     pub fn wakeup_reaper_if_genome_usage_over(&mut self, threshold: f64) {
         while threshold < self.compute_genome_soup_used_rate() {
             match self.creatures.pop() {
-                None => panic!("!?"),
+                // The rest of the soup is lost, check_invariants reports it.
+                None => break,
                 Some(target) => {
                     self.gene_bank
                         .count_up_dead_genome(target.geno_type.as_ref().unwrap());
+                    self.gene_bank
+                        .record_seen(target.geno_type.as_ref().unwrap(), self.world_clock);
+                    self.notify(|o, u| o.on_death(u, &target));
 
                     if let Some(daughter) = target.daughter {
                         self.free_genome_soup(daughter.genome_region);
                     }
 
                     self.free_genome_soup(target.genome_region);
+
+                    if let Some(ref mut board) = self.message_board {
+                        board.close(&target.genome_region);
+                    }
+
+                    // The energy of the dead goes back to the pool.
+                    if let Some(ref e) = self.config.energy {
+                        self.energy_pool = e.pool_capacity.min(self.energy_pool + target.energy);
+                    }
+                }
+            }
+        }
+    }
+
+    // Check the consistency of the soup and the creatures.
+    pub fn check_invariants(&self) -> Result<(), String> {
+        let soup_size = self.genome_soup.len();
+
+        let mut regions = self
+            .free_regions
+            .iter()
+            .map(|&r| (r, "free"))
+            .collect::<Vec<_>>();
+        for c in self.creatures.iter() {
+            regions.push((c.genome_region, "creature"));
+            if let Some(ref d) = c.daughter {
+                regions.push((d.genome_region, "daughter"));
+            }
+
+            for (i, cpu) in Some(&c.core)
+                .into_iter()
+                .chain(c.threads.iter())
+                .enumerate()
+            {
+                if cpu.stack.len() < cpu.sp as usize {
+                    return Err(format!(
+                        "sp of {:?} thread {} is {}",
+                        c.genome_region, i, cpu.sp
+                    ));
+                }
+                if soup_size <= cpu.ip as usize {
+                    return Err(format!(
+                        "ip of {:?} thread {} is {}",
+                        c.genome_region, i, cpu.ip
+                    ));
                 }
             }
         }
+        regions.sort();
+
+        let mut end_addr = 0;
+        for &(r, kind) in regions.iter() {
+            if r.size == 0 || r.addr < end_addr || soup_size < r.end_addr() {
+                return Err(format!(
+                    "{} region {:?} overlaps or is out of the soup",
+                    kind, r
+                ));
+            }
+            end_addr = r.end_addr();
+        }
+
+        let total_size = regions.iter().map(|&(r, _)| r.size).sum::<usize>();
+        if total_size != soup_size {
+            return Err(format!(
+                "{} cells of the soup are lost",
+                soup_size - total_size
+            ));
+        }
+
+        Ok(())
+    }
+
+    pub fn world_clock(&self) -> usize {
+        self.world_clock
+    }
+
+    pub fn genome_soup(&self) -> &[Instruction] {
+        &self.genome_soup
+    }
+
+    pub fn genome_soup_slice(&self, range: Range<usize>) -> &[Instruction] {
+        &self.genome_soup[range]
+    }
+
+    pub fn genome_of(&self, creature: &Creature) -> &[Instruction] {
+        &self.genome_soup[creature.genome_region.range()]
+    }
+
+    pub fn genome_capacity(&self) -> usize {
+        self.genome_soup.len()
+    }
+
+    pub fn write_heat_map(&self) -> Option<&[u32]> {
+        self.write_heat_map.as_deref()
+    }
+
+    pub fn energy_pool(&self) -> usize {
+        self.energy_pool
+    }
+
+    pub fn message_board(&self) -> Option<&MessageBoard> {
+        self.message_board.as_ref()
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn creatures(&self) -> &[Creature] {
+        &self.creatures
+    }
+
+    // The number of the alive creatures which have performed each task.
+    pub fn count_task_performers(&self) -> [usize; TASK_COUNT] {
+        let mut counts = [0; TASK_COUNT];
+        for c in self.creatures.iter() {
+            for &t in TASKS.iter() {
+                if c.task_state.is_performed(t) {
+                    counts[t as usize] += 1;
+                }
+            }
+        }
+        counts
     }
 
     pub fn count_creatures(&self) -> usize {
@@ -459,13 +1177,28 @@ impl Universe {
     pub fn gene_bank(&self) -> &GeneBank {
         &self.gene_bank
     }
+
+    pub fn species(&self) -> Option<&SpeciesMap> {
+        self.species.as_ref()
+    }
+
+    pub fn profiler(&self) -> &Profiler {
+        &self.profiler
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use ancestor::ANCESTOR;
+    use config::StagnationPolicy;
+    use energy::EnergyConfig;
+    use environment::{Environment, Task};
     use instruction::Instruction::*;
     use instruction::*;
+    use species::SpeciesCriterion;
+    use std::cell::RefCell;
+    use std::rc::Rc;
 
     #[test]
     fn test_alloc_free() {
@@ -596,7 +1329,7 @@ mod tests {
         let (mut univ, mut c) = prepare_test_creature(&insts);
 
         univ.execute_creature_by_index(0, 4);
-        c.core.ip = c.genome_region.size as u16;
+        c.core.ip = c.genome_region.size as Register;
         assert_eq!(univ.creatures[0].core, c.core);
     }
 
@@ -643,7 +1376,7 @@ mod tests {
         assert_eq!(univ.creatures[0].core, c.core);
 
         univ.execute_creature_by_index(0, 2);
-        c.core.ip = c.genome_region.addr as u16 + 1;
+        c.core.ip = c.genome_region.addr as Register + 1;
         c.core.cx = 0;
         assert_eq!(univ.creatures[0].core, c.core);
     }
@@ -663,7 +1396,7 @@ mod tests {
         assert_eq!(univ.creatures[0].core, c.core);
 
         univ.execute_creature_by_index(0, 1);
-        c.core.ip = c.genome_region.addr as u16 + 1;
+        c.core.ip = c.genome_region.addr as Register + 1;
         c.core.cx = 1;
         assert_eq!(univ.creatures[0].core, c.core);
 
@@ -686,7 +1419,7 @@ mod tests {
         assert_eq!(univ.creatures[0].core, c.core);
 
         univ.execute_creature_by_index(0, 2);
-        c.core.ip = c.genome_region.addr as u16 + 1;
+        c.core.ip = c.genome_region.addr as Register + 1;
         c.core.ax = 1;
         assert_eq!(univ.creatures[0].core, c.core);
     }
@@ -700,7 +1433,7 @@ mod tests {
         c.core.ax = 2;
         c.core.bx = 2;
         c.core.cx = 2;
-        c.core.ip = c.genome_region.size as u16;
+        c.core.ip = c.genome_region.size as Register;
         assert_eq!(univ.creatures[0].core, c.core);
     }
 
@@ -718,7 +1451,7 @@ mod tests {
         c.core.stack[2] = 1;
         c.core.stack[3] = 0;
         c.core.sp = 4;
-        c.core.ip = c.genome_region.size as u16;
+        c.core.ip = c.genome_region.size as Register;
         assert_eq!(univ.creatures[0].core, c.core);
     }
 
@@ -739,7 +1472,7 @@ mod tests {
         c.core.bx = 1;
         c.core.cx = 1;
         c.core.dx = 1;
-        c.core.ip = c.genome_region.size as u16;
+        c.core.ip = c.genome_region.size as Register;
         assert_eq!(univ.creatures[0].core, c.core);
     }
 
@@ -760,7 +1493,7 @@ mod tests {
         assert_eq!(univ.creatures[0].core, c.core);
 
         univ.execute_creature_by_index(0, 1);
-        c.core.ip = c.genome_region.addr as u16 + 3;
+        c.core.ip = c.genome_region.addr as Register + 3;
         assert_eq!(univ.creatures[0].core, c.core);
     }
 
@@ -779,7 +1512,7 @@ mod tests {
 
         univ.execute_creature_by_index(0, 1);
         c.core.sp -= 1;
-        c.core.ip = c.genome_region.addr as u16 + 2;
+        c.core.ip = c.genome_region.addr as Register + 2;
         assert_eq!(univ.creatures[0].core, c.core);
     }
 
@@ -821,12 +1554,12 @@ mod tests {
 
         univ.execute_creature_by_index(0, 3);
         c.core.ip += 3;
-        c.core.ax = c.genome_region.addr as u16 + 2;
+        c.core.ax = c.genome_region.addr as Register + 2;
         assert_eq!(univ.creatures[0].core, c.core);
 
         univ.execute_creature_by_index(0, 3);
         c.core.ip += 3;
-        c.core.ax = c.genome_region.addr as u16 + 13;
+        c.core.ax = c.genome_region.addr as Register + 13;
         assert_eq!(univ.creatures[0].core, c.core);
     }
 
@@ -834,6 +1567,7 @@ mod tests {
     fn test_instruction_mal_divide() {
         let insts = [IncC, IncC, IncC, Mal, Divide];
         let (mut univ, mut c) = prepare_test_creature(&insts);
+        univ.config.minimum_daughter_size = 0;
 
         univ.execute_creature_by_index(0, 4);
         c.core.ip += 4;
@@ -843,7 +1577,7 @@ mod tests {
             .as_ref()
             .unwrap()
             .genome_region
-            .addr as u16;
+            .addr as Register;
         assert_eq!(univ.creatures[0].core, c.core);
 
         univ.execute_creature_by_index(0, 1);
@@ -852,4 +1586,385 @@ mod tests {
         assert_eq!(univ.creatures[0].daughter.is_none(), true);
         assert_eq!(univ.creatures[1].genome_region.addr, c.core.ax as usize);
     }
+
+    #[test]
+    fn test_embedding_api() {
+        let mut univ = Universe::with_config(Config {
+            genome_capacity: 4096,
+            ..Default::default()
+        });
+        assert_eq!(univ.genome_capacity(), 4096);
+
+        let r = univ.inject_genome(&ANCESTOR).unwrap();
+        assert_eq!(univ.genome_soup_slice(r.range()), &ANCESTOR[..]);
+        assert_eq!(univ.genome_of(&univ.creatures()[0]), &ANCESTOR[..]);
+
+        assert_eq!(univ.step(10000), 10000);
+        assert!(1 < univ.creatures().len());
+
+        let bank = univ.gene_bank();
+        let record = bank.find_record_by_genome(&ANCESTOR).unwrap();
+        assert_eq!(record.geno_type(), "80-0");
+        assert_eq!(bank.count_alive("80-0"), univ.count_creatures());
+        assert_eq!(bank.alive_geno_types(), vec!["80-0".to_string()]);
+
+        assert!(univ.inject_genome(&[Nop0; 4096]).is_none());
+    }
+
+    #[test]
+    fn test_step_exact() {
+        let run = |steps: &[usize]| {
+            let mut univ = Universe::with_config(Config {
+                seed: Some(7),
+                ..Default::default()
+            });
+            univ.generate_creature(&ANCESTOR);
+            for &n in steps {
+                assert_eq!(univ.step(n), n);
+            }
+            univ
+        };
+
+        // The suspended round is resumed as if it were not split.
+        let a = run(&[1, 79, 333, 5000, 4587]);
+        let b = run(&[10000]);
+        assert_eq!(a.world_clock(), 10000);
+        assert_eq!(b.world_clock(), 10000);
+        assert_eq!(a.genome_soup(), b.genome_soup());
+        assert_eq!(a.count_creatures(), b.count_creatures());
+    }
+
+    #[test]
+    fn test_instruction_split_join() {
+        let insts = [Split, IncA, IncA, Join, IncB, Split, IfCz, Nop0, Join];
+        let (mut univ, mut c) = prepare_test_creature(&insts);
+
+        // Split is disabled by default.
+        univ.execute_creature_by_index(0, 1);
+        assert_eq!(univ.creatures[0].core.count_fails, 1);
+        assert_eq!(univ.creatures[0].count_threads(), 1);
+
+        univ.config.maximum_threads = 2;
+        univ.instruction_set = Universe::compute_instruction_set(&univ.config);
+        univ.creatures[0] = c.clone();
+
+        univ.execute_creature_by_index(0, 1);
+        c.core.ip += 1;
+        c.core.cx = 1;
+        assert_eq!(univ.creatures[0].core, c.core);
+        assert_eq!(univ.creatures[0].threads[0].ip, c.core.ip);
+        assert_eq!(univ.creatures[0].threads[0].cx, 0);
+
+        // The threads take turns.
+        univ.execute_creature_by_index(0, 4);
+        c.core.ip += 2;
+        c.core.ax = 2;
+        assert_eq!(univ.creatures[0].core, c.core);
+        assert_eq!(univ.creatures[0].threads[0].ax, 2);
+
+        // The forked thread joins first.
+        univ.execute_creature_by_index(0, 1);
+        assert_eq!(univ.creatures[0].count_threads(), 1);
+        assert_eq!(univ.creatures[0].core, c.core);
+
+        univ.execute_creature_by_index(0, 2);
+        c.core.ip += 2;
+        c.core.bx = 1;
+        assert_eq!(univ.creatures[0].core, c.core);
+
+        // Only the core skips Nop0 and reaches Join first.
+        univ.execute_creature_by_index(0, 4);
+        assert_eq!(univ.creatures[0].count_threads(), 2);
+        assert_eq!(univ.creatures[0].threads[0].ip, c.core.ip + 3);
+
+        // Join by the core terminates all the others.
+        univ.execute_creature_by_index(0, 1);
+        assert_eq!(univ.creatures[0].count_threads(), 1);
+        assert_eq!(
+            univ.creatures[0].core.ip as usize,
+            c.genome_region.end_addr()
+        );
+    }
+
+    #[test]
+    fn test_instruction_put_get_send() {
+        let mut univ = Universe::with_config(Config {
+            is_enable_communication: true,
+            ..Default::default()
+        });
+        let mut sender = vec![Put, Send, Put];
+        sender.resize(22, Nop0);
+        let mut receiver = vec![Get, Get, Get];
+        receiver.resize(23, Nop0);
+        univ.generate_creature(&sender);
+        univ.generate_creature(&receiver);
+
+        univ.creatures[0].core.ax = 22;
+        univ.creatures[0].core.dx = 7;
+        univ.execute_creature_by_index(0, 2);
+        assert_eq!(univ.creatures[0].core.count_fails, 0);
+
+        // A creature cannot put a message to herself.
+        univ.creatures[0].core.ax = 0;
+        univ.execute_creature_by_index(0, 1);
+        assert_eq!(univ.creatures[0].core.count_fails, 1);
+
+        univ.execute_creature_by_index(1, 2);
+        assert_eq!(univ.creatures[1].core.dx, 7);
+        assert_eq!(univ.creatures[1].core.count_fails, 0);
+        univ.execute_creature_by_index(1, 1);
+        assert_eq!(univ.creatures[1].core.count_fails, 1);
+
+        let bank = univ.gene_bank();
+        assert_eq!(bank.count_communication("22-0", "23-0"), 2);
+        assert_eq!(bank.count_communication("23-0", "22-0"), 0);
+        assert!(bank.is_communicating("22-0"));
+    }
+
+    #[test]
+    fn test_creature_over_u16_address() {
+        let mut univ = Universe::new();
+        univ.generate_creature(&vec![Nop0; 0x10000]);
+        let r = univ.inject_genome(&[IfCz, IncA, Jmpb, Nop1]).unwrap();
+        assert_eq!(univ.creatures[1].core.ip as usize, r.addr);
+
+        univ.execute_creature_by_index(1, 10);
+        // Jmpb goes back to the Nop0 just before the creature.
+        assert_eq!(univ.creatures[1].core.ip as usize, r.addr + 1);
+        assert_eq!(univ.creatures[1].core.ax, 3);
+        assert_eq!(univ.check_invariants(), Ok(()));
+    }
+
+    #[test]
+    fn test_check_invariants() {
+        let mut univ = Universe::new();
+        univ.generate_creature(&ANCESTOR);
+        univ.generate_creature(&ANCESTOR);
+        assert_eq!(univ.check_invariants(), Ok(()));
+
+        univ.creatures[1].core.ip = univ.genome_capacity() as Register;
+        assert!(univ.check_invariants().is_err());
+        univ.creatures[1].core.ip = 0;
+
+        univ.creatures[1].daughter = Some(Box::new(Creature::new(MemoryRegion::new(70, 10))));
+        assert!(univ.check_invariants().is_err());
+    }
+
+    // The creature allocates the large daughter and the reaper kills her in the first round.
+    fn prepare_extinction(policy: ExtinctionPolicy) -> Universe {
+        let mut univ = Universe::with_config(Config {
+            genome_capacity: 100,
+            extinction_policy: policy,
+            ..Default::default()
+        });
+        let mut genome = vec![Mal];
+        genome.resize(30, Nop0);
+        univ.generate_creature(&genome);
+        univ.creatures[0].core.cx = 60;
+        univ
+    }
+
+    #[test]
+    fn test_extinction() {
+        let mut univ = prepare_extinction(ExtinctionPolicy::Stop);
+        assert!(0 < univ.run_round());
+        assert_eq!(univ.count_creatures(), 0);
+        assert_eq!(univ.run_round(), 0);
+        assert_eq!(univ.step(100), 0);
+
+        let mut univ = prepare_extinction(ExtinctionPolicy::Reseed(ANCESTOR.to_vec()));
+        univ.run_round();
+        assert_eq!(univ.count_creatures(), 1);
+        assert_eq!(univ.genome_of(&univ.creatures[0]), &ANCESTOR[..]);
+
+        let mut univ = prepare_extinction(ExtinctionPolicy::ReseedFromSnapshot);
+        univ.run_round();
+        assert_eq!(univ.count_creatures(), 1);
+        assert_eq!(univ.gene_bank().count_dead("30-0"), 1);
+        assert_eq!(univ.gene_bank().count_alive("30-0"), 1);
+        assert_eq!(
+            univ.gene_bank().seen_clocks("30-0"),
+            Some((0, univ.world_clock()))
+        );
+        assert_eq!(univ.check_invariants(), Ok(()));
+    }
+
+    #[test]
+    fn test_stagnation() {
+        let mut univ = Universe::with_config(Config {
+            stagnation: Some(StagnationPolicy {
+                ticks: 100,
+                mutation_boost: 4.0,
+            }),
+            ..Default::default()
+        });
+        univ.generate_creature(&[Nop0; 30]);
+
+        univ.run_round();
+        assert!(!univ.is_stagnant());
+        univ.step(100);
+        assert!(univ.is_stagnant());
+        assert_eq!(univ.mutation_rate(), 4.0);
+
+        // A new genotype ends the stagnation.
+        univ.generate_creature(&[Nop1; 31]);
+        assert!(!univ.is_stagnant());
+        assert_eq!(univ.mutation_rate(), 1.0);
+    }
+
+    #[test]
+    fn test_species_by_trace() {
+        let mut univ = Universe::with_config(Config {
+            species: Some(SpeciesCriterion::Trace(10)),
+            ..Default::default()
+        });
+        // Jmp skips the third instruction.
+        let mut a = vec![Jmp, Nop0, Divide, Nop1];
+        a.resize(22, IncA);
+        let mut b = a.clone();
+        b[2] = Mal;
+        b.push(IncA);
+        let mut c = a.clone();
+        c[4] = IncB;
+        c.resize(24, IncA);
+        univ.generate_creature(&a);
+        univ.generate_creature(&b);
+        univ.generate_creature(&c);
+
+        assert_eq!(univ.trace_genome(&a, 3), vec![Jmp, IncA, IncA]);
+        let species = univ.species().unwrap();
+        assert_eq!(species.species().len(), 2);
+        assert_eq!(species.species_of("23-0").unwrap().founder, "22-0");
+        assert_eq!(species.species_of("24-0").unwrap().id, 1);
+    }
+
+    #[test]
+    fn test_instruction_in_out() {
+        let mut univ = Universe::with_config(Config {
+            environment: Some(Environment::logic_tasks()),
+            ..Default::default()
+        });
+        let mut genome = vec![In, In, Out];
+        genome.resize(22, Nop0);
+        univ.generate_creature(&genome);
+        univ.creatures[0].task_state.inputs = [0b1100, 0b1010, 0b0001];
+
+        univ.execute_creature_by_index(0, 2);
+        assert_eq!(univ.creatures[0].core.dx, 0b1010);
+        univ.creatures[0].core.dx = 0b1000;
+        univ.execute_creature_by_index(0, 1);
+        assert!(univ.creatures[0].task_state.is_performed(Task::And));
+        assert_eq!(univ.count_task_performers(), [0, 0, 1, 0, 0]);
+
+        // The time slice is multiplied by the bonus of And.
+        let clock = univ.world_clock();
+        univ.execute_all_creatures(1.0);
+        assert_eq!(univ.world_clock() - clock, 22 * 4);
+    }
+
+    fn prepare_energy_test_creature(insts: &[Instruction]) -> Universe {
+        let mut univ = Universe::with_config(Config {
+            minimum_daughter_size: 0,
+            energy: Some(EnergyConfig {
+                pool_capacity: 0,
+                pool_inflow: 0,
+                creature_capacity: 5,
+                replenish_amount: 0,
+                divide_cost: 10,
+                instruction_costs: [1; INSTRUCTION_COUNT],
+            }),
+            ..Default::default()
+        });
+        univ.generate_creature(insts);
+        univ
+    }
+
+    #[test]
+    fn test_energy_starvation() {
+        let insts = [IncA, IncA, IncA, IncA, IncA, IncA, IncA, IncA];
+        let mut univ = prepare_energy_test_creature(&insts);
+        assert_eq!(univ.creatures[0].energy, 5);
+
+        univ.execute_creature_by_index(0, insts.len());
+        assert_eq!(univ.creatures[0].core.ax, 5);
+        assert_eq!(univ.creatures[0].energy, 0);
+
+        univ.execute_all_creatures(1.0);
+        assert_eq!(univ.creatures[0].core.ax, 5);
+        assert_eq!(univ.step(100), 0);
+    }
+
+    #[test]
+    fn test_energy_divide_cost() {
+        let insts = [IncC, Mal, Divide];
+        let mut univ = prepare_energy_test_creature(&insts);
+
+        univ.execute_creature_by_index(0, insts.len());
+        assert_eq!(univ.creatures[0].core.count_fails, 1);
+        assert!(univ.creatures[0].daughter.is_some());
+        assert_eq!(univ.count_creatures(), 1);
+
+        univ.creatures[0].energy = 11;
+        univ.creatures[0].core.ip -= 1;
+        univ.execute_creature_by_index(0, 1);
+        assert_eq!(univ.count_creatures(), 2);
+        assert_eq!(univ.creatures[0].energy, 0);
+        assert_eq!(univ.creatures[1].energy, 10);
+    }
+
+    #[derive(Default)]
+    struct EventCounts {
+        births: usize,
+        new_genotypes: Vec<String>,
+        allocation_failures: Vec<usize>,
+        ticks: Vec<usize>,
+    }
+
+    struct CountingObserver(Rc<RefCell<EventCounts>>);
+
+    impl UniverseObserver for CountingObserver {
+        fn tick_interval(&self) -> Option<usize> {
+            Some(5)
+        }
+
+        fn on_birth(&mut self, _univ: &Universe, _mother: &Creature, _daughter: &Creature) {
+            self.0.borrow_mut().births += 1;
+        }
+
+        fn on_new_genotype(&mut self, _univ: &Universe, geno_type: &str, _mother: Option<&str>) {
+            self.0
+                .borrow_mut()
+                .new_genotypes
+                .push(geno_type.to_string());
+        }
+
+        fn on_allocation_failure(&mut self, _univ: &Universe, request_size: usize) {
+            self.0.borrow_mut().allocation_failures.push(request_size);
+        }
+
+        fn on_tick(&mut self, univ: &Universe) {
+            self.0.borrow_mut().ticks.push(univ.world_clock());
+        }
+    }
+
+    #[test]
+    fn test_observer() {
+        let insts = [
+            Zero, Or1, Shl, Shl, Shl, Shl, Shl, Mal, Divide, Zero, Or1, Mal, Nop0, Nop0, Nop0,
+            Nop0, Nop0, Nop0, Nop0, Nop0, Nop0, Nop0,
+        ];
+        let counts = Rc::new(RefCell::new(EventCounts::default()));
+        let (mut univ, _) = prepare_test_creature(&insts);
+        univ.add_observer(Box::new(CountingObserver(counts.clone())));
+
+        univ.execute_creature_by_index(0, 9);
+        assert_eq!(counts.borrow().births, 1);
+        assert_eq!(counts.borrow().new_genotypes, vec!["32-1".to_string()]);
+        assert_eq!(counts.borrow().ticks, vec![5]);
+
+        univ.execute_creature_by_index(0, 3);
+        assert_eq!(counts.borrow().births, 1);
+        assert_eq!(counts.borrow().allocation_failures, vec![1]);
+        assert_eq!(counts.borrow().ticks, vec![5, 10]);
+    }
 }